@@ -7,6 +7,7 @@ use std::path::PathBuf;
 use crate::ResultApp;
 use crate::errors::ApplicationErrors;
 use crate::mappings::AcceptedType;
+use crate::upload::{GraphMode, GraphStore};
 use crate::{error, warning, info, time_info};
 
 use std::io::Read;
@@ -20,14 +21,43 @@ use serde_json;
 pub enum OutputFormat{
     NTriplesMap,
     Turtle,
+    JsonLd,
+    RdfXml,
+    Hdt,
+    // Changes from a previous output
+    RdfPatch,
+    SparqlUpdate,
+    AddedTriples,
+    RemovedTriples,
+    // SQLite store that can be queried after the run
+    Store,
     Other
 }
 impl OutputFormat{
+    // Format given by the extension of a file, if it is one of the known ones.
+    pub fn from_path(path: &std::path::Path) -> Option<Self>{
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match Self::from_str(&ext){
+            Self::Other => None,
+            format => Some(format)
+        }
+    }
+
     pub fn from_str(ext: &str) -> Self{
         if ext == "nt"{
             Self::NTriplesMap
         }else if ext == "ttl"{
             Self::Turtle
+        }else if ext == "jsonld"{
+            Self::JsonLd
+        }else if ext == "rdf" || ext == "owl"{
+            Self::RdfXml
+        }else if ext == "hdt"{
+            Self::Hdt
+        }else if ext == "rdfp"{
+            Self::RdfPatch
+        }else if ext == "ru"{
+            Self::SparqlUpdate
         }else{
             Self::Other
         }
@@ -45,9 +75,162 @@ impl OutputFormat{
             _ => false
         }
     }
+
+    pub fn is_jsonld(&self) -> bool{
+        matches!(self, Self::JsonLd)
+    }
+
+    pub fn is_rdfxml(&self) -> bool{
+        matches!(self, Self::RdfXml)
+    }
+
+    pub fn is_hdt(&self) -> bool{
+        matches!(self, Self::Hdt)
+    }
+
+    // The formats of the changes are written by comparing the triples with the previous output.
+    pub fn is_changes(&self) -> bool{
+        matches!(self, Self::RdfPatch | Self::SparqlUpdate | Self::AddedTriples | Self::RemovedTriples)
+    }
+
+    pub fn is_store(&self) -> bool{
+        matches!(self, Self::Store)
+    }
+
+    // Content type of the formats that can be sent over HTTP.
+    pub fn media_type(&self) -> Option<&'static str>{
+        match self{
+            Self::NTriplesMap => Some("application/n-triples"),
+            Self::Turtle => Some("text/turtle"),
+            Self::JsonLd => Some("application/ld+json"),
+            Self::RdfXml => Some("application/rdf+xml"),
+            _ => None
+        }
+    }
+}
+
+// Document form of the JSON-LD output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonLdForm{
+    // Prefixed names with a @context and plain values where possible
+    Compacted,
+    // Full IRIs and value objects, without @context
+    Expanded
+}
+
+impl JsonLdForm{
+    pub fn from_str(form: &str) -> Option<Self>{
+        match form.to_lowercase().as_str(){
+            "compacted" => Some(Self::Compacted),
+            "expanded" => Some(Self::Expanded),
+            _ => None
+        }
+    }
 }
 
+// Compression of a data file or an output, given by the last extension of the file or by the configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression{
+    Gzip,
+    Zstd,
+    Bzip2
+}
 
+impl Compression{
+    pub fn from_str(name: &str) -> Option<Self>{
+        match name.to_lowercase().as_str(){
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            "bz2" | "bzip2" => Some(Self::Bzip2),
+            _ => None
+        }
+    }
+
+    // Compression given by the last extension of the file, as in "data.csv.gz".
+    pub fn from_path(path: &std::path::Path) -> Option<Self>{
+        Self::from_str(path.extension()?.to_str()?)
+    }
+}
+
+// A place where the RDF is written. Without path, it is written to the standard output or uploaded to a Graph Store.
+#[derive(Debug, Clone)]
+pub struct OutputTarget{
+    path: Option<PathBuf>,
+    // Format given by the extension of the file
+    format: Option<OutputFormat>,
+    compression: Option<Compression>,
+    upload: Option<GraphStore>
+}
+
+impl OutputTarget{
+    // "-" is the standard output. The compressed files take the format of the extension before the compression one.
+    pub fn new(output: &str) -> Self{
+        if output == "-"{
+            return Self{path: None, format: None, compression: None, upload: None}
+        }
+        if GraphStore::is_url(output){
+            return Self{path: None, format: None, compression: None, upload: Some(GraphStore::new(output))}
+        }
+        let path = PathBuf::from(output);
+        let compression = Compression::from_path(&path);
+        let format = match compression{
+            Some(_) => OutputFormat::from_path(&path.with_extension("")),
+            None => OutputFormat::from_path(&path)
+        };
+        Self{path: Some(path), format, compression, upload: None}
+    }
+
+    // Output with a format that does not depend on the extension, like the added and removed triples.
+    pub fn with_format(output: &str, format: OutputFormat) -> Self{
+        Self{format: Some(format), ..Self::new(output)}
+    }
+
+    pub fn path(&self) -> Option<&PathBuf>{
+        self.path.as_ref()
+    }
+
+    pub fn get_format(&self) -> &OutputFormat{
+        self.format.as_ref().unwrap_or(&OutputFormat::Other)
+    }
+
+    pub fn get_compression(&self) -> Option<Compression>{
+        self.compression
+    }
+
+    pub fn get_upload(&self) -> Option<&GraphStore>{
+        self.upload.as_ref()
+    }
+
+    pub fn name(&self) -> String{
+        match (&self.path, &self.upload){
+            (Some(path), _) => path.display().to_string(),
+            (None, Some(store)) => store.name(),
+            (None, None) => "standard output".to_string()
+        }
+    }
+}
+
+// What is done with the template values that are not valid inside an IRI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IriEncoding{
+    // Percent-encode the values (R2RML IRI-safe values)
+    Encode,
+    // Discard the IRIs with invalid characters
+    Skip,
+    // Stop the map that generated the IRI
+    Fail
+}
+
+impl IriEncoding{
+    pub fn from_str(mode: &str) -> Option<Self>{
+        match mode.to_lowercase().as_str(){
+            "encode" => Some(Self::Encode),
+            "skip" => Some(Self::Skip),
+            "fail" => Some(Self::Fail),
+            _ => None
+        }
+    }
+}
 
 pub struct AppConfiguration{
     // Reading and writing Custom Information.
@@ -57,8 +240,31 @@ pub struct AppConfiguration{
     // Max Thread Usage: [Parsing, Reading, Creating RDF]
     threads: [usize;3],
     // Output Data
-    output_path: PathBuf,
-    output_format: OutputFormat,
+    outputs: Vec<OutputTarget>,
+    // Format of the outputs whose extension does not give it
+    output_format: Option<OutputFormat>,
+    // Compression of the outputs whose extension does not give it
+    output_compression: Option<Compression>,
+    iri_encoding: IriEncoding,
+    jsonld_form: JsonLdForm,
+    // Write the output file directly, even if some map fails
+    keep_partial: bool,
+    // Remove the duplicated triples of the whole run
+    dedupe: bool,
+    // Write the same output in every run
+    deterministic: bool,
+    // Output of a previous run, used to write the changes
+    previous: Option<PathBuf>,
+    // SHACL shapes that the triples must conform to and file of the validation report
+    shapes: Option<PathBuf>,
+    report: Option<PathBuf>,
+    // File of the VoID and PROV-O metadata of the run and configuration file that was used
+    metadata: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    // Options of the outputs uploaded to a Graph Store
+    upload_graph: Option<String>,
+    upload_mode: GraphMode,
+    upload_headers: Vec<(String, String)>,
     // Debug Display
     debug: bool,
     clear: bool
@@ -96,8 +302,26 @@ impl std::fmt::Debug for AppConfiguration{
 
         writeln!(f, "\nOutput Information: ")?;
         writeln!(f, "------------------------------------------")?;
-        writeln!(f, "Output Path: {}", self.output_path.display())?;
-        writeln!(f, "Output Format: {:?}", self.output_format)?;
+        for output in self.get_outputs(){
+            writeln!(f, "Output: {} Format: {:?} Compression: {:?}", output.name(), output.get_format(), output.get_compression())?;
+        }
+        writeln!(f, "IRI Encoding: {:?}", self.iri_encoding)?;
+        writeln!(f, "JSON-LD Form: {:?}", self.jsonld_form)?;
+        writeln!(f, "Keep Partial Output: {}", self.keep_partial)?;
+        writeln!(f, "Remove Duplicated Triples: {}", self.dedupe)?;
+        writeln!(f, "Deterministic Output: {}", self.deterministic)?;
+        if let Some(previous) = &self.previous{
+            writeln!(f, "Previous Output: {}", previous.display())?;
+        }
+        if let Some(shapes) = &self.shapes{
+            writeln!(f, "SHACL Shapes: {}", shapes.display())?;
+        }
+        if let Some(metadata) = &self.metadata{
+            writeln!(f, "Metadata Output: {}", metadata.display())?;
+        }
+        if self.outputs.iter().any(|output| output.upload.is_some()){
+            writeln!(f, "Graph Store Mode: {:?}", self.upload_mode)?;
+        }
         Ok(())
 
     }
@@ -106,15 +330,28 @@ impl std::fmt::Debug for AppConfiguration{
 
 impl AppConfiguration{
 
-    pub fn new(output_path: PathBuf) -> Self{
-        let output_format = OutputFormat::from_str(output_path.extension().unwrap().to_str().unwrap_or("nt"));
+    pub fn new(outputs: Vec<OutputTarget>) -> Self{
 
         Self{
             file_specs: collections::HashMap::with_capacity(2),
             memory_threshold: 500, // 500
             threads: [3;3],
-            output_path,
-            output_format,
+            outputs,
+            output_format: None,
+            output_compression: None,
+            iri_encoding: IriEncoding::Encode,
+            jsonld_form: JsonLdForm::Compacted,
+            keep_partial: false,
+            dedupe: false,
+            deterministic: false,
+            previous: None,
+            shapes: None,
+            report: None,
+            metadata: None,
+            config_file: None,
+            upload_graph: None,
+            upload_mode: GraphMode::Replace,
+            upload_headers: Vec::new(),
             debug: false,
             clear: false
         }
@@ -137,6 +374,73 @@ impl AppConfiguration{
         self.clear = true;
     }
 
+    pub fn keep_partial_mode(&self) -> bool{
+        self.keep_partial
+    }
+    pub fn set_keep_partial_mode(&mut self){
+        self.keep_partial = true;
+    }
+
+    pub fn dedupe_mode(&self) -> bool{
+        self.dedupe
+    }
+    pub fn set_dedupe_mode(&mut self){
+        self.dedupe = true;
+    }
+
+    pub fn deterministic_mode(&self) -> bool{
+        self.deterministic
+    }
+    pub fn set_deterministic_mode(&mut self){
+        self.deterministic = true;
+    }
+
+    pub fn get_previous_output(&self) -> Option<&PathBuf>{
+        self.previous.as_ref()
+    }
+    pub fn set_previous_output(&mut self, previous: PathBuf){
+        self.previous = Some(previous);
+    }
+
+    pub fn get_shapes(&self) -> Option<&PathBuf>{
+        self.shapes.as_ref()
+    }
+    pub fn set_shapes(&mut self, shapes: PathBuf){
+        self.shapes = Some(shapes);
+    }
+    pub fn get_validation_report(&self) -> Option<&PathBuf>{
+        self.report.as_ref()
+    }
+    pub fn set_validation_report(&mut self, report: PathBuf){
+        self.report = Some(report);
+    }
+    pub fn get_metadata_output(&self) -> Option<&PathBuf>{
+        self.metadata.as_ref()
+    }
+    pub fn set_metadata_output(&mut self, metadata: PathBuf){
+        self.metadata = Some(metadata);
+    }
+    pub fn get_config_file(&self) -> Option<&PathBuf>{
+        self.config_file.as_ref()
+    }
+
+    pub fn get_upload_graph(&self) -> Option<&String>{
+        self.upload_graph.as_ref()
+    }
+    pub fn has_upload_headers(&self) -> bool{
+        !self.upload_headers.is_empty()
+    }
+    pub fn set_upload_graph(&mut self, graph: String){
+        self.upload_graph = Some(graph);
+    }
+    pub fn set_upload_mode(&mut self, mode: GraphMode){
+        self.upload_mode = mode;
+    }
+    // Header of the uploads whose value is taken from an environment variable
+    pub fn add_upload_header(&mut self, name: String, variable: String){
+        self.upload_headers.push((name, variable));
+    }
+
     pub fn update_clear_mode(&mut self, is_file: bool){
         self.clear = self.clear & is_file;
     }
@@ -169,7 +473,7 @@ impl AppConfiguration{
             let file_type2;
             match file_type{
                 AcceptedType::Unspecify => {
-                    file_type2 = AcceptedType::from_str(&path.extension().expect("File with No extension").to_str().unwrap().to_lowercase())
+                    file_type2 = AcceptedType::from_path(&path)
                 }
                 _ => {
                     file_type2 = file_type;
@@ -180,12 +484,38 @@ impl AppConfiguration{
             self.file_specs.insert(path, settings);
         }
     }
-    pub fn get_output_path(&self) -> &PathBuf{
-        &self.output_path
+    // Outputs of the run, where the ones without a known extension use the given output format and compression.
+    pub fn get_outputs(&self) -> Vec<OutputTarget>{
+        self.outputs.iter()
+            .map(|output| match &output.upload{
+                // The uploads are sent without compression and in N-Triples, unless another format is given.
+                Some(store) => OutputTarget{
+                    path: None,
+                    format: output.format.clone().or_else(|| self.output_format.clone()).or(Some(OutputFormat::NTriplesMap)),
+                    compression: None,
+                    upload: Some(store.clone().with_options(self.upload_graph.clone(), self.upload_mode, self.upload_headers.clone()))
+                },
+                None => OutputTarget{
+                    path: output.path.clone(),
+                    format: output.format.clone().or_else(|| self.output_format.clone()),
+                    // The store is a database, it can not be compressed
+                    compression: output.compression.or(self.output_compression).filter(|_| !matches!(output.format, Some(OutputFormat::Store))),
+                    upload: None
+                }
+            })
+            .collect()
+    }
+
+    pub fn set_output_format(&mut self, format: OutputFormat){
+        self.output_format = Some(format);
     }
 
-    pub fn get_output_format(&self) -> &OutputFormat{
-        &self.output_format
+    pub fn get_iri_encoding(&self) -> IriEncoding{
+        self.iri_encoding
+    }
+
+    pub fn get_jsonld_form(&self) -> JsonLdForm{
+        self.jsonld_form
     }
 
     pub fn get_parsing_theads(&self) -> usize{
@@ -204,8 +534,8 @@ impl AppConfiguration{
         self.memory_threshold >= total_memory_usage
     }
 
-    pub fn from_json(output_path: PathBuf, json_data: serde_json::Value) -> ResultApp<Self>{
-        let mut tmp = Self::new(output_path);
+    pub fn from_json(outputs: Vec<OutputTarget>, json_data: serde_json::Value) -> ResultApp<Self>{
+        let mut tmp = Self::new(outputs);
         tmp.file_specs = Self::parse_file_specs(&json_data)?;
         tmp.threads = Self::parse_threads(&json_data)?;
 
@@ -220,7 +550,7 @@ impl AppConfiguration{
         }
         if let Some(output_format) = json_data.get("output-format"){
             tmp.output_format = match output_format.as_str(){
-                Some(format) => OutputFormat::from_str(format),
+                Some(format) => Some(OutputFormat::from_str(format)),
                 None => {
                     error!("The option of \"output format\" must contain a string");
                     return Err(ApplicationErrors::IncorrectJsonFile)
@@ -228,6 +558,34 @@ impl AppConfiguration{
                 }
             }
         }
+        if let Some(output_compression) = json_data.get("output-compression"){
+            tmp.output_compression = match output_compression.as_str().map(|name| (name, Compression::from_str(name))){
+                Some((_, Some(compression))) => Some(compression),
+                Some((name, None)) if name.eq_ignore_ascii_case("none") => None,
+                _ => {
+                    error!("The option of \"output compression\" must be one of the following strings: \"none\", \"gzip\", \"zstd\" or \"bzip2\"");
+                    return Err(ApplicationErrors::IncorrectJsonFile)
+                }
+            }
+        }
+        if let Some(iri_encoding) = json_data.get("iri-encoding"){
+            tmp.iri_encoding = match iri_encoding.as_str().and_then(IriEncoding::from_str){
+                Some(mode) => mode,
+                None => {
+                    error!("The option of \"iri encoding\" must be one of the following strings: \"encode\", \"skip\" or \"fail\"");
+                    return Err(ApplicationErrors::IncorrectJsonFile)
+                }
+            }
+        }
+        if let Some(jsonld_form) = json_data.get("jsonld-form"){
+            tmp.jsonld_form = match jsonld_form.as_str().and_then(JsonLdForm::from_str){
+                Some(form) => form,
+                None => {
+                    error!("The option of \"jsonld form\" must be one of the following strings: \"compacted\" or \"expanded\"");
+                    return Err(ApplicationErrors::IncorrectJsonFile)
+                }
+            }
+        }
         Ok(tmp)
     }
 
@@ -424,16 +782,16 @@ fn get_encoding_from_str(value: &str) -> &'static encoding_rs::Encoding{
    }
 }
 
-pub fn get_configuration(output_file: &PathBuf, config_path: Option<PathBuf>) -> AppConfiguration{
+pub fn get_configuration(outputs: &[OutputTarget], config_path: Option<PathBuf>) -> AppConfiguration{
     let now = Instant::now();
     match config_path{
         Some(path) => {
-            let mut config_file =  match std::fs::File::open(path){
+            let mut config_file =  match std::fs::File::open(&path){
                 Ok(file) => file,
                 Err(error) => {
                     error!("Error Trying Opening Configuration File: {:?}", ApplicationErrors::from(error));
                     warning!("Given the error, it will be used the default configuration to proceed with the parsing");
-                    return AppConfiguration::new(output_file.clone())
+                    return AppConfiguration::new(outputs.to_vec())
                 }
             };
 
@@ -441,7 +799,7 @@ pub fn get_configuration(output_file: &PathBuf, config_path: Option<PathBuf>) ->
             if let Err(error) = config_file.read_to_string(&mut json_tmp){
                 error!("Error Trying Reading Configuration File: {:?}", ApplicationErrors::from(error));
                 warning!("Given the error, it will be used the default configuration to proceed with the parsing");
-                return AppConfiguration::new(output_file.clone())
+                return AppConfiguration::new(outputs.to_vec())
             }
             
             let json_config = match serde_json::from_str(&json_tmp){
@@ -449,25 +807,26 @@ pub fn get_configuration(output_file: &PathBuf, config_path: Option<PathBuf>) ->
                 Err(error) => {
                     error!("Error parsing text to json values: {:?}", error);
                     warning!("Given the error, it will be used the default configuration to proceed with the parsing");
-                    return AppConfiguration::new(output_file.clone())
+                    return AppConfiguration::new(outputs.to_vec())
                 }
             };
 
-            match AppConfiguration::from_json(output_file.clone(), json_config){
-                Ok(config) => {
+            match AppConfiguration::from_json(outputs.to_vec(), json_config){
+                Ok(mut config) => {
                     info!("Given Configuration File was Succesfully Parsed");
                     time_info("Parsing Configuration File", now);
+                    config.config_file = Some(path);
                     config
                 },
                 Err(error) => {
                     error!("Error parsing the Json Values to the configuration: {:?}", error);
                     warning!("Given the error, it will be used the default configuration to proceed with the parsing");
-                    AppConfiguration::new(output_file.clone())
+                    AppConfiguration::new(outputs.to_vec())
                 }
             }
         }
         None => {
-            AppConfiguration::new(output_file.clone())
+            AppConfiguration::new(outputs.to_vec())
         }
     }
 }
@@ -480,7 +839,7 @@ pub fn get_configuration(output_file: &PathBuf, config_path: Option<PathBuf>) ->
 
     let now = Instant::now();
     let json_config = json::parse(&json_tmp)?;
-    let mut configuration = match config::AppConfiguration::from_json(output_file.clone(), json_config){
+    let mut configuration = match config::AppConfiguration::from_json(outputs.to_vec(), json_config){
         Ok(config) => {
             info!("Given Configuration File was Succesfully Parsed");
             time_info("Parsing Configuration File", now);
@@ -489,7 +848,7 @@ pub fn get_configuration(output_file: &PathBuf, config_path: Option<PathBuf>) ->
         Err(error) => {
             error!("ERROR CODE: {:?}", error);
             warning!("Given the error, it will be used the default configuration to proceed with the parsing");
-            AppConfiguration::new(output_file.clone())
+            AppConfiguration::new(outputs.to_vec())
         }
     };
     configuration
@@ -0,0 +1,362 @@
+// Vocabulary and SHACL shapes derived from the mappings, without reading their data files. The classes come from the
+// subject maps and the properties from the predicate object maps, with the domains and ranges that all their maps
+// agree on. Each class gets a node shape with the kinds, datatypes and classes of the objects of its properties, so
+// it can be given to --shapes as a first version of the validation.
+use crate::ResultApp;
+use crate::mappings::{maps::Mapping, parts::Parts};
+use crate::materialiser;
+use crate::rdf::{self, Term};
+use crate::rdf::terms::escape_literal;
+use crate::mappings::shapes::{self, Kind};
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const RDFS: &str = "http://www.w3.org/2000/01/rdf-schema#";
+const OWL: &str = "http://www.w3.org/2002/07/owl#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+const SH: &str = "http://www.w3.org/ns/shacl#";
+
+// What is known of the objects of a predicate object map.
+struct Object{
+    kind: Kind,
+    // Class of the subjects of the parent map of a referencing object map
+    class: Option<String>,
+    // The object of a rr:constant
+    constant: Option<Term>
+}
+
+// A predicate object map of a map.
+struct Usage{
+    map: usize,
+    predicate: String,
+    object: Object
+}
+
+// Class of the subjects of the maps and the map of each predicate object map.
+pub struct Description{
+    map_names: Vec<String>,
+    classes: Vec<Option<String>>,
+    subjects: Vec<Option<Kind>>,
+    usages: Vec<Usage>,
+    prefixes: HashMap<String, String>
+}
+
+// Constraints that all the objects of a property agree on.
+struct Summary{
+    node_kind: Option<&'static str>,
+    datatype: Option<String>,
+    class: Option<String>,
+    values: Option<Vec<Term>>
+}
+
+impl Description{
+    pub fn new(mappings: &[Mapping]) -> Self{
+        let mut mappings = mappings.iter().collect::<Vec<_>>();
+        mappings.sort_by(|a, b| a.get_identifier().cmp(b.get_identifier()));
+        let mut warn = true;
+        // As in a run, only the first class is used
+        let classes = mappings.iter()
+            .map(|map| match map.get_subject(){
+                Parts::SubjectMap{components} => components.iter().find_map(|part| match part{
+                    Parts::Class(class) => Some(materialiser::expand_iri(class, map, &mut warn)),
+                    _ => None
+                }),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+        let subjects = mappings.iter().map(|map| shapes::subject_kind(map, &mut warn)).collect::<Vec<_>>();
+
+        let mut usages = Vec::new();
+        for (i, map) in mappings.iter().enumerate(){
+            for predicate_map in map.get_predicates(){
+                let (predicate, object_map) = match predicate_map{
+                    Parts::PredicateObjectMap{predicate, object_map} => (materialiser::expand_iri(predicate, map, &mut warn), object_map),
+                    _ => continue
+                };
+                let parent = object_map.iter().find_map(|part| match part{
+                    Parts::ParentMap(parent) => mappings.iter().position(|other| other.get_identifier() == parent),
+                    _ => None
+                });
+                let object = match parent{
+                    Some(parent) => subjects[parent].clone().map(|kind| Object{kind, class: classes[parent].clone(), constant: None}),
+                    None => shapes::object_kind(object_map, map, &mut warn).map(|(kind, constant)| Object{kind, class: None, constant})
+                };
+                if let Some(object) = object{
+                    usages.push(Usage{map: i, predicate, object});
+                }
+            }
+        }
+
+        let mut prefixes = HashMap::new();
+        for map in mappings.iter(){
+            for (name, url) in map.get_prefixes().iter(){
+                prefixes.entry(name.clone()).or_insert_with(|| url.clone());
+            }
+        }
+        for (name, url) in [("rdf:", RDF), ("rdfs:", RDFS), ("owl:", OWL), ("xsd:", XSD), ("sh:", SH)]{
+            if !prefixes.values().any(|found| found == url){
+                prefixes.entry(name.to_string()).or_insert_with(|| url.to_string());
+            }
+        }
+        let map_names = mappings.iter().map(|map| map.get_identifier().clone()).collect();
+        Self{map_names, classes, subjects, usages, prefixes}
+    }
+
+    pub fn write_prefixes<W: Write>(&self, out: &mut W) -> ResultApp<()>{
+        rdf::turtle::TurtleWriter::new(&self.prefixes).write_header(out)
+    }
+
+    // The classes with their maps, and the properties with the domain and the range given by all their maps.
+    pub fn write_ontology<W: Write>(&self, out: &mut W) -> ResultApp<()>{
+        let turtle = rdf::turtle::TurtleWriter::new(&self.prefixes);
+        let iri = |iri: &str| turtle.term(&Term::Iri(iri.to_string()));
+        writeln!(out, "[] a {} ;", iri(&format!("{}Ontology", OWL)))?;
+        writeln!(out, "    {} \"Classes and properties of the RDF generated by the mappings\" .", iri(&format!("{}comment", RDFS)))?;
+
+        for class in unique(self.classes.iter().flatten()){
+            let maps = self.maps_where(|i| self.classes[i].as_ref() == Some(class));
+            writeln!(out)?;
+            writeln!(out, "{} a {} ;", iri(class), iri(&format!("{}Class", OWL)))?;
+            writeln!(out, "    {} \"{}\" ;", iri(&format!("{}label", RDFS)), escape_literal(local_name(class)))?;
+            writeln!(out, "    {} \"Subjects of the maps: {}\" .", iri(&format!("{}comment", RDFS)), escape_literal(&maps))?;
+        }
+
+        for predicate in unique(self.usages.iter().map(|usage| &usage.predicate)){
+            let usages = self.usages.iter().filter(|usage| usage.predicate == *predicate).collect::<Vec<_>>();
+            let summary = summarise(&usages.iter().map(|usage| &usage.object).collect::<Vec<_>>());
+            let kind = match summary.node_kind{
+                Some("Literal") => format!("{}DatatypeProperty", OWL),
+                Some("IRI") | Some("BlankNode") | Some("BlankNodeOrIRI") => format!("{}ObjectProperty", OWL),
+                _ => format!("{}Property", RDF)
+            };
+            let maps = self.maps_where(|i| usages.iter().any(|usage| usage.map == i));
+            writeln!(out)?;
+            writeln!(out, "{} a {} ;", iri(predicate), iri(&kind))?;
+            writeln!(out, "    {} \"{}\" ;", iri(&format!("{}label", RDFS)), escape_literal(local_name(predicate)))?;
+            let domains = unique(usages.iter().map(|usage| &self.classes[usage.map]));
+            if let [Some(domain)] = domains.as_slice(){
+                writeln!(out, "    {} {} ;", iri(&format!("{}domain", RDFS)), iri(domain))?;
+            }
+            if let Some(range) = summary.datatype.as_ref().or(summary.class.as_ref()){
+                writeln!(out, "    {} {} ;", iri(&format!("{}range", RDFS)), iri(range))?;
+            }
+            writeln!(out, "    {} \"Generated by the maps: {}\" .", iri(&format!("{}comment", RDFS)), escape_literal(&maps))?;
+        }
+        Ok(())
+    }
+
+    // A node shape for each class, and for each map without class. A property has sh:minCount 1 only if all the maps
+    // of the shape generate it with a constant, because the rest of the objects can be missing in the data.
+    pub fn write_shapes<W: Write>(&self, out: &mut W) -> ResultApp<()>{
+        let turtle = rdf::turtle::TurtleWriter::new(&self.prefixes);
+        let iri = |iri: &str| turtle.term(&Term::Iri(iri.to_string()));
+        let sh = |name: &str| iri(&format!("{}{}", SH, name));
+        let mut groups: Vec<(Option<&String>, Vec<usize>)> = Vec::new();
+        for (i, class) in self.classes.iter().enumerate(){
+            match groups.iter_mut().find(|(found, _)| class.is_some() && *found == class.as_ref()){
+                Some((_, maps)) => maps.push(i),
+                None => groups.push((class.as_ref(), vec![i]))
+            }
+        }
+
+        let mut first = true;
+        // Classes of different namespaces and maps can have the same name, so the repeated names get a number.
+        let mut used = HashSet::new();
+        for (class, maps) in groups.iter(){
+            let name = match class{
+                Some(class) => local_name(class).to_string(),
+                None => self.map_names[maps[0]].clone()
+            };
+            if !first{
+                writeln!(out)?;
+            }
+            first = false;
+            let name = name.chars().filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect::<String>();
+            let name = (1..)
+                .map(|n| if n == 1 { name.clone() } else { format!("{}{}", name, n) })
+                .find(|name| !used.contains(name))
+                .unwrap();
+            used.insert(name.clone());
+            writeln!(out, "<#{}Shape> a {} ;", name, sh("NodeShape"))?;
+            let names = self.maps_where(|i| maps.contains(&i));
+            match class{
+                Some(class) => {
+                    writeln!(out, "    {} \"Subjects of the maps: {}\" ;", iri(&format!("{}comment", RDFS)), escape_literal(&names))?;
+                    write!(out, "    {} {}", sh("targetClass"), iri(class))?;
+                }
+                None => write!(out, "    {} \"Subjects of the maps: {}. They have no class to target them\"", iri(&format!("{}comment", RDFS)), escape_literal(&names))?
+            }
+            let subjects = maps.iter().filter_map(|&i| self.subjects[i].clone()).map(|kind| Object{kind, class: None, constant: None}).collect::<Vec<_>>();
+            if let Some(kind) = summarise(&subjects.iter().collect::<Vec<_>>()).node_kind{
+                write!(out, " ;\n    {} {}", sh("nodeKind"), sh(kind))?;
+            }
+
+            let usages = self.usages.iter().filter(|usage| maps.contains(&usage.map)).collect::<Vec<_>>();
+            for predicate in unique(usages.iter().map(|usage| &usage.predicate)){
+                let objects = usages.iter().filter(|usage| usage.predicate == *predicate).collect::<Vec<_>>();
+                let summary = summarise(&objects.iter().map(|usage| &usage.object).collect::<Vec<_>>());
+                let always = maps.iter().all(|&i| objects.iter().any(|usage| usage.map == i && usage.object.constant.is_some()));
+                writeln!(out, " ;")?;
+                writeln!(out, "    {} [", sh("property"))?;
+                write!(out, "        {} {}", sh("path"), iri(predicate))?;
+                if always{
+                    write!(out, " ;\n        {} 1", sh("minCount"))?;
+                }
+                if let Some(kind) = summary.node_kind{
+                    write!(out, " ;\n        {} {}", sh("nodeKind"), sh(kind))?;
+                }
+                if let Some(datatype) = &summary.datatype{
+                    write!(out, " ;\n        {} {}", sh("datatype"), iri(datatype))?;
+                }
+                if let Some(class) = &summary.class{
+                    write!(out, " ;\n        {} {}", sh("class"), iri(class))?;
+                }
+                if let Some(values) = &summary.values{
+                    write!(out, " ;\n        {} ( {} )", sh("in"), values.iter().map(|value| turtle.term(value)).collect::<Vec<_>>().join(" "))?;
+                }
+                write!(out, "\n    ]")?;
+            }
+            writeln!(out, " .")?;
+        }
+        Ok(())
+    }
+
+    // Names of the maps that pass the filter, separated by commas.
+    fn maps_where(&self, filter: impl Fn(usize) -> bool) -> String{
+        self.map_names.iter().enumerate()
+            .filter(|(i, _)| filter(*i))
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn summarise(objects: &[&Object]) -> Summary{
+    let has = |kind: fn(&Kind) -> bool| objects.iter().any(|object| kind(&object.kind));
+    let (iri, blank, literal) = (has(|kind| *kind == Kind::Iri), has(|kind| *kind == Kind::BlankNode), has(|kind| matches!(kind, Kind::Literal{..})));
+    let node_kind = match (iri, blank, literal){
+        (true, false, false) => Some("IRI"),
+        (false, true, false) => Some("BlankNode"),
+        (false, false, true) => Some("Literal"),
+        (true, true, false) => Some("BlankNodeOrIRI"),
+        (false, true, true) => Some("BlankNodeOrLiteral"),
+        (true, false, true) => Some("IRIOrLiteral"),
+        _ => None
+    };
+    let datatypes = unique(objects.iter().map(|object| match &object.kind{
+        Kind::Literal{language: Some(_), ..} => Some(format!("{}langString", RDF)),
+        Kind::Literal{datatype: Some(datatype), ..} => Some(datatype.clone()),
+        Kind::Literal{..} => Some(format!("{}string", XSD)),
+        _ => None
+    }));
+    let classes = unique(objects.iter().map(|object| &object.class));
+    let values = objects.iter().map(|object| object.constant.clone()).collect::<Option<Vec<_>>>();
+    Summary{
+        node_kind,
+        datatype: match datatypes.as_slice(){
+            [Some(datatype)] => Some(datatype.clone()),
+            _ => None
+        },
+        class: match classes.as_slice(){
+            [Some(class)] => Some(class.to_string()),
+            _ => None
+        },
+        values: values.map(|values| unique(values.iter()).into_iter().cloned().collect())
+    }
+}
+
+// The items in the order they are first found, without repetitions.
+fn unique<T: PartialEq>(items: impl Iterator<Item = T>) -> Vec<T>{
+    let mut found = Vec::new();
+    for item in items{
+        if !found.contains(&item){
+            found.push(item);
+        }
+    }
+    found
+}
+
+// Name of an IRI after its last '#' or '/', used as label.
+fn local_name(iri: &str) -> &str{
+    iri.rsplit(['#', '/']).find(|name| !name.is_empty()).unwrap_or(iri)
+}
+
+#[cfg(test)]
+mod test_describe{
+    use super::*;
+    use crate::mappings::AcceptedType;
+    use crate::rdf::reader;
+    use crate::shacl::shapes::ShapesGraph;
+    use std::sync::Arc;
+
+    fn map(name: &str, subject: Vec<Parts>, predicates: Vec<(&str, Vec<Parts>)>) -> Mapping{
+        let mut map = Mapping::new(name.to_string());
+        map.add_component(Parts::LogicalSource{
+            source: std::path::PathBuf::from(format!("{}.csv", name)),
+            reference_formulation: AcceptedType::CSV,
+            iterator: String::new()
+        });
+        map.add_component(Parts::SubjectMap{components: subject});
+        for (predicate, object_map) in predicates{
+            map.add_component(Parts::PredicateObjectMap{predicate: predicate.to_string(), object_map});
+        }
+        let prefixes = vec![("ex:".to_string(), "http://example.com/".to_string()), ("xsd:".to_string(), XSD.to_string())].into_iter().collect();
+        map.change_prefixes(Arc::new(prefixes));
+        map
+    }
+
+    #[test]
+    fn shapes_follow_the_objects_of_the_maps(){
+        let template = |template: &str, field: &str| Parts::Template{template: template.to_string(), input_fields: vec![field.to_string()]};
+        let person = map("Person", vec![template("http://example.com/person/{}", "id"), Parts::Class("ex:Person".to_string())], vec![
+            ("ex:name", vec![Parts::Reference("name".to_string())]),
+            ("ex:age", vec![Parts::Reference("age".to_string()), Parts::DataType("xsd:integer".to_string())]),
+            ("ex:source", vec![Parts::ConstantTerm("ex:census".to_string())]),
+            ("ex:livesIn", vec![Parts::ParentMap("City".to_string()), Parts::JoinCondition("city".to_string(), "city".to_string(), Default::default())])
+        ]);
+        let city = map("City", vec![template("http://example.com/city/{}", "city"), Parts::Class("ex:City".to_string())], vec![
+            ("ex:label", vec![Parts::Reference("label".to_string()), Parts::Language("es".to_string())])
+        ]);
+        let description = Description::new(&[person, city]);
+
+        let mut text = Vec::new();
+        description.write_prefixes(&mut text).unwrap();
+        description.write_ontology(&mut text).unwrap();
+        description.write_shapes(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("ex:livesIn a owl:ObjectProperty ;\n    rdfs:label \"livesIn\" ;\n    rdfs:domain ex:Person ;\n    rdfs:range ex:City ;"));
+        assert!(text.contains("sh:path ex:source ;\n        sh:minCount 1 ;\n        sh:nodeKind sh:IRI ;\n        sh:in ( ex:census )"));
+        assert!(text.contains("sh:path ex:label ;\n        sh:nodeKind sh:Literal ;\n        sh:datatype rdf:langString"));
+
+        // The shapes can be used to validate a run
+        let shapes = ShapesGraph::from_triples(reader::read_turtle(&text, Some("file:///shapes.ttl")).unwrap()).unwrap();
+        assert_eq!(shapes.shapes.len(), 7);
+        assert_eq!(shapes.shapes[0].id, Term::Iri("file:///shapes.ttl#CityShape".to_string()));
+    }
+
+    #[test]
+    fn shape_names_are_unique(){
+        let subject = |class: Option<&str>| {
+            let mut parts = vec![Parts::Template{template: "http://example.com/{}".to_string(), input_fields: vec!["id".to_string()]}];
+            parts.extend(class.map(|class| Parts::Class(class.to_string())));
+            parts
+        };
+        let description = Description::new(&[
+            map("People", subject(Some("ex:Person")), vec![]),
+            map("Agents", subject(Some("<http://xmlns.com/foaf/0.1/Person>")), vec![]),
+            map("Person", subject(None), vec![])
+        ]);
+
+        let mut text = Vec::new();
+        description.write_prefixes(&mut text).unwrap();
+        description.write_shapes(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let shapes = ShapesGraph::from_triples(reader::read_turtle(&text, Some("file:///shapes.ttl")).unwrap()).unwrap();
+        let mut names = shapes.shapes.iter().map(|shape| shape.id.clone()).collect::<Vec<_>>();
+        names.sort();
+        let name = |name: &str| Term::Iri(format!("file:///shapes.ttl#{}", name));
+        assert_eq!(names, [name("Person2Shape"), name("Person3Shape"), name("PersonShape")]);
+    }
+}
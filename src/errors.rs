@@ -20,6 +20,7 @@ pub enum ApplicationErrors{
     IncorrectJsonPath,
     IncorrectXMLFile,
     IncorrectXPath,
+    MissingEnvironmentVariable,
 
     // Database Errors
     CantOpenDatabase,
@@ -38,6 +39,22 @@ pub enum ApplicationErrors{
      
     // RDF Creations
     FAiledToCreateRDF,
+    InvalidIRI,
+    InvalidQName,
+    InvalidXMLCharacter,
+    InvalidNTriplesLine,
+    FailedToUploadRDF,
+
+    // Query Errors
+    #[cfg(feature = "sparql")]
+    InvalidSparqlQuery,
+    #[cfg(feature = "sparql")]
+    CantStartEndpoint,
+
+    // Validation Errors
+    InvalidTurtle,
+    InvalidShape,
+    NotConformingToShapes,
 
     // Other errors
     FailedToTransmitDataBetweenThreads,
@@ -47,6 +64,15 @@ pub enum ApplicationErrors{
     Miscelaneous
 }
 
+// The query engine needs the errors of the datasets that it reads to be standard errors.
+impl std::fmt::Display for ApplicationErrors{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ApplicationErrors{}
+
 impl From<io::Error> for ApplicationErrors{
     fn from(error: io::Error) -> Self{
          match error.kind(){
@@ -124,6 +150,18 @@ impl From<sxd_xpath::Error> for ApplicationErrors{
     }
 }
 
+impl From<sxd_xpath::ParserError> for ApplicationErrors{
+    fn from(_: sxd_xpath::ParserError) -> Self{
+        Self::IncorrectXPath
+    }
+}
+
+impl From<sxd_xpath::ExecutionError> for ApplicationErrors{
+    fn from(_: sxd_xpath::ExecutionError) -> Self{
+        Self::IncorrectXPath
+    }
+}
+
 impl From<serde_json::Error> for ApplicationErrors{
     fn from(_ : serde_json::Error) -> Self{
         Self::IncorrectJsonFile
@@ -135,3 +173,30 @@ impl From<jsonpath_lib::JsonPathError> for ApplicationErrors{
     }
 }
 
+#[cfg(feature = "sparql")]
+impl From<spargebra::SparqlSyntaxError> for ApplicationErrors{
+    fn from(error: spargebra::SparqlSyntaxError) -> Self{
+        crate::error!("The SPARQL query is not valid: {}", error);
+        Self::InvalidSparqlQuery
+    }
+}
+
+#[cfg(feature = "sparql")]
+impl From<spareval::QueryEvaluationError> for ApplicationErrors{
+    fn from(error: spareval::QueryEvaluationError) -> Self{
+        match error{
+            // The errors of the store or of the mappings were already reported
+            spareval::QueryEvaluationError::Dataset(error) => match error.downcast::<ApplicationErrors>(){
+                Ok(error) => *error,
+                Err(error) => {
+                    crate::error!("The query could not be answered: {}", error);
+                    Self::Miscelaneous
+                }
+            },
+            error => {
+                crate::error!("The query could not be answered: {}", error);
+                Self::InvalidSparqlQuery
+            }
+        }
+    }
+}
@@ -2,34 +2,72 @@
 use crate::mappings::AcceptedType;
 use crate::{error, info};
 use crate::config;
+use crate::config::Compression;
 use crate::ResultApp;
 use crate::errors::ApplicationErrors;
 
 
 use std::fs;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
 use std::sync::{mpsc};
 
 use std::collections::{HashMap, HashSet};
 use jsonpath_lib::selector;
-use sxd_xpath::evaluate_xpath;
+use sxd_xpath::{Context, Factory, Value};
 
 // Trial and error
 const MAX_BATCH: usize = 23; // Number of Insert Queries that are executed by batch
 
-pub fn read_store_data_files(config: &mut config::AppConfiguration, fields: HashMap<PathBuf, HashSet<String>>) -> ResultApp<(rusqlite::Connection, bool)>{
-    let mut fi = Vec::new();
-    let mut paths = Vec::new();
+// Locations of the database. The in-memory database is named so the writer threads can open their own connections to it.
+const MEMORY_DB: &str = "file:rossete-data?mode=memory&cache=shared";
+const FILE_DB: &str = "./rossete-tmp/data_tmp.sqlite";
+
+// First bytes of the compressed data files
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+// Usual size of a text data file compared with its compressed size, used when the file does not store its size
+const COMPRESSION_RATIO: u64 = 5;
+
+// Database where the data files of the staged maps are stored.
+pub struct StagedData{
+    // Keeps the database alive while the writer threads read it
+    pub connection: rusqlite::Connection,
+    location: &'static str
+}
+
+impl StagedData{
+    pub fn location(&self) -> &'static str{
+        self.location
+    }
+}
+
+// Opens a new read-only connection, so every writer thread can query the database without locking the others.
+pub fn open_reader(location: &str) -> ResultApp<rusqlite::Connection>{
+    let mut flags = rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY |
+        rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX |
+        rusqlite::OpenFlags::SQLITE_OPEN_URI;
+    if location == MEMORY_DB{
+        flags |= rusqlite::OpenFlags::SQLITE_OPEN_SHARED_CACHE;
+    }
+    Ok(rusqlite::Connection::open_with_flags(location, flags)?)
+}
+
+pub fn read_store_data_files(config: &mut config::AppConfiguration, fields: HashMap<PathBuf, HashSet<String>>) -> ResultApp<(StagedData, bool)>{
+    let mut sizes = Vec::new();
     
-    let files = config.get_data_files();
+    // Only the files used by the staged maps are stored in the database.
+    let files = config.get_data_files().iter()
+        .filter(|(p, _)| fields.contains_key(*p))
+        .map(|(p, specs)| (p.clone(), specs.clone()))
+        .collect::<HashMap<_, _>>();
     for p in files.keys(){
         info!("Opening File: {}", p.display());
-        fi.push(fs::File::open(p)?);
-        paths.push(p.clone());
+        sizes.push(data_file_size(p)?);
     }
     
-    let (loc, is_file) = select_storage_loc(&fi, &config)?;
+    let (loc, is_file) = select_storage_loc(&sizes, config)?;
     let (data_tx, data_rx) = mpsc::channel();
 
     let num_files = files.len();
@@ -37,21 +75,17 @@ pub fn read_store_data_files(config: &mut config::AppConfiguration, fields: Hash
         store_data(loc, data_rx, num_files)
     });
     create_tables(data_tx.clone(), &files, &fields)?; // This will not fail for sure.
-    reading_procedure(config, data_tx, &fields)?;    
+    reading_procedure(config, &files, data_tx, &fields)?;    
     
     match handler.join()?{
-        Ok(db) => Ok((db, is_file)),
+        Ok(db) => Ok((StagedData{connection: db, location: loc}, is_file)),
         Err(error) => Err(error)
     }
 }
 
-fn select_storage_loc(fi: &Vec<fs::File>, config: &config::AppConfiguration) -> ResultApp<(&'static str, bool)>{    
+fn select_storage_loc(sizes: &[usize], config: &config::AppConfiguration) -> ResultApp<(&'static str, bool)>{    
     // Get an extimated Size of the datafiles combined. This is more as a guide, given that could be some dupllicate rows that are going to be eliminated.
-    let total_memory_usage: usize = fi.iter()
-        .map(|file| {
-            file.metadata().expect("No Metadata Was Found: Size of File is needed").len() as usize
-        })
-        .sum();
+    let total_memory_usage: usize = sizes.iter().sum();
 
     let total_memory_usage = total_memory_usage  / 1048576; // To Transform the number of bytes to megabytes (MB) 
 
@@ -60,7 +94,7 @@ fn select_storage_loc(fi: &Vec<fs::File>, config: &config::AppConfiguration) ->
     if !config.debug_mode() && config.can_be_in_memory_db(total_memory_usage){
         info!("All the files is estimated to requiere {} MB, TMP Database will be created in memory", total_memory_usage);
 
-        loc = ":memory:";
+        loc = MEMORY_DB;
         is_file = false;
     }else{
         info!("All the files is estimated to requiere {} MB, TMP Database will be created in a sqlite DB File", total_memory_usage);
@@ -71,18 +105,84 @@ fn select_storage_loc(fi: &Vec<fs::File>, config: &config::AppConfiguration) ->
         }
         fs::create_dir("rossete-tmp")?;
         is_file = true;
-        loc = "./rossete-tmp/data_tmp.sqlite";
+        loc = FILE_DB;
     }
 
     Ok((loc, is_file))
 }
 
+// Compression of a data file, given by its extension or by the first bytes of the file.
+fn data_file_compression(path: &Path) -> ResultApp<Option<Compression>>{
+    if let Some(compression) = Compression::from_path(path){
+        return Ok(Some(compression))
+    }
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    fs::File::open(path)?.take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    if magic.starts_with(GZIP_MAGIC){
+        Ok(Some(Compression::Gzip))
+    }else if magic.starts_with(ZSTD_MAGIC){
+        Ok(Some(Compression::Zstd))
+    }else if magic.starts_with(BZIP2_MAGIC){
+        Ok(Some(Compression::Bzip2))
+    }else{
+        Ok(None)
+    }
+}
+
+// Opens a data file. The compressed files are decompressed while they are read.
+pub fn open_data_file(path: &Path) -> ResultApp<Box<dyn Read>>{
+    let file = io::BufReader::new(fs::File::open(path)?);
+    Ok(match data_file_compression(path)?{
+        Some(Compression::Gzip) => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::with_buffer(file)?),
+        Some(Compression::Bzip2) => Box::new(bzip2::bufread::MultiBzDecoder::new(file)),
+        None => Box::new(file)
+    })
+}
+
+// Size of the data of a file once it is decompressed, without reading the whole file. The zstd files store it in the
+// header of the frame. The gzip ones store it modulo 4 GB at the end, so the multiple of 4 GB is taken from the usual
+// ratio of compression. The size of the rest of them is estimated with that ratio.
+fn data_file_size(path: &Path) -> ResultApp<usize>{
+    let file_size = fs::metadata(path)?.len();
+    let estimated_size = file_size.saturating_mul(COMPRESSION_RATIO);
+    let size = match data_file_compression(path)?{
+        None => file_size,
+        Some(Compression::Gzip) => {
+            let mut file = fs::File::open(path)?;
+            let mut size = [0; 4];
+            file.seek(io::SeekFrom::End(-4))?;
+            file.read_exact(&mut size)?;
+            gzip_size(u32::from_le_bytes(size) as u64, file_size)
+        }
+        Some(Compression::Zstd) => {
+            let mut header = Vec::with_capacity(18);
+            fs::File::open(path)?.take(18).read_to_end(&mut header)?;
+            zstd::zstd_safe::get_frame_content_size(&header).ok().flatten().unwrap_or(estimated_size)
+        }
+        Some(Compression::Bzip2) => estimated_size
+    };
+    Ok(size as usize)
+}
+
+// Size of a gzip file from the size modulo 4 GB stored in it, that is the closest one to the estimated size.
+fn gzip_size(stored_size: u64, file_size: u64) -> u64{
+    let estimated_size = file_size.saturating_mul(COMPRESSION_RATIO);
+    let wraps = (estimated_size.saturating_sub(stored_size) + (1 << 31)) >> 32;
+    stored_size + (wraps << 32)
+}
+
 fn store_data(localization: &str, data_rx: mpsc::Receiver<String>, total_files: usize) -> ResultApp<rusqlite::Connection>{
     let conn = rusqlite::Connection::open_with_flags(localization,
         rusqlite::OpenFlags::SQLITE_OPEN_SHARED_CACHE |
         rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX |
         rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE |
+        rusqlite::OpenFlags::SQLITE_OPEN_URI |
         rusqlite::OpenFlags::SQLITE_OPEN_CREATE)?; // Database Connection
+    if localization == FILE_DB{
+        // The readers of the writer threads do not block each other.
+        conn.query_row("PRAGMA journal_mode=WAL;", [], |_| Ok(()))?;
+    }
     
     let mut left_files = total_files;
     let mut batch_size = 0;
@@ -185,8 +285,7 @@ fn get_table_name_with_iterator(path: &PathBuf, file_type: &AcceptedType, iterat
 
 
 // This function creates and manages all the reading threads of the program.
-fn reading_procedure(config: &config::AppConfiguration, con: mpsc::Sender<String>, input_fields: &HashMap<PathBuf, HashSet<String>>) -> ResultApp<()>{
-    let files = config.get_data_files();
+fn reading_procedure(config: &config::AppConfiguration, files: &HashMap<PathBuf, config::FileSpecs>, con: mpsc::Sender<String>, input_fields: &HashMap<PathBuf, HashSet<String>>) -> ResultApp<()>{
     let paths = files.keys().collect::<Vec<_>>();
     let mut current_file = 0;
     let mut threads = Vec::with_capacity(config.get_reading_theads());
@@ -250,61 +349,26 @@ fn reading_procedure(config: &config::AppConfiguration, con: mpsc::Sender<String
 }
 
 fn read_csv(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sender<String>, rc: mpsc::Sender<usize>, fields: Vec<String>) -> ResultApp<()>{
-    // TDOO Given the file type, it creates the reader.
     let table_name = get_table_name(&path, specs.get_file_type());
 
-    let file = fs::File::open(&path)?;
-    let encoding = specs.get_encoding();
-    let file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
-                                            .encoding(Some(encoding))
-                                            .build(file);
-
-    let mut csv_file = csv::ReaderBuilder::new()
-        .delimiter(specs.get_delimiter() as u8)
-        .has_headers(specs.get_has_header())
-        .from_reader(file_reader);
-
     let mut initial_query = format!("INSERT INTO {} (\"", table_name);
     for (i, field) in fields.iter().enumerate(){
-        initial_query.extend(field.chars());
+        initial_query.push_str(field);
         if i != fields.len() - 1{
             initial_query.push_str("\", \"")
         }else{
-            initial_query.push_str("\") VALUES (\"")
+            initial_query.push_str("\") VALUES (")
         }
     }
 
-    for row in csv_file.deserialize() {
-        let row_data: HashMap<String, String> = match row{
-            Ok(d) => d,
-            Err(error) => {
-                error!("CSV Reader could not extract row from file {}", path.display());
-                return Err(error.into());
-            }
-        };
-        let column_names = row_data.keys().collect::<Vec<_>>();
-        if !fields.iter().all(|k| column_names.contains(&k)){
-            con.send(format!("{:6}", id))?;
-            rc.send(id)?;
-            error!("There is a missing field in the data file corresponding to the following data table: {}.", table_name);
-            eprintln!("This are the columns in the CSV and the requested columns in all the files. Consider changing delimiter in the configuration file to remedy this.");
-            column_names.iter().for_each(|c| eprintln!("COLUMN: {}", c));
-            fields.iter().for_each(|c| eprintln!("FIELD: {}", c));
-            return Err(ApplicationErrors::MissingFieldInData)        
-        }
-
-        let data = fields.iter().map(|column| row_data.get(column).expect("There is a missing value in a CSV row").clone()).collect::<Vec<_>>();
-        let mut query_buffer = String::with_capacity(fields.iter().map(|f| f.len()).sum::<usize>() + 100);
-        query_buffer.extend(initial_query.chars());
-        for (i,d) in data.iter().enumerate(){
-            query_buffer.extend(d.chars());
-            if i != data.len() - 1{
-                query_buffer.push_str("\", \"")
-            }else{
-                query_buffer.push_str("\");")
-            }
-        }
-        con.send(query_buffer)?;
+    let result = csv_rows(&path, &specs, &fields, |data| {
+        con.send(insert_query(&initial_query, &data))?;
+        Ok(())
+    });
+    if let Err(error) = result{
+        con.send(format!("{:6}", id))?;
+        rc.send(id)?;
+        return Err(error)
     }
 
     // DELETE duplicates
@@ -320,74 +384,26 @@ fn read_csv(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sende
 fn read_json(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sender<String>, rc: mpsc::Sender<usize>, fields: Vec<String>) -> ResultApp<()>{
 
     // Read File and get the parsed json.
-    let file = fs::File::open(&path)?;
-    let encoding = specs.get_encoding();
-    let file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
-                                            .encoding(Some(encoding))
-                                            .build(file);
-
-
-    let json_data: serde_json::Value = serde_json::from_reader(file_reader)?;
+    let json_data = open_json(&path, &specs)?;
 
     // Divide field by the iterator that uses
     let iter_field = extract_iterator_and_fields(&fields);
     // Iterate by the iterators and get the need data
-    
-    let mut data_iterator = selector(&json_data);
-
     for (ref iterator, ref associated_fields) in iter_field{
-        let iterable_data = data_iterator(iterator)?;
         let table_name = get_table_name_with_iterator(&path, specs.get_file_type(), iterator);
-        for data in iterable_data.iter(){
-
-            let mut field_sel = selector(data);
-            let mut init_query = format!("INSERT INTO {} (\"", &table_name); // until the insert of the data
-            let mut data_query = String::with_capacity(255);    
-            for (i, (field, col)) in associated_fields.iter().enumerate(){
-                let retrieven = field_sel(field)?;
-                if retrieven.is_empty(){
-                    if i == associated_fields.len() - 1{
-                        init_query.push_str("\") VALUES (");
-                        data_query.push_str(");");
-                    }
-                    continue
-                } 
-                let data_retrieven = match to_string_json(retrieven[0]){
-                    Some(data) => data,
-                    None => {
-                        if i == associated_fields.len() - 1{
-                            // remove last added chars
-                            init_query.pop(); // "
-                            init_query.pop(); // _
-                            init_query.pop(); // ,
-
-                            data_query.pop(); // _ 
-                            data_query.pop(); // ,
-
-                            init_query.push_str(") VALUES (");
-                            data_query.push_str(");");
-                        }
-                        continue    
-                    }
-                };
-                init_query.extend(col.chars());
-                data_query.extend(data_retrieven.chars());
+        let columns = associated_fields.iter().map(|(_, col)| col.clone()).collect::<Vec<_>>();
 
-                if i != associated_fields.len() - 1{
-                    init_query.push_str("\", \"");
-                    data_query.push_str(", ");
+        let mut initial_query = format!("INSERT INTO {} (\"", &table_name); // until the insert of the data
+        initial_query.push_str(&columns.join("\", \""));
+        initial_query.push_str("\") VALUES (");
 
-                }else{
-                    init_query.push_str("\") VALUES (");
-                    data_query.push_str(");");
-                }
-            }
+        json_rows(&json_data, iterator, associated_fields, |data| {
+            con.send(insert_query(&initial_query, &data))?;
+            Ok(())
+        })?;
 
-            init_query.extend(data_query.chars());
-            con.send(init_query)?;
-        }
         // Remove duplicates
-        let remove_duplicates = query_remove_duplicates(&table_name, &fields);
+        let remove_duplicates = query_remove_duplicates(&table_name, &columns);
         con.send(remove_duplicates)?;
     }
 
@@ -397,36 +413,173 @@ fn read_json(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Send
     Ok(())
 }
 
-fn read_xml(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sender<String>, rc: mpsc::Sender<usize>, fields: Vec<String>) -> ResultApp<()>{
-    // TODO Read XML files
-    let file = fs::File::open(&path)?;
-    let mut xml_string = String::with_capacity(file.metadata().unwrap().len() as usize); 
+/// Reads the data file row by row without storing it in the database. Each row holds the values of the
+/// requested fields in the same order as they were given. Fields follow the same naming as the database
+/// columns, so JSON and XML fields are prefixed with the iterator ("iterator||field").
+pub fn stream_data_file<F>(path: &Path, specs: &config::FileSpecs, fields: &[String], mut row_fn: F) -> ResultApp<()>
+where F: FnMut(Vec<String>) -> ResultApp<()>{
+    let file_type = specs.get_file_type();
+    if file_type.is_csv() || file_type.is_tsv(){
+        csv_rows(path, specs, fields, row_fn)
+    }else if file_type.is_json(){
+        let json_data = open_json(path, specs)?;
+        for (ref iterator, ref associated_fields) in extract_iterator_and_fields(fields){
+            // Each row has to respect the given order of the fields.
+            let positions = associated_fields.iter()
+                .map(|(_, col)| fields.iter().position(|f| f == col).unwrap())
+                .collect::<Vec<_>>();
+            json_rows(&json_data, iterator, associated_fields, |data| {
+                let mut row = vec![String::new(); fields.len()];
+                for (value, &pos) in data.into_iter().zip(positions.iter()){
+                    row[pos] = value;
+                }
+                row_fn(row)
+            })?;
+        }
+        Ok(())
+    }else if file_type.is_xml(){
+        let xml_string = open_xml(path, specs)?;
+        let xml_package = sxd_document::parser::parse(&xml_string)?;
+        let xml_doc = xml_package.as_document();
+        for (ref iterator, ref associated_fields) in extract_iterator_and_fields(fields){
+            let positions = associated_fields.iter()
+                .map(|(_, col)| fields.iter().position(|f| f == col).unwrap())
+                .collect::<Vec<_>>();
+            xml_rows(&xml_doc, iterator, associated_fields, |data| {
+                let mut row = vec![String::new(); fields.len()];
+                for (value, &pos) in data.into_iter().zip(positions.iter()){
+                    row[pos] = value;
+                }
+                row_fn(row)
+            })?;
+        }
+        Ok(())
+    }else{
+        error!("The file {} can not be streamed given its format: {:?}", path.display(), file_type);
+        Err(ApplicationErrors::InvalidDataEntry)
+    }
+}
+
+// Iterates all the rows of a CSV/TSV file with the values of the requested fields.
+fn csv_rows<F>(path: &Path, specs: &config::FileSpecs, fields: &[String], mut row_fn: F) -> ResultApp<()>
+where F: FnMut(Vec<String>) -> ResultApp<()>{
+    let file = open_data_file(path)?;
     let encoding = specs.get_encoding();
-    let mut file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
+    let file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
                                             .encoding(Some(encoding))
                                             .build(file);
 
+    let mut csv_file = csv::ReaderBuilder::new()
+        .delimiter(specs.get_delimiter() as u8)
+        .has_headers(specs.get_has_header())
+        .from_reader(file_reader);
 
-    
-    file_reader.read_to_string(&mut xml_string)?;
+    let headers = csv_file.headers()?.clone();
+    let mut positions = Vec::with_capacity(fields.len());
+    for field in fields.iter(){
+        match headers.iter().position(|col| col == field){
+            Some(pos) => positions.push(pos),
+            None => {
+                error!("There is a missing field in the following data file: {}.", path.display());
+                eprintln!("This are the columns in the CSV and the requested columns in all the files. Consider changing delimiter in the configuration file to remedy this.");
+                headers.iter().for_each(|c| eprintln!("COLUMN: {}", c));
+                fields.iter().for_each(|c| eprintln!("FIELD: {}", c));
+                return Err(ApplicationErrors::MissingFieldInData)
+            }
+        }
+    }
+
+    for row in csv_file.records() {
+        let record = match row{
+            Ok(d) => d,
+            Err(error) => {
+                error!("CSV Reader could not extract row from file {}", path.display());
+                return Err(error.into());
+            }
+        };
+        let data = positions.iter()
+            .map(|&pos| record.get(pos).expect("There is a missing value in a CSV row").to_string())
+            .collect::<Vec<_>>();
+        row_fn(data)?;
+    }
+    Ok(())
+}
+
+fn open_json(path: &Path, specs: &config::FileSpecs) -> ResultApp<serde_json::Value>{
+    let file = open_data_file(path)?;
+    let encoding = specs.get_encoding();
+    let file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
+                                            .encoding(Some(encoding))
+                                            .build(file);
+
+    Ok(serde_json::from_reader(file_reader)?)
+}
+
+// Iterates all the elements selected by the iterator. Missing or non scalar values are returned as empty strings.
+fn json_rows<F>(json_data: &serde_json::Value, iterator: &str, fields: &[(String, String)], mut row_fn: F) -> ResultApp<()>
+where F: FnMut(Vec<String>) -> ResultApp<()>{
+    let mut data_iterator = selector(json_data);
+    let iterable_data = data_iterator(iterator)?;
+    for data in iterable_data.iter(){
+        let mut field_sel = selector(data);
+        let mut row = Vec::with_capacity(fields.len());
+        for (field, _) in fields.iter(){
+            let retrieven = field_sel(&format!("$.{}", field))?;
+            let value = retrieven.first()
+                .and_then(|&value| to_string_json(value))
+                .unwrap_or_default();
+            row.push(value);
+        }
+        row_fn(row)?;
+    }
+    Ok(())
+}
+
+// Completes the insert query with the row values. Empty values are stored as NULL and the rest as SQL string literals.
+fn insert_query(initial_query: &str, data: &[String]) -> String{
+    let mut query_buffer = String::with_capacity(initial_query.len() + data.iter().map(|d| d.len() + 4).sum::<usize>() + 2);
+    query_buffer.push_str(initial_query);
+    for (i, d) in data.iter().enumerate(){
+        if d.is_empty(){
+            query_buffer.push_str("NULL");
+        }else{
+            query_buffer.push('\'');
+            query_buffer.push_str(&d.replace('\'', "''"));
+            query_buffer.push('\'');
+        }
+        if i != data.len() - 1{
+            query_buffer.push_str(", ");
+        }
+    }
+    query_buffer.push_str(");");
+    query_buffer
+}
+
+fn read_xml(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sender<String>, rc: mpsc::Sender<usize>, fields: Vec<String>) -> ResultApp<()>{
+    // Read File and parse the document
+    let xml_string = open_xml(&path, &specs)?;
     let xml_package = sxd_document::parser::parse(&xml_string)?;
     let xml_doc = xml_package.as_document();
 
+    // Divide field by the iterator that uses
     let iter_field = extract_iterator_and_fields(&fields);
-    for (iterator, __associated_fields) in iter_field.iter(){
-        let __table_name = get_table_name_with_iterator(&path, specs.get_file_type(), iterator);
-        let __iterable_data = evaluate_xpath(&xml_doc, iterator)?;        
-        // let remove_duplicates = query_remove_duplicates(&table_name, &fields);
-        // con.send(remove_duplicates)?;
-    }
+    for (ref iterator, ref associated_fields) in iter_field{
+        let table_name = get_table_name_with_iterator(&path, specs.get_file_type(), iterator);
+        let columns = associated_fields.iter().map(|(_, col)| col.clone()).collect::<Vec<_>>();
 
-    // Read File
+        let mut initial_query = format!("INSERT INTO {} (\"", &table_name);
+        initial_query.push_str(&columns.join("\", \""));
+        initial_query.push_str("\") VALUES (");
 
-    
-    // Divide field by the iterator that uses
-    // Iterate by the iterators and get the need data
+        xml_rows(&xml_doc, iterator, associated_fields, |data| {
+            con.send(insert_query(&initial_query, &data))?;
+            Ok(())
+        })?;
 
-    // Remove duplicates
+        // Remove duplicates
+        let remove_duplicates = query_remove_duplicates(&table_name, &columns);
+        con.send(remove_duplicates)?;
+    }
 
     // End Transmission
     con.send(format!("{:6}", id))?;
@@ -434,15 +587,66 @@ fn read_xml(id: usize, path: PathBuf, specs: config::FileSpecs, con: mpsc::Sende
     Ok(())
 }
 
-fn extract_iterator_and_fields(fields: &Vec<String>)  -> HashMap<String, Vec<(String, String)>>{
+fn open_xml(path: &Path, specs: &config::FileSpecs) -> ResultApp<String>{
+    let file = open_data_file(path)?;
+    let mut xml_string = String::with_capacity(fs::metadata(path)?.len() as usize); 
+    let encoding = specs.get_encoding();
+    let mut file_reader = encoding_rs_io::DecodeReaderBytesBuilder::new()
+                                            .encoding(Some(encoding))
+                                            .build(file);
+    file_reader.read_to_string(&mut xml_string)?;
+    Ok(xml_string)
+}
+
+// Iterates all the nodes selected by the iterator (XPath). The fields are evaluated using the node as context
+// and only the first selected node is used.
+fn xml_rows<F>(xml_doc: &sxd_document::dom::Document, iterator: &str, fields: &[(String, String)], mut row_fn: F) -> ResultApp<()>
+where F: FnMut(Vec<String>) -> ResultApp<()>{
+    let factory = Factory::new();
+    let context = Context::new();
+    let build = |path: &str| -> ResultApp<sxd_xpath::XPath>{
+        match factory.build(path)?{
+            Some(xpath) => Ok(xpath),
+            None => {
+                error!("The following XPath is empty: \"{}\"", path);
+                Err(ApplicationErrors::IncorrectXPath)
+            }
+        }
+    };
+
+    let iterator_path = build(iterator)?;
+    let field_paths = fields.iter().map(|(field, _)| build(field)).collect::<ResultApp<Vec<_>>>()?;
+
+    let nodes = match iterator_path.evaluate(&context, xml_doc.root())?{
+        Value::Nodeset(nodes) => nodes.document_order(),
+        _ => {
+            error!("The XPath iterator \"{}\" does not select any node", iterator);
+            return Err(ApplicationErrors::IncorrectXPath)
+        }
+    };
+
+    for node in nodes{
+        let mut row = Vec::with_capacity(fields.len());
+        for xpath in field_paths.iter(){
+            let value = match xpath.evaluate(&context, node)?{
+                Value::Nodeset(selected) => selected.document_order_first().map(|n| n.string_value()).unwrap_or_default(),
+                other => other.string()
+            };
+            row.push(value);
+        }
+        row_fn(row)?;
+    }
+    Ok(())
+}
+
+fn extract_iterator_and_fields(fields: &[String])  -> HashMap<String, Vec<(String, String)>>{
     let mut iter_field = HashMap::new();
 
     for f in fields{
         let mut split = f.split("||");
         let iterator = split.next().unwrap().to_string();
         let field = split.next().unwrap().to_string();
-        let field = format!("$.{}", field);
-        let iter_cap = iter_field.entry(iterator).or_insert(Vec::new());
+        let iter_cap = iter_field.entry(iterator).or_insert_with(Vec::new);
         iter_cap.push((field, f.clone())); // Field, Column Name
     }
     
@@ -468,21 +672,60 @@ fn query_remove_duplicates(table: &String, fields: &Vec<String>) -> String{
 
 fn to_string_json(value: &serde_json::Value) -> Option<String>{
     if value.is_array() || value.is_object() || value.is_null(){
-        return None
+        None
     }else if value.is_string(){
-        return Some(format!("\"{}\"", value.as_str().unwrap()))
+        Some(value.as_str().unwrap().to_string())
     }else if value.is_i64(){
-        return Some(format!("\"{}\"", value.as_i64().unwrap()))
+        Some(format!("{}", value.as_i64().unwrap()))
     }else if value.is_f64(){
-        return Some(format!("\"{}\"", value.as_f64().unwrap()))
+        Some(format!("{}", value.as_f64().unwrap()))
     }else if value.is_boolean(){
-        return Some(format!("\"{}\"", value.as_bool().unwrap()))
+        Some(format!("{}", value.as_bool().unwrap()))
     }else if value.is_u64(){
-        return Some(format!("\"{}\"", value.as_u64().unwrap()))
+        Some(format!("{}", value.as_u64().unwrap()))
     }else if value.is_number(){
-        return Some(format!("\"{}\"", value.as_f64().unwrap()))
+        Some(format!("{}", value.as_f64().unwrap()))
     }else{
-        return None
+        None
+    }
+}
+
+#[cfg(test)]
+mod test_input{
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn compressed_files_are_read_decompressed(){
+        let content = "id,name\n".to_string() + &"1,Santiago\n".repeat(1000);
+        let path = std::env::temp_dir().join(format!("rossete-test-{}.csv", std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&path).unwrap(), flate2::Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        // The file does not have a compression extension, so it is found by its first bytes.
+        assert_eq!(data_file_compression(&path).unwrap(), Some(Compression::Gzip));
+        assert_eq!(data_file_size(&path).unwrap(), content.len());
+        let mut read = String::new();
+        open_data_file(&path).unwrap().read_to_string(&mut read).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, content);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn compressed_sizes_are_estimated_without_decompressing(){
+        let content = "id,name\n".to_string() + &"1,Santiago\n".repeat(1000);
+        let path = std::env::temp_dir().join(format!("rossete-test-size-{}.csv.bz2", std::process::id()));
+        let mut encoder = bzip2::write::BzEncoder::new(fs::File::create(&path).unwrap(), bzip2::Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        let file_size = fs::metadata(&path).unwrap().len();
+        assert_eq!(data_file_size(&path).unwrap() as u64, file_size * COMPRESSION_RATIO);
+        fs::remove_file(&path).unwrap();
+
+        // The stored size of gzip is exact for the small files, and the bigger ones get the multiple of 4 GB
+        assert_eq!(gzip_size(10_000, 100), 10_000);
+        assert_eq!(gzip_size(1 << 30, 2 << 30), (1 << 30) + (2 << 32));
+        assert_eq!(gzip_size(0, 1 << 30), 1 << 32);
+    }
+}
@@ -9,10 +9,19 @@ mod parser;
 mod input;
 mod config;
 mod materialiser;
+mod rdf;
+mod output;
+mod upload;
+#[cfg(feature = "sparql")]
+mod sparql;
+mod shacl;
+mod describe;
+mod metadata;
 
 use config::AppConfiguration;
 use logging::*;
 use mappings::maps::Mapping;
+use mappings::AcceptedType;
 
 
 use std::path::{self, PathBuf};
@@ -20,25 +29,38 @@ use std::sync::mpsc::channel;
 use std::collections::{HashMap, HashSet};
 
 use std::time::Instant;
+use std::io::Write;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use clap::{crate_authors, crate_version, crate_description};
 
 const DEBUG: bool = cfg!(debug_assertions);
+#[cfg(feature = "sparql")]
+const DEFAULT_ENDPOINT_ADDRESS: &str = "127.0.0.1:8000";
 
 fn main(){
-    let m = App::new("Rossete RDF Generator")
+    let app = App::new("Rossete RDF Generator")
         .about(crate_description!())
         .version(crate_version!())
         .author(crate_authors!())
         .help_message("Displays this message")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("output")
             .long("output")
             .value_name("OUTPUT")
             .required(true)
             .takes_value(true)
-            .help("File name where the output file is written")
+            .multiple(true)
+            .number_of_values(1)
+            .help("File name where the output file is written, - for the standard output or the http(s) URL of a Graph Store. It can be given several times")
+        )
+        .arg(
+            Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .takes_value(true)
+            .help("Format of the outputs whose extension does not give it, like the standard output. Values: nt, ttl, jsonld, rdf, hdt")
         )
         .arg(
             Arg::with_name("config")
@@ -70,15 +92,169 @@ fn main(){
             .long("clear")
             .help("Delete the database if it was created while reading the databases")
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("keep-partial")
+            .long("keep-partial")
+            .help("Write the output file directly, so it is kept even if some map fails. Useful for debugging")
+        )
+        .arg(
+            Arg::with_name("dedupe")
+            .long("dedupe")
+            .help("Remove the triples that are generated more than once in the run, even by different maps")
+        )
+        .arg(
+            Arg::with_name("deterministic")
+            .long("deterministic")
+            .help("Write the same output, byte by byte, in every run with the same mappings and data files")
+        )
+        .arg(
+            Arg::with_name("previous")
+            .long("previous")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("N-Triples output of a previous run. The .rdfp (RDF Patch) and .ru (SPARQL Update) outputs are the changes from it")
+        )
+        .arg(
+            Arg::with_name("added")
+            .long("added")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("File where the triples that are not in the previous output are written in N-Triples")
+        )
+        .arg(
+            Arg::with_name("removed")
+            .long("removed")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("File where the triples of the previous output that are not generated anymore are written in N-Triples")
+        )
+        .arg(
+            Arg::with_name("shapes")
+            .long("shapes")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("SHACL shapes in Turtle that the RDF must conform to. The run fails if there is any violation")
+        )
+        .arg(
+            Arg::with_name("report")
+            .long("report")
+            .value_name("FILE")
+            .takes_value(true)
+            .requires("shapes")
+            .help("File where the SHACL validation report is written in Turtle. Without it, the report is written to the standard error if the RDF does not conform")
+        )
+        .arg(
+            Arg::with_name("metadata")
+            .long("metadata")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("File where a VoID description of the RDF and the PROV-O provenance of the run are written in Turtle")
+        )
+        .arg(
+            Arg::with_name("graph")
+            .long("graph")
+            .value_name("IRI")
+            .takes_value(true)
+            .help("Named graph where the outputs are uploaded to a Graph Store. Without it, the default graph is used")
+        )
+        .arg(
+            Arg::with_name("merge")
+            .long("merge")
+            .help("Add the triples to the graph of the Graph Store (POST) instead of replacing the graph (PUT)")
+        )
+        .arg(
+            Arg::with_name("graph-store-header")
+            .long("graph-store-header")
+            .value_name("NAME=VARIABLE")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Header sent to the Graph Store with the value of an environment variable, like Authorization=FUSEKI_AUTH")
+        )
+        .arg(
+            Arg::with_name("store")
+            .long("store")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("SQLite store where the triples are also written, so they can be queried with the query subcommand")
+        )
+        .subcommand(
+            SubCommand::with_name("describe")
+            .about("Writes the classes and properties of the RDF of the mappings and SHACL shapes for them, without reading the data files")
+            .arg(
+                Arg::with_name("mappings")
+                .long("mappings")
+                .required(true)
+                .value_name("MAPPINGS")
+                .takes_value(true)
+                .help("Mappings that are described. Values: Folder or a file")
+            )
+            .arg(
+                Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Sets a custom config file to create the main settings of the program")
+            )
+            .arg(
+                Arg::with_name("ontology")
+                .long("ontology")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("File where the OWL and RDFS vocabulary is written in Turtle")
+            )
+            .arg(
+                Arg::with_name("shapes")
+                .long("shapes")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("File where the SHACL shapes are written in Turtle. Without --ontology and --shapes, both are written to the standard output")
+            )
+        );
+    #[cfg(feature = "sparql")]
+    let app = sparql_subcommands(app);
+    let m = app.get_matches();
+
+    #[cfg(feature = "sparql")]
+    if let Some(query) = m.subcommand_matches("query"){
+        if let Err(error) = run_query(query){
+            error!("The query could not be run. ERROR CODE: {:?}", error);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    #[cfg(feature = "sparql")]
+    if let Some(serve) = m.subcommand_matches("serve"){
+        if let Err(error) = run_endpoint(serve){
+            error!("The SPARQL endpoint stopped due to an error. ERROR CODE: {:?}", error);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if let Some(describe) = m.subcommand_matches("describe"){
+        if let Err(error) = run_describe(describe){
+            error!("The mappings could not be described. ERROR CODE: {:?}", error);
+            std::process::exit(1);
+        }
+        return
+    }
 
-    
     // This will be given by the user.
-    let output_file = if let Some(out) = m.value_of("output"){
-        path::PathBuf::from(out)
-    }else{
-        path::PathBuf::from("output.ttl")
+    let mut outputs = match m.values_of("output"){
+        Some(outputs) => outputs.map(config::OutputTarget::new).collect::<Vec<_>>(),
+        None => vec![config::OutputTarget::new("output.ttl")]
     };
+    if let Some(added) = m.value_of("added"){
+        outputs.push(config::OutputTarget::with_format(added, config::OutputFormat::AddedTriples));
+    }
+    if let Some(removed) = m.value_of("removed"){
+        outputs.push(config::OutputTarget::with_format(removed, config::OutputFormat::RemovedTriples));
+    }
+    if let Some(store) = m.value_of("store"){
+        outputs.push(config::OutputTarget::with_format(store, config::OutputFormat::Store));
+    }
 
     let config_file = if let Some(con) = m.value_of("config"){
         Some(path::PathBuf::from(con))
@@ -86,7 +262,11 @@ fn main(){
         None
     };
 
-    let mut configuration = config::get_configuration(&output_file, config_file); 
+    let mut configuration = config::get_configuration(&outputs, config_file); 
+
+    if let Some(format) = m.value_of("format"){
+        configuration.set_output_format(config::OutputFormat::from_str(&format.to_lowercase()));
+    }
 
     if DEBUG || m.is_present("debug") { // This will be activatedd using a cli flag
         configuration.set_debug_mode();
@@ -96,6 +276,52 @@ fn main(){
         configuration.set_clear_mode();
     }
 
+    if m.is_present("keep-partial"){
+        configuration.set_keep_partial_mode();
+    }
+
+    if m.is_present("dedupe"){
+        configuration.set_dedupe_mode();
+    }
+
+    if m.is_present("deterministic"){
+        configuration.set_deterministic_mode();
+    }
+
+    if let Some(previous) = m.value_of("previous"){
+        configuration.set_previous_output(path::PathBuf::from(previous));
+    }
+
+    if let Some(shapes) = m.value_of("shapes"){
+        configuration.set_shapes(path::PathBuf::from(shapes));
+    }
+
+    if let Some(report) = m.value_of("report"){
+        configuration.set_validation_report(path::PathBuf::from(report));
+    }
+
+    if let Some(metadata) = m.value_of("metadata"){
+        configuration.set_metadata_output(path::PathBuf::from(metadata));
+    }
+
+    if let Some(graph) = m.value_of("graph"){
+        configuration.set_upload_graph(graph.to_string());
+    }
+
+    if m.is_present("merge"){
+        configuration.set_upload_mode(upload::GraphMode::Merge);
+    }
+
+    for header in m.values_of("graph-store-header").into_iter().flatten(){
+        match header.split_once('='){
+            Some((name, variable)) => configuration.add_upload_header(name.trim().to_string(), variable.trim().to_string()),
+            None => {
+                error!("The header {} of the Graph Store must be given as NAME=VARIABLE, where VARIABLE is the environment variable with its value", header);
+                return
+            }
+        }
+    }
+
     // CLI Input
     let file_name = if let Some(maps) = m.value_of("mappings"){
         path::PathBuf::from(maps)
@@ -110,14 +336,32 @@ fn main(){
         },
         Err(error) => {
             error!("Process Finished Due to an error. ERROR CODE: {:?}", error);
+            std::process::exit(1);
         }
     }
 }
 
 // Regulates all the processes
 fn run(mut config: AppConfiguration, map_path: PathBuf) -> ResultApp<()>{
+    let started = Local::now();
 
 
+    // The outputs are checked before anything is done, so a wrong format does not waste the whole run.
+    for output in config.get_outputs(){
+        if let config::OutputFormat::Other = output.get_format(){
+            error!("The format of the output {} is unknown. Use an extension like .nt or .ttl, or give it with --format", output.name());
+            return Err(errors::ApplicationErrors::IncorrectFieldType)
+        }
+        if output.get_format().is_store() && (output.path().is_none() || output.get_upload().is_some()){
+            error!("The store {} must be a file", output.name());
+            return Err(errors::ApplicationErrors::IncorrectFieldType)
+        }
+    }
+    check_previous_output(&config)?;
+    check_uploads(&config)?;
+    // The shapes are read before the run, so a mistake in them is found at once.
+    let shapes = config.get_shapes().map(|shapes| shacl::shapes::ShapesGraph::load(shapes)).transpose()?;
+
     eprintln!("\n");
     info!("Starting to Parse all the given mapping files.");
     
@@ -128,28 +372,68 @@ fn run(mut config: AppConfiguration, map_path: PathBuf) -> ResultApp<()>{
     }
     
     let now = Instant::now();
+    let mut mapping_files = get_all_files(map_path.clone())?;
+    mapping_files.sort();
     let mappings = parse_all_mappings(&config, map_path)?;
     time_info("Parsing Mapping Files", now);
 
+    // Maps that are not part of a join are streamed directly from the data files.
+    let (staged, streamed) = plan_pipeline(mappings);
+    info!("{} maps will be staged in the database and {} maps will be streamed from their data files", staged.len(), streamed.len());
+    let in_memory = staged.iter().chain(streamed.iter())
+        .filter(|map| matches!(map.get_source_file_ext(), Ok(AcceptedType::JSON | AcceptedType::XML)))
+        .count();
+    if in_memory > 0{
+        info!("The data files of {} maps are JSON or XML, which are read whole into memory instead of row by row", in_memory);
+    }
+
+    add_all_data_files(&staged, &streamed, &mut config)?;
     let mut data_fields = HashMap::new();
-    add_all_data_files(&mappings, &mut config, &mut data_fields)?;
-    add_all_join_fields(&mappings, &mut data_fields)?;
+    add_all_data_fields(&staged, &mut data_fields)?;
+    add_all_join_fields(&staged, &mut data_fields)?;
         
-    eprintln!("\n");
-    info!("Starting to Read and Store all required data files");
-    let now = Instant::now();
-    let (db, is_file) = input::read_store_data_files(&mut config, data_fields)?; 
-    time_info("Reading and Storing Data Files", now);
+    let (db, is_file) = if data_fields.is_empty(){
+        info!("There are no joins between maps, so the data files will not be stored in a database");
+        (None, false)
+    }else{
+        eprintln!("\n");
+        info!("Starting to Read and Store all required data files");
+        let now = Instant::now();
+        let (db, is_file) = input::read_store_data_files(&mut config, data_fields)?; 
+        time_info("Reading and Storing Data Files", now);
+        (Some(db), is_file)
+    };
 
     config.update_clear_mode(is_file);
     let clear_mode = config.clear_mode();
 
     eprintln!("\n");
     info!("Starting to create the RDF File from Mapping and Data Files");
+    // The metadata describes the run, so what it needs is taken before the configuration is given away.
+    let metadata = config.get_metadata_output().cloned().map(|path| {
+        let mut source_files = config.get_data_files().keys().cloned().collect::<Vec<_>>();
+        source_files.sort();
+        let dumps = config.get_outputs().into_iter()
+            .filter(|output| output.get_upload().is_none() && !output.get_format().is_store() && !output.get_format().is_changes())
+            .filter_map(|output| output.path().cloned())
+            .collect::<Vec<_>>();
+        let run = metadata::RunDescription{
+            started, ended: started, mapping_files, source_files, dumps,
+            config_file: config.get_config_file().cloned(),
+            arguments: std::env::args().collect()
+        };
+        (path, run)
+    });
     let now = Instant::now();
-    materialiser::rdf_procedure(db, mappings, config)?;
+    let statistics = materialiser::rdf_procedure(db, staged, streamed, config, shapes)?;
     time_info("Create RDF File with all Data and Mappings", now);
 
+    if let (Some((path, mut run)), Some(statistics)) = (metadata, statistics){
+        run.ended = Local::now();
+        metadata::write_metadata(&path, statistics, &run)?;
+        info!("The metadata of the run was written to {}", path.display());
+    }
+
     if clear_mode{
         crate::warning!("Database will be removed given that clear flag was provided");
         std::fs::remove_dir_all("./rossete-tmp")?;
@@ -159,6 +443,178 @@ fn run(mut config: AppConfiguration, map_path: PathBuf) -> ResultApp<()>{
 }
 
 
+// The query and serve subcommands are only built with the sparql feature.
+#[cfg(feature = "sparql")]
+fn sparql_subcommands<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b>{
+    app
+        .subcommand(
+            SubCommand::with_name("query")
+            .about("Runs a SPARQL SELECT, CONSTRUCT or ASK query over the store written by a run")
+            .arg(
+                Arg::with_name("store")
+                .long("store")
+                .value_name("FILE")
+                .required(true)
+                .takes_value(true)
+                .help("Store written by a run with --store")
+            )
+            .arg(
+                Arg::with_name("file")
+                .long("file")
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with("QUERY")
+                .help("File with the query")
+            )
+            .arg(
+                Arg::with_name("QUERY")
+                .index(1)
+                .required_unless("file")
+                .help("Text of the query")
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+            .about("Answers SPARQL queries over the mappings from a local HTTP endpoint, without materialising the RDF")
+            .arg(
+                Arg::with_name("mappings")
+                .long("mappings")
+                .required(true)
+                .value_name("MAPPINGS")
+                .takes_value(true)
+                .help("Mappings whose RDF is queried. Values: Folder or a file")
+            )
+            .arg(
+                Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Sets a custom config file to create the main settings of the program")
+            )
+            .arg(
+                Arg::with_name("address")
+                .long("address")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .default_value(DEFAULT_ENDPOINT_ADDRESS)
+                .help("Address where the endpoint listens. The queries are sent to the /sparql path")
+            )
+        )
+}
+
+// The query is given in the command or in a file.
+#[cfg(feature = "sparql")]
+fn run_query(m: &ArgMatches) -> ResultApp<()>{
+    let query = match m.value_of("file"){
+        Some(file) => std::fs::read_to_string(file)?,
+        None => m.value_of("QUERY").unwrap_or_default().to_string()
+    };
+    let store = PathBuf::from(m.value_of("store").unwrap_or_default());
+    sparql::run_query(&store, &query)
+}
+
+// Every map is staged, so the queries are answered with SQL over the rows of its data file.
+#[cfg(feature = "sparql")]
+fn run_endpoint(m: &ArgMatches) -> ResultApp<()>{
+    let mut config = config::get_configuration(&[], m.value_of("config").map(PathBuf::from));
+    let mappings = parse_all_mappings(&config, PathBuf::from(m.value_of("mappings").unwrap_or("mappings")))?;
+    add_all_data_files(&mappings, &[], &mut config)?;
+    let mut data_fields = HashMap::new();
+    add_all_data_fields(&mappings, &mut data_fields)?;
+    add_all_join_fields(&mappings, &mut data_fields)?;
+
+    info!("Starting to Read and Store all required data files");
+    let now = Instant::now();
+    let (db, _) = input::read_store_data_files(&mut config, data_fields)?;
+    time_info("Reading and Storing Data Files", now);
+
+    let graph = std::sync::Arc::new(sparql::rewrite::MappedGraph::new(db, mappings, config.get_iri_encoding())?);
+    sparql::endpoint::serve(
+        |query| sparql::rewrite::MappedGraph::evaluate(&graph, query),
+        m.value_of("address").unwrap_or(DEFAULT_ENDPOINT_ADDRESS)
+    )
+}
+
+// The vocabulary and the shapes are written to their files, or together to the standard output.
+fn run_describe(m: &ArgMatches) -> ResultApp<()>{
+    let config = config::get_configuration(&[], m.value_of("config").map(PathBuf::from));
+    let mappings = parse_all_mappings(&config, PathBuf::from(m.value_of("mappings").unwrap_or("mappings")))?;
+    let description = describe::Description::new(&mappings);
+    let (ontology, shapes) = (m.value_of("ontology"), m.value_of("shapes"));
+    if ontology.is_none() && shapes.is_none(){
+        let stdout = std::io::stdout();
+        let mut out = std::io::BufWriter::new(stdout.lock());
+        description.write_prefixes(&mut out)?;
+        description.write_ontology(&mut out)?;
+        writeln!(out)?;
+        description.write_shapes(&mut out)?;
+        out.flush()?;
+        return Ok(())
+    }
+    if let Some(ontology) = ontology{
+        let mut out = std::io::BufWriter::new(std::fs::File::create(ontology)?);
+        description.write_prefixes(&mut out)?;
+        description.write_ontology(&mut out)?;
+        out.flush()?;
+        info!("The vocabulary of {} maps was written to {}", mappings.len(), ontology);
+    }
+    if let Some(shapes) = shapes{
+        let mut out = std::io::BufWriter::new(std::fs::File::create(shapes)?);
+        description.write_prefixes(&mut out)?;
+        description.write_shapes(&mut out)?;
+        out.flush()?;
+        info!("The shapes of {} maps were written to {}", mappings.len(), shapes);
+    }
+    Ok(())
+}
+
+// The changes need the previous output, and it can not be overwritten before it is read.
+fn check_previous_output(config: &AppConfiguration) -> ResultApp<()>{
+    let outputs = config.get_outputs();
+    let previous = match config.get_previous_output(){
+        Some(previous) => previous,
+        None => {
+            if let Some(output) = outputs.iter().find(|output| output.get_format().is_changes()){
+                error!("The output {} has the changes from a previous output, give it with --previous", output.name());
+                return Err(errors::ApplicationErrors::MissingFilePathInConfiguration)
+            }
+            return Ok(())
+        }
+    };
+    if !previous.is_file(){
+        error!("The previous output {} was not found", previous.display());
+        return Err(errors::ApplicationErrors::FileNotFound)
+    }
+    if !outputs.iter().any(|output| output.get_format().is_changes()){
+        crate::warning!("The previous output {} is not used, there are no outputs with the changes (.rdfp, .ru, --added or --removed)", previous.display());
+    }
+    if config.keep_partial_mode() && outputs.iter().any(|output| output.path() == Some(previous)){
+        error!("The previous output {} would be overwritten before it is read. Do not use --keep-partial", previous.display());
+        return Err(errors::ApplicationErrors::IncorrectFieldType)
+    }
+    Ok(())
+}
+
+// The uploads are checked before the run, because they are only sent when all the RDF has been created.
+fn check_uploads(config: &AppConfiguration) -> ResultApp<()>{
+    let outputs = config.get_outputs();
+    for output in outputs.iter(){
+        let store = match output.get_upload(){
+            Some(store) => store,
+            None => continue
+        };
+        if output.get_format().media_type().is_none(){
+            error!("The output {} can not be uploaded as {:?}. Use --format with nt, ttl, jsonld or rdf", output.name(), output.get_format());
+            return Err(errors::ApplicationErrors::IncorrectFieldType)
+        }
+        store.check_headers()?;
+    }
+    if !outputs.iter().any(|output| output.get_upload().is_some()) && (config.get_upload_graph().is_some() || config.has_upload_headers()){
+        crate::warning!("The options of the Graph Store are not used, there are no outputs with an http(s) URL");
+    }
+    Ok(())
+}
+
 fn parse_all_mappings(config: &AppConfiguration, mapping_folder: PathBuf) -> ResultApp<Vec<Mapping>>{
     // it assumes that all mapping files are encoded in UTF-8;
     let paths = get_all_files(mapping_folder)?;
@@ -235,20 +691,39 @@ fn get_all_files(mapping_paths: PathBuf) -> ResultApp<Vec<PathBuf>>{
     Ok(map_files)
 }
 
-// Add all the data files to the configuration and retrieves all the data fields that need to be accessed
-fn add_all_data_files(mappings: &Vec<Mapping>, config: &mut AppConfiguration, fields: &mut HashMap<PathBuf, HashSet<String>>) -> ResultApp<()>{
-    let mut tmp_files = Vec::with_capacity(mappings.len());
-    for map in mappings.iter() {
+// Divides the maps between the ones that take part in a join (staged in the database) and the ones
+// that can be streamed from the data file straight into the output.
+fn plan_pipeline(mappings: Vec<Mapping>) -> (Vec<Mapping>, Vec<Mapping>){
+    let referenced = mappings.iter()
+        .flat_map(|map| map.get_parent_maps())
+        .collect::<HashSet<_>>();
+
+    mappings.into_iter()
+    .partition(|map| !map.get_parent_maps().is_empty() || referenced.contains(map.get_identifier()))
+}
+
+// Add all the data files to the configuration
+fn add_all_data_files(staged: &[Mapping], streamed: &[Mapping], config: &mut AppConfiguration) -> ResultApp<()>{
+    let mut tmp_files = Vec::with_capacity(staged.len() + streamed.len());
+    for map in staged.iter().chain(streamed.iter()) {
         let data_file = map.source_file()?;
         let file_type = map.get_source_file_ext()?;
         tmp_files.push((data_file.clone(), file_type));
-        let field = fields.entry(data_file.clone()).or_insert(HashSet::new());
-        field.extend(map.get_all_desired_fields()?);
     }
     config.remove_unused_files(tmp_files);
     Ok(())
 }
 
+// Retrieves all the data fields that need to be stored in the database
+fn add_all_data_fields(mappings: &[Mapping], fields: &mut HashMap<PathBuf, HashSet<String>>) -> ResultApp<()>{
+    for map in mappings.iter() {
+        let data_file = map.source_file()?;
+        let field = fields.entry(data_file.clone()).or_default();
+        field.extend(map.get_all_desired_fields()?);
+    }
+    Ok(())
+}
+
 fn add_all_join_fields(mappings: &Vec<Mapping>, fields: &mut HashMap<PathBuf, HashSet<String>>) -> ResultApp<()>{
     
     let map_path = mappings.iter().map(|map|{
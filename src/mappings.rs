@@ -2,6 +2,7 @@
 // ShortCuts
 pub mod maps;
 pub mod parts;
+pub mod shapes;
 mod others;
 
-pub use others::AcceptedType;
\ No newline at end of file
+pub use others::{AcceptedType, KeyNormalisation};
\ No newline at end of file
@@ -146,7 +146,7 @@ impl Mapping{
                                 return Err(ApplicationErrors::IncorrectMappingFormat)
                             }
                         }
-                        else if let parts::Parts::JoinCondition(_, parent) = obj{
+                        else if let parts::Parts::JoinCondition(_, parent, _) = obj{
                             fields.insert(parent.clone());
                         }
                     }
@@ -160,6 +160,26 @@ impl Mapping{
         Ok(cons)
     }
 
+    // Returns the identifiers of the maps used in a rr:parentTriplesMap
+    pub fn get_parent_maps(&self) -> Vec<String>{
+        self.get_predicates().iter()
+        .filter(|pre| pre.is_parent())
+        .flat_map(|pre| {
+            match pre{
+                parts::Parts::PredicateObjectMap{object_map, ..} => {
+                    object_map.iter().filter_map(|obj| {
+                        match obj{
+                            parts::Parts::ParentMap(other) => Some(other.clone()),
+                            _ => None
+                        }
+                    }).collect::<Vec<_>>()
+                }
+                _ => Vec::new()
+            }
+        })
+        .collect()
+    }
+
     pub fn get_identifier(&self) -> &String{
         &self.identificador
     }
@@ -1,4 +1,7 @@
+use crate::config::Compression;
+
 use std::fmt;
+use std::path::Path;
 
 // This structures are only an identifier, they should be able to be copied
 #[derive(Clone, Copy)]
@@ -36,6 +39,18 @@ impl fmt::Debug for AcceptedType{
 }
 
 impl AcceptedType{
+    // Type given by the extension of the file. The compressed files use the extension before the compression one.
+    pub fn from_path(path: &Path) -> Self{
+        let path = match Compression::from_path(path){
+            Some(_) => path.with_extension(""),
+            None => path.to_path_buf()
+        };
+        match path.extension(){
+            Some(ext) => Self::from_str(&ext.to_string_lossy().to_lowercase()),
+            None => Self::Other
+        }
+    }
+
     pub fn from_str(file: &str) -> Self{
         if file.contains("csv"){
             AcceptedType::CSV
@@ -77,4 +92,75 @@ impl AcceptedType{
         }
 
     }
-}
\ No newline at end of file
+}
+// Normalisation applied to both keys of a rr:joinCondition before comparing them.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct KeyNormalisation{
+    pub trim: bool,
+    pub lowercase: bool,
+    pub numeric: bool
+}
+
+impl fmt::Debug for KeyNormalisation{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Vec::with_capacity(3);
+        if self.trim{
+            names.push("trim");
+        }
+        if self.lowercase{
+            names.push("lowercase");
+        }
+        if self.numeric{
+            names.push("numeric");
+        }
+        write!(f, "{}", names.join(","))
+    }
+}
+
+impl KeyNormalisation{
+    // Adds the normalisation given by its name. Returns false if the name is unknown.
+    pub fn add(&mut self, name: &str) -> bool{
+        match name.trim().to_lowercase().as_str(){
+            "trim" => self.trim = true,
+            "lowercase" | "casefold" | "case-fold" => self.lowercase = true,
+            "numeric" | "number" => self.numeric = true,
+            _ => return false
+        }
+        true
+    }
+
+    pub fn is_empty(&self) -> bool{
+        !(self.trim || self.lowercase || self.numeric)
+    }
+
+    // SQL expression that applies the normalisation to the given column.
+    pub fn sql_expression(&self, column: &str) -> String{
+        let mut expression = column.to_string();
+        if self.trim{
+            expression = format!("TRIM({})", expression);
+        }
+        if self.lowercase{
+            expression = format!("LOWER({})", expression);
+        }
+        if self.numeric{
+            // Only the values that are a whole number are casted. CAST keeps the number at the start of the text, so
+            // dates or phone numbers would be equal to their first digits, and text keys would all be equal to 0.
+            let value = format!("TRIM({})", expression);
+            let number = [
+                // A sign, digits and a decimal point, or a decimal point and digits
+                "({0} GLOB '[0-9]*' OR {0} GLOB '.[0-9]*' OR {0} GLOB '[+-][0-9]*' OR {0} GLOB '[+-].[0-9]*')",
+                "NOT {0} GLOB '*[^0-9.eE+-]*'",
+                // Only one decimal point and one exponent, with a sign only at the start of each one
+                "NOT {0} GLOB '*.*.*'",
+                "NOT {0} GLOB '*[eE]*[eE]*'",
+                "NOT {0} GLOB '*[eE]*.*'",
+                "NOT {0} GLOB '*[^eE][+-]*'",
+                // The exponent has digits
+                "NOT {0} GLOB '*[eE]'",
+                "NOT {0} GLOB '*[eE][+-]'"
+            ].iter().map(|condition| condition.replace("{0}", &value)).collect::<Vec<_>>();
+            expression = format!("(CASE WHEN {} THEN CAST({} AS REAL) ELSE {} END)", number.join(" AND "), value, expression);
+        }
+        expression
+    }
+}
@@ -1,5 +1,5 @@
 
-use super::AcceptedType;
+use super::{AcceptedType, KeyNormalisation};
 
 #[derive(Clone)]
 pub enum Parts{
@@ -21,7 +21,7 @@ pub enum Parts{
 
     // rr:parentTriplesMap
     ParentMap(String),
-    JoinCondition(String, String),
+    JoinCondition(String, String, KeyNormalisation),
 
     // rr:graphMaps
     GraphMap(Box<Self>),
@@ -34,6 +34,8 @@ pub enum Parts{
     ConstantString(String), // rr:constant "España"
     // rr:dataType
     DataType(String),
+    // rr:language
+    Language(String),
     // rr:termType
     TermType(String),
     // rr:template
@@ -77,10 +79,13 @@ impl std::fmt::Debug for Parts{
             Self::ParentMap(other_map) => {
                 writeln!(f, "rr:parentTriplesMap <#{}>;", other_map)
             }
-            Self::JoinCondition(child, parent) => {
+            Self::JoinCondition(child, parent, normalisation) => {
                 writeln!(f, "rr:joinCondition [")?;
                 writeln!(f, "\t\t\t\trr:child \"{}\";", child)?;
                 writeln!(f, "\t\t\t\trr:parent \"{}\";", parent)?;
+                if !normalisation.is_empty(){
+                    writeln!(f, "\t\t\t\trossete:normalise \"{:?}\";", normalisation)?;
+                }
                 writeln!(f, "\t\t\t]")
             }
 
@@ -106,6 +111,9 @@ impl std::fmt::Debug for Parts{
             Self::DataType(data) => {
                 write!(f, "rr:dataType {}", data)
             }
+            Self::Language(data) => {
+                write!(f, "rr:language \"{}\"", data)
+            }
             Self::Template{template, input_fields} => {
                 write!(f, "rr:template \"{}\"", add_input_field(template, input_fields))
             }
@@ -114,18 +122,6 @@ impl std::fmt::Debug for Parts{
 }
 
 impl Parts{
-    pub fn get_template(&self) -> Option<&Parts>{
-        match self{
-            Self::SubjectMap{components} => {
-                components.iter()
-                .filter(|f| if let Parts::Template{template: _, input_fields: _} = f {true} else {false})
-                .nth(0)
-            },
-            temp @ Self::Template{..} => Some(temp),
-            _ => None
-        }
-    }
-
     pub fn get_fields(&self) -> std::collections::HashSet<String>{
         let mut fields = std::collections::HashSet::new();
         match self{
@@ -141,7 +137,7 @@ impl Parts{
                 }
             },
             Parts::ParentMap(_) => {},
-            Parts::JoinCondition(child, _) => {
+            Parts::JoinCondition(child, ..) => {
                 fields.insert(child.clone());
             },
             Parts::GraphMap(other) => {
@@ -154,6 +150,7 @@ impl Parts{
             Parts::ConstantTerm(_) => {},
             Parts::ConstantString(_) => {},
             Parts::DataType(_) => {},
+            Parts::Language(_) => {},
             Parts::TermType(_) => {},
             Parts::Template { template: _, input_fields } => {
                 fields.extend(input_fields.iter().map(|data| data.clone()));
@@ -0,0 +1,134 @@
+// Kinds and shapes of the terms that the term maps generate, found from the mappings without reading the data files.
+use crate::materialiser;
+use crate::rdf::Term;
+use super::maps::Mapping;
+use super::parts::Parts;
+
+// Kind of the terms generated by a term map
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind{
+    Iri,
+    BlankNode,
+    Literal{
+        datatype: Option<String>,
+        language: Option<String>
+    }
+}
+
+// What is known of the terms of a term map before reading the rows, used to discard the maps that can not match a
+// pattern and to join the tables by their columns. The columns are only read by the SPARQL rewriting.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "sparql"), allow(dead_code))]
+pub enum Shape{
+    Constant(Term),
+    // The text between the fields of the template and the columns of the fields
+    Template{
+        kind: Kind,
+        segments: Vec<String>,
+        columns: Vec<String>
+    },
+    Reference{
+        kind: Kind,
+        column: String
+    },
+    // Blank nodes identified by their row
+    Row
+}
+
+// Kind of the subjects of a map, and of the objects of the maps that reference it.
+pub fn subject_kind(map: &Mapping, warn: &mut bool) -> Option<Kind>{
+    subject_shape(map.get_subject(), map, "", warn).map(|shape| match shape{
+        Shape::Constant(term) => term_kind(&term),
+        Shape::Template{kind, ..} | Shape::Reference{kind, ..} => kind,
+        Shape::Row => Kind::BlankNode
+    })
+}
+
+// Kind of the objects of an object map, with the term if it is a constant. None if it does not generate objects.
+pub fn object_kind(objects: &[Parts], map: &Mapping, warn: &mut bool) -> Option<(Kind, Option<Term>)>{
+    match object_shape(objects, map, "", warn){
+        Shape::Constant(term) => Some((term_kind(&term), Some(term))),
+        Shape::Template{kind, ..} | Shape::Reference{kind, ..} => Some((kind, None)),
+        Shape::Row => None
+    }
+}
+
+// Shape of the subjects generated by materialiser::generate_subject.
+pub fn subject_shape(subject: &Parts, map: &Mapping, iterator: &str, warn: &mut bool) -> Option<Shape>{
+    let components = match subject{
+        Parts::SubjectMap{components} => components,
+        _ => return None
+    };
+    let blank = components.iter().any(|comp| matches!(comp, Parts::TermType(kind) if kind.contains("BlankNode")));
+    let kind = if blank { Kind::BlankNode } else { Kind::Iri };
+    for comp in components.iter(){
+        return Some(match comp{
+            Parts::Template{template, input_fields} => template_shape(kind, template, input_fields, iterator),
+            Parts::Reference(field) => Shape::Reference{kind, column: materialiser::column_with_iterator(iterator, field)},
+            Parts::ConstantTerm(term) => Shape::Constant(Term::Iri(materialiser::expand_iri(term, map, warn))),
+            Parts::ConstantString(term) => Shape::Constant(Term::Iri(term.clone())),
+            _ => continue
+        })
+    }
+    if blank { Some(Shape::Row) } else { None }
+}
+
+// Shape of the objects generated by materialiser::term_from_object.
+pub fn object_shape(objects: &[Parts], map: &Mapping, iterator: &str, warn: &mut bool) -> Shape{
+    let mut term_type = None;
+    let mut datatype = None;
+    let mut language = None;
+    let mut value = None;
+    let mut template = None;
+    for element in objects.iter(){
+        match element{
+            Parts::Template{..} => template = Some(element),
+            Parts::Reference(_) | Parts::ConstantString(_) => value = Some(element),
+            Parts::DataType(type_data) => datatype = Some(materialiser::expand_iri(type_data, map, warn)),
+            Parts::Language(tag) => language = Some(tag.clone()),
+            Parts::TermType(type_term) => term_type = Some(type_term),
+            Parts::ConstantTerm(term) => return Shape::Constant(Term::Iri(materialiser::expand_iri(term, map, warn))),
+            _ => {}
+        }
+    }
+
+    // The templates are used before the references and the constants
+    let is_template = template.is_some();
+    let value = template.or(value);
+    let kind = match term_type{
+        Some(kind) if kind.contains("BlankNode") => Kind::BlankNode,
+        Some(kind) if kind.contains("IRI") => Kind::Iri,
+        Some(kind) if kind.contains("Literal") => Kind::Literal{datatype: datatype.filter(|_| language.is_none()), language},
+        _ if is_template => Kind::Iri,
+        _ => Kind::Literal{datatype: datatype.filter(|_| language.is_none()), language}
+    };
+    match value{
+        Some(Parts::Template{template, input_fields}) => template_shape(kind, template, input_fields, iterator),
+        Some(Parts::Reference(field)) => Shape::Reference{kind, column: materialiser::column_with_iterator(iterator, field)},
+        Some(Parts::ConstantString(value)) => Shape::Constant(match kind{
+            Kind::Iri => Term::Iri(value.clone()),
+            Kind::BlankNode => Term::BlankNode(value.clone()),
+            Kind::Literal{language: Some(language), ..} => Term::LangLiteral{value: value.clone(), language},
+            Kind::Literal{datatype, ..} => Term::Literal{value: value.clone(), datatype}
+        }),
+        _ => Shape::Row
+    }
+}
+
+// The fields of the templates are written as {} and their names are kept apart.
+pub fn template_shape(kind: Kind, template: &str, fields: &[String], iterator: &str) -> Shape{
+    Shape::Template{
+        kind,
+        segments: template.split("{}").map(|segment| segment.to_string()).collect(),
+        columns: fields.iter().map(|field| materialiser::column_with_iterator(iterator, field)).collect()
+    }
+}
+
+pub fn term_kind(term: &Term) -> Kind{
+    match term{
+        Term::Iri(_) => Kind::Iri,
+        Term::BlankNode(_) => Kind::BlankNode,
+        Term::Literal{datatype, ..} => Kind::Literal{datatype: datatype.clone(), language: None},
+        Term::LangLiteral{language, ..} => Kind::Literal{datatype: None, language: Some(language.clone())}
+    }
+}
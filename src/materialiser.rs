@@ -3,80 +3,281 @@ use crate::ResultApp;
 use crate::errors::ApplicationErrors;
 use crate::mappings::{
     maps::Mapping,
-    parts::Parts
+    parts::Parts,
+    KeyNormalisation
 };
-use crate::config;
+use crate::config::{self, IriEncoding};
+use crate::input;
+use crate::output;
+use crate::shacl;
+use crate::metadata::DatasetStatistics;
+use crate::rdf::{self, Term, Triple};
 use crate::{warning, error, info}; // Debug and Message Print
 
 use std::io::Write;
-use std::sync::{Arc, Mutex, mpsc};
+use std::sync::{Arc, mpsc};
 use std::thread;
-use std::fs;
 use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
-pub fn rdf_procedure(db: rusqlite::Connection, mappings: Vec<Mapping>, config: config::AppConfiguration) -> ResultApp<()>{
+// Number of rows of the database that are read at once by each writer thread.
+const PAGE_ROWS: i64 = 10_000;
 
-    let db = Arc::new(Mutex::new(db)); // This will make safe to use it between threads.
+// Minimum number of rows of each partition of a staged map.
+const MIN_PARTITION_ROWS: i64 = 50_000;
 
-    let (file_tx, file_rx) = mpsc::channel();
+// Graph map constant of the default graph.
+const DEFAULT_GRAPH: &str = "http://www.w3.org/ns/r2rml#defaultGraph";
+
+// Range of col_id of the rows of a table (both included).
+type RowRange = (i64, i64);
+
+// Part of the work of a map that is done by a single writer thread.
+struct Job{
+    map: usize,
+    // Only the staged maps are divided in ranges of rows.
+    rows: Option<RowRange>
+}
+
+pub fn rdf_procedure(db: Option<input::StagedData>, mut staged: Vec<Mapping>, mut streamed: Vec<Mapping>, config: config::AppConfiguration, shapes: Option<shacl::shapes::ShapesGraph>) -> ResultApp<Option<DatasetStatistics>>{
+
+    // The maps are parsed in parallel, so their order is only stable if they are sorted.
+    if config.deterministic_mode(){
+        staged.sort_by(|a, b| a.get_identifier().cmp(b.get_identifier()));
+        streamed.sort_by(|a, b| a.get_identifier().cmp(b.get_identifier()));
+    }
+
+    // Only the staged maps can be the parent of a join.
+    let tables = staged.iter()
+        .map(|map| (map.get_identifier().clone(), map.clone()))
+        .collect::<HashMap<_, _>>();
+
+    // The staged maps go first, so the index of a map is its position in this list.
+    let num_staged = staged.len();
+    let mappings = staged.into_iter().chain(streamed).collect::<Vec<_>>();
+    let map_names = mappings.iter().map(|map| map.get_identifier().clone()).collect::<Vec<_>>();
+
+    // All the prefixes of the mappings are available for the output. If two mappings use the same prefix, the first one is kept.
+    let mut prefixes = HashMap::new();
+    for map in mappings.iter(){
+        for (name, url) in map.get_prefixes().iter(){
+            prefixes.entry(name.clone()).or_insert_with(|| url.clone());
+        }
+    }
+
+    let mut failed_maps = Vec::new();
+    let jobs = plan_jobs(db.as_ref(), &mappings, num_staged, &tables, config.get_writing_theads(), &mut failed_maps);
+
+    // The same triples are written to every output.
+    let outputs = config.get_outputs().into_iter()
+        .map(|target| output::Output::new(target, config.keep_partial_mode()))
+        .collect::<Vec<_>>();
+
+    let (file_tx, file_rx) = triple_channel(config.deterministic_mode());
     let config_arc = Arc::new(config);
     let config_arc2 = Arc::clone(&config_arc);
-    let num_maps = mappings.len(); 
+    let num_jobs = jobs.len();
+    let outputs2 = outputs.clone();
     let file_thread = thread::spawn( move || {
-        write_file(config_arc2, file_rx, num_maps)
+        write_file(config_arc2, file_rx, num_jobs, prefixes, outputs2, shapes.map(|shapes| (shapes, map_names)))
     });
 
     std::thread::sleep(std::time::Duration::from_micros(10)); // Wait a blip
 
-    create_rdf(file_tx, Arc::clone(&db), mappings, config_arc)?;
-    file_thread.join()?
+    let location = db.as_ref().map(|db| db.location());
+    let result = create_rdf(file_tx, location, mappings, jobs, Arc::new(tables), config_arc, failed_maps);
+    let (result, statistics) = match file_thread.join()?{
+        Ok(statistics) => (result, statistics),
+        Err(e) => (Err(e), None)
+    };
+    drop(db); // The in-memory database is kept until all the readers have finished.
+
+    // Every output is closed, even if an upload fails, so no temporary file is left behind.
+    let mut closed = Ok(());
+    for output in outputs.iter(){
+        closed = closed.and(output.close(result.is_ok()));
+    }
+    result.and(closed).map(|_| statistics)
+}
+
+// Divides the work of each map between the writer threads. The staged maps are split in ranges of rows, so a single
+// large map can use all the threads. The indexes are created here, because the writer threads can only read the database.
+fn plan_jobs(db: Option<&input::StagedData>, mappings: &[Mapping], num_staged: usize, tables: &HashMap<String, Mapping>, max_threads: usize, failed_maps: &mut Vec<usize>) -> Vec<Job>{
+    let mut jobs = Vec::with_capacity(mappings.len());
+    for (i, map) in mappings.iter().enumerate(){
+        if i >= num_staged{
+            jobs.push(Job{map: i, rows: None});
+            continue
+        }
+        let db = match db{
+            Some(db) => &db.connection,
+            None => {
+                error!("The map {} requieres the database but it was not created", map.get_identifier());
+                failed_maps.push(i);
+                continue
+            }
+        };
+        match prepare_staged_map(db, map, tables, max_threads){
+            Ok(ranges) => jobs.extend(ranges.into_iter().map(|rows| Job{map: i, rows: Some(rows)})),
+            Err(error) => {
+                error!("Failed to create RDF using the following mappings: {:<20} Error Code: {:?}", map.get_identifier(), error);
+                failed_maps.push(i);
+            }
+        }
+    }
+    jobs
+}
+
+// Creates the indexes used by a staged map and returns the ranges of rows in which it is divided.
+fn prepare_staged_map(db: &rusqlite::Connection, map: &Mapping, tables: &HashMap<String, Mapping>, max_threads: usize) -> ResultApp<Vec<RowRange>>{
+    let table_name = map.get_table_name()?;
+    index_staged_map(db, map, tables)?;
+
+    let bounds: (Option<i64>, Option<i64>) = db.query_row(
+        &format!("SELECT MIN(col_id), MAX(col_id) FROM {};", table_name), [],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    )?;
+    match bounds{
+        (Some(first), Some(last)) => Ok(partition_rows(first, last, max_threads)),
+        _ => {
+            warning!("The table {} of the map {} has no rows", table_name, map.get_identifier());
+            Ok(Vec::new())
+        }
+    }
+}
+
+// Creates the indexes of the main columns and the join conditions of a staged map.
+pub fn index_staged_map(db: &rusqlite::Connection, map: &Mapping, tables: &HashMap<String, Mapping>) -> ResultApp<()>{
+    let table_name = map.get_table_name()?;
+    create_main_index(db, &table_name, &main_columns(map)?)?;
+    for plan in plan_joins(map, &table_name, tables)?.values(){
+        create_join_indexes(db, &table_name, &plan.other_table, &plan.conditions)?;
+    }
+    Ok(())
+}
+
+// Splits the range of col_id in contiguous ranges of similar size. Small tables are not split.
+fn partition_rows(first: i64, last: i64, max_partitions: usize) -> Vec<RowRange>{
+    let total = last - first + 1;
+    let partitions = (total / MIN_PARTITION_ROWS).clamp(1, max_partitions.max(1) as i64);
+    let size = (total + partitions - 1) / partitions;
+    (0..partitions)
+        .map(|p| (first + p * size, (first + (p + 1) * size - 1).min(last)))
+        .collect()
+}
+
+// Where the writer threads send their triples. In the deterministic mode, every job has its own channel and the
+// writer reads them in the order of the jobs, so the output does not depend on which thread is faster. The triples
+// of the jobs that are ahead are kept in their channels until it is their turn. Each group of triples goes with the
+// index of its map, so the validation can tell which map has created them.
+type Batch = (usize, Vec<Triple>);
+
+enum TripleSender{
+    Shared(mpsc::Sender<Batch>),
+    Ordered(mpsc::Sender<mpsc::Receiver<Batch>>)
+}
+
+enum TripleReceiver{
+    Shared(mpsc::Receiver<Batch>),
+    Ordered{
+        jobs: mpsc::Receiver<mpsc::Receiver<Batch>>,
+        current: Option<mpsc::Receiver<Batch>>
+    }
+}
+
+// Sender of the triples of a single job.
+struct JobSender{
+    map: usize,
+    sender: mpsc::Sender<Batch>
 }
 
-fn create_rdf(file_con: mpsc::Sender<Vec<u8>>, db: Arc<Mutex<rusqlite::Connection>>, mappings: Vec<Mapping>, config: Arc<config::AppConfiguration>) -> ResultApp<()>{
+fn triple_channel(ordered: bool) -> (TripleSender, TripleReceiver){
+    if ordered{
+        let (tx, rx) = mpsc::channel();
+        (TripleSender::Ordered(tx), TripleReceiver::Ordered{jobs: rx, current: None})
+    }else{
+        let (tx, rx) = mpsc::channel();
+        (TripleSender::Shared(tx), TripleReceiver::Shared(rx))
+    }
+}
+
+impl TripleSender{
+    // Sender of the next job. The jobs must ask for it in their order.
+    fn job_sender(&self, map: usize) -> ResultApp<JobSender>{
+        let sender = match self{
+            Self::Shared(sender) => sender.clone(),
+            Self::Ordered(jobs) => {
+                let (tx, rx) = mpsc::channel();
+                jobs.send(rx)?;
+                tx
+            }
+        };
+        Ok(JobSender{map, sender})
+    }
+}
+
+impl JobSender{
+    fn send(&self, triples: Vec<Triple>) -> ResultApp<()>{
+        Ok(self.sender.send((self.map, triples))?)
+    }
+}
+
+impl TripleReceiver{
+    // Next group of triples and the index of their map. An empty group is the end of a job.
+    fn recv(&mut self) -> ResultApp<Batch>{
+        match self{
+            Self::Shared(receiver) => Ok(receiver.recv()?),
+            Self::Ordered{jobs, current} => {
+                if current.is_none(){
+                    *current = Some(jobs.recv()?);
+                }
+                let batch = current.as_ref().map(|job| job.recv()).transpose()?.unwrap_or_default();
+                if batch.1.is_empty(){
+                    *current = None;
+                }
+                Ok(batch)
+            }
+        }
+    }
+}
+
+fn create_rdf(file_con: TripleSender, location: Option<&'static str>, mappings: Vec<Mapping>, jobs: Vec<Job>, tables: Arc<HashMap<String, Mapping>>, config: Arc<config::AppConfiguration>, mut failed_maps: Vec<usize>) -> ResultApp<()>{
 
     let max_threads = config.get_writing_theads();
     let mut threads: Vec<thread::JoinHandle<Result<(), ApplicationErrors>>> = Vec::with_capacity(max_threads);
     let mut threads_id: Vec<usize> = Vec::with_capacity(max_threads); // It allow us to find the handler of the finished thread
 
     let (rc_tx, rc_rx) = mpsc::channel::<usize>(); // Indicates which thread has finished to remove it and check if it failed.
-    let mut failed_maps: Vec<&str> = Vec::new();
-    
-    let tables = mappings.iter().map(|map| {
-        let table = map.get_table_name().unwrap();
-        let iterador = map.get_iterator().unwrap();
-        let template = map.get_subject().get_template().unwrap().clone(); 
-        (map.get_identifier().clone(),  (table, iterador, template))
-    }).collect::<HashMap<_, _>>();
-
-    let tables = Arc::new(tables);
-
-    let mut current_map = 0;
-    loop{
+
+    let mut current_job = 0;
+    while current_job < jobs.len() || !threads.is_empty(){
         // Thread Initialization
-        if threads.len() < max_threads && current_map < mappings.len(){
+        if threads.len() < max_threads && current_job < jobs.len(){
             let rc = rc_tx.clone();
-            let rdf_map = mappings[current_map].clone();
-            let output_format = config.get_output_format().clone();
-            let id = current_map;
-            let db_c = Arc::clone(&db);
-            let write = file_con.clone();
+            let job = &jobs[current_job];
+            let rdf_map = mappings[job.map].clone();
+            let rows = job.rows;
+            let id = current_job;
+            let write = file_con.job_sender(job.map)?;
             let table_co = Arc::clone(&tables);
+            let config_c = Arc::clone(&config);
             let handler = thread::spawn(move || -> ResultApp<()>{
-                if output_format.is_nt(){
-                    create_rdf_nt(id, rdf_map, rc, db_c, write, table_co)
-                }else if output_format.is_ttl(){
-                    create_rdf_ttl(id, rdf_map, rc, db_c, write, table_co)
+                let result = if let Some(rows) = rows{
+                    create_rdf_staged(&rdf_map, location, rows, &config_c, &write, table_co)
                 }else{
-                    warning!("The output format of the result cann't be processed. FORMAT: {:?}", &output_format);
-                    rc.send(current_map)?;
-                    write.send(Vec::new())?;
-                    Ok(())
-                }
+                    create_rdf_streamed(&rdf_map, &config_c, &write)
+                };
+
+                // Close and Finish Signal
+                write.send(Vec::new())?;
+                rc.send(id)?;
+                result
             });
 
             threads.push(handler);
-            threads_id.push(current_map);
-            current_map += 1;
+            threads_id.push(current_job);
+            current_job += 1;
         }else{
             let rc = rc_rx.recv()?;
             let thread_id = threads_id.iter().position(|x| x == &rc).expect("Thread ID was not found");
@@ -84,300 +285,433 @@ fn create_rdf(file_con: mpsc::Sender<Vec<u8>>, db: Arc<Mutex<rusqlite::Connectio
             match threads.remove(thread_id).join()?{
                 Ok(_) => {},
                 Err(error) => {
-                    let map_name = &mappings[thread_id].get_identifier();
-                    error!("Failed to create RDF using the following mappings: {:<20} Error Code: {:?}", map_name, error);
-                    failed_maps.push(map_name);
+                    let map = jobs[rc].map;
+                    error!("Failed to create RDF using the following mappings: {:<20} Error Code: {:?}", mappings[map].get_identifier(), error);
+                    if !failed_maps.contains(&map){
+                        failed_maps.push(map);
+                    }
                 },
             }
             threads_id.remove(thread_id);
-
-        }
-        if threads.len() == 0 && current_map >= mappings.len(){
-            break
         }
     }
 
-    drop(tables);
     if !failed_maps.is_empty(){
         warning!("The Program has failed to create rdf from the following maps: ");
         for fail in failed_maps.iter(){
-            warning!("\t+ {}", fail);
+            warning!("\t+ {}", mappings[*fail].get_identifier());
         }
-        return Err(ApplicationErrors::FAiledToCreateRDF)
+        Err(ApplicationErrors::FAiledToCreateRDF)
     }else{
         Ok(())
     }
 
 }
 
-fn write_file(config: Arc<config::AppConfiguration>, rdf_rx: mpsc::Receiver<Vec<u8>>, mut num_maps: usize) -> ResultApp<()>{
-    // create a file or if it exist, we remove it first and then we create it again
-    let output_path = config.get_output_path();
-    if output_path.exists(){
-        warning!("It was found a file with the same name as the output file \"{}\", it will be overwritten", output_path.display());
-    }
-    let mut output_file = fs::OpenOptions::new().write(true).truncate(true).create(true).open(output_path)?;
-    loop{
-        let rdf = rdf_rx.recv()?;
-        if rdf.is_empty(){
-            num_maps -= 1;
+fn write_file(config: Arc<config::AppConfiguration>, mut rdf_rx: TripleReceiver, mut num_jobs: usize, prefixes: HashMap<String, String>, outputs: Vec<output::Output>, validation: Option<(shacl::shapes::ShapesGraph, Vec<String>)>) -> ResultApp<Option<DatasetStatistics>>{
+    // The stores are databases that receive the triples with their graphs, instead of a written syntax.
+    let (stores, outputs): (Vec<_>, Vec<_>) = outputs.iter().partition(|output| output.get_format().is_store());
+    let mut stores = stores.iter()
+        .filter_map(|output| output.written_path())
+        .map(|path| rdf::store::TripleStore::create(path))
+        .collect::<ResultApp<Vec<_>>>()?;
+    let mut writers = outputs.iter().map(|output| output.open()).collect::<ResultApp<Vec<_>>>()?;
+
+    // The formats other than N-Triples group the triples of each subject, so they are kept until all the maps have
+    // finished. Only JSON-LD has graphs, so the other formats use a spool without them.
+    let formats = outputs.iter().map(|output| output.get_format()).collect::<Vec<_>>();
+    let mut graph_spool = if formats.iter().any(|format| format.is_jsonld()){
+        Some(rdf::spool::TripleSpool::new()?)
+    }else{
+        None
+    };
+    // With shapes, the N-Triples are also kept until the triples are validated, so nothing is written before it.
+    let held = validation.is_some() && formats.iter().any(|format| format.is_nt());
+    let mut spool = if held || formats.iter().any(|format| !format.is_nt() && !format.is_jsonld() && !format.is_changes()){
+        Some(rdf::spool::TripleSpool::new()?)
+    }else{
+        None
+    };
+    let streamed = !held && formats.iter().any(|format| format.is_nt());
+    // The changes compare the sorted lines of this run with the ones of the previous output.
+    let mut current = if formats.iter().any(|format| format.is_changes()){
+        Some(rdf::runs::ExternalSort::new())
+    }else{
+        None
+    };
+    // The quads of JSON-LD and the triples of the rest of the formats are deduplicated separately.
+    let (mut quads_dedupe, mut triples_dedupe) = if config.dedupe_mode(){
+        (graph_spool.as_ref().map(|_| rdf::dedupe::TripleDedupe::new()), Some(rdf::dedupe::TripleDedupe::new()))
+    }else{
+        (None, None)
+    };
+    // The shapes need all the triples at once, so they are kept in memory with their maps.
+    let mut data_graph = validation.as_ref().map(|_| shacl::graph::DataGraph::new());
+    // The counters of the metadata follow the triples written to the outputs.
+    let mut statistics = config.get_metadata_output().map(|_| DatasetStatistics::new());
+
+    while num_jobs > 0{
+        let (map, triples) = rdf_rx.recv()?;
+        if triples.is_empty(){
+            num_jobs -= 1;
+            continue
+        }
+        if let Some(graph) = data_graph.as_mut(){
+            graph.push(map, &triples);
         }
         // Write Data
-        output_file.write_all(&rdf)?;
-        // Stop Condition
-        if num_maps == 0{
-            break
+        for store in stores.iter_mut(){
+            store.push(&triples)?;
+        }
+        if let Some(spool) = graph_spool.as_mut(){
+            match quads_dedupe.as_mut(){
+                Some(dedupe) => spool.push(&dedupe.filter(triples.clone())?)?,
+                None => spool.push(&triples)?
+            }
+        }
+        // The copies of a triple in several graphs are written once.
+        let triples = without_graphs(triples);
+        let triples = match triples_dedupe.as_mut(){
+            Some(dedupe) => dedupe.filter(triples)?,
+            None => triples
+        };
+        write_triples(&triples, spool.as_mut(), current.as_mut(), statistics.as_mut(), &mut writers, &formats, streamed)?;
+    }
+    // The outputs are not written if the triples do not conform to the shapes.
+    if let (Some((shapes, map_names)), Some(graph)) = (validation.as_ref(), data_graph.take()){
+        info!("Validating the RDF with {} shapes", shapes.shapes.len());
+        shacl::check(shapes, &graph, map_names, config.get_validation_report())?;
+    }
+    // The triples that the deduplication has kept on disk
+    if let (Some(dedupe), Some(spool)) = (quads_dedupe, graph_spool.as_mut()){
+        dedupe.finish(|quads| spool.push(&quads))?;
+    }
+    if let Some(dedupe) = triples_dedupe{
+        dedupe.finish(|triples| write_triples(&triples, spool.as_mut(), current.as_mut(), statistics.as_mut(), &mut writers, &formats, streamed))?;
+    }
+    if let (Some(current), Some(previous)) = (current, config.get_previous_output()){
+        let mut changes = writers.iter_mut().zip(formats.iter())
+            .filter(|(_, format)| format.is_changes())
+            .map(|(writer, format)| rdf::changes::ChangeWriter::new(format, writer))
+            .collect::<Vec<_>>();
+        let previous_lines = rdf::changes::read_previous(input::open_data_file(previous)?)?;
+        let (removed, added) = rdf::changes::write_changes(previous_lines, current.into_sorted()?, &mut changes)?;
+        info!("There are {} removed triples and {} added triples since the previous output {}", removed, added, previous.display());
+    }
+
+    for ((output, mut writer), format) in outputs.iter().zip(writers).zip(formats){
+        if let (true, Some(spool)) = (format.is_jsonld(), graph_spool.as_mut()){
+            rdf::jsonld::JsonLdWriter::new(&prefixes, config.get_jsonld_form()).write(spool, &mut writer)?;
+        }else if let (true, true, Some(spool)) = (format.is_nt(), held, spool.as_mut()){
+            spool.for_each_sorted(false, |triple| {
+                writeln!(writer, "{}", triple.to_ntriples())?;
+                Ok(())
+            })?;
+        }else if let (false, Some(spool)) = (format.is_nt(), spool.as_mut()){
+            if format.is_rdfxml(){
+                rdf::rdfxml::RdfXmlWriter::new(&prefixes).write(spool, &mut writer)?;
+            }else if format.is_hdt(){
+                // The dataset is identified by the base of the mappings or by the output file.
+                let base_uri = match prefixes.get("").filter(|base| !base.is_empty()){
+                    Some(base) => base.clone(),
+                    None => output.file_iri()?.unwrap_or_else(|| "file:///dev/stdout".to_string())
+                };
+                rdf::hdt::HdtWriter::new(&base_uri).write(spool, &mut writer)?;
+            }else if format.is_ttl(){
+                let turtle = rdf::turtle::TurtleWriter::new(&prefixes);
+                turtle.write_header(&mut writer)?;
+                turtle.write(spool, &mut writer)?;
+            }
+        }
+        writer.finish()?;
+    }
+    for store in stores{
+        store.finish()?;
+    }
+    Ok(statistics)
+}
+
+// Writes the triples without graphs: the N-Triples outputs are written at once, the rest are kept in the spool and
+// their lines are kept for the changes.
+fn write_triples(triples: &[Triple], spool: Option<&mut rdf::spool::TripleSpool>, current: Option<&mut rdf::runs::ExternalSort>, statistics: Option<&mut DatasetStatistics>, writers: &mut [output::OutputWriter], formats: &[&config::OutputFormat], streamed: bool) -> ResultApp<()>{
+    if triples.is_empty(){
+        return Ok(())
+    }
+    if let Some(spool) = spool{
+        spool.push(triples)?;
+    }
+    if let Some(current) = current{
+        for triple in triples.iter(){
+            current.push(triple.to_ntriples())?;
+        }
+    }
+    if let Some(statistics) = statistics{
+        statistics.push(triples)?;
+    }
+    if streamed{
+        let text = rdf::to_ntriples(triples);
+        for (writer, format) in writers.iter_mut().zip(formats.iter()){
+            if format.is_nt(){
+                writer.write_all(text.as_bytes())?;
+            }
         }
     }
     Ok(())
 }
 
-fn create_rdf_nt<'a>(id: usize, map: Mapping, rc: mpsc::Sender<usize>, db: Arc<Mutex<rusqlite::Connection>>, write: mpsc::Sender<Vec<u8>>, tables: Arc<HashMap<String, (String, String, Parts)>>) -> ResultApp<()>{
+fn without_graphs(triples: Vec<Triple>) -> Vec<Triple>{
+    if triples.iter().all(|triple| triple.graph.is_none()){
+        return triples
+    }
+    let mut seen = HashSet::with_capacity(triples.len());
+    triples.into_iter()
+        .map(|triple| Triple{graph: None, ..triple})
+        .filter(|triple| seen.insert(triple.clone()))
+        .collect()
+}
 
-    let table_name = map.get_table_name()?;
-    info!("RDF FROM DB TABLE: {:<30} AND MAP: {}", &table_name, map.get_identifier());
-    let main_columns = map.get_all_desired_fields()?;
-    let mut warn = true;
-    let (rows, id_col) = match select_main_columns(&table_name, main_columns, &db){
-        Ok((row, dict)) => (row, dict),
-        Err(error) => {
-            error!("Something Ocurred while selecting the main columns from the table {}.", &table_name);
-            rc.send(id)?;
-            write.send(Vec::new())?;
-            return Err(error)
+// Creates the RDF of a range of rows of a map whose data is stored in the database. It is the only one that can solve joins.
+// Each call uses its own read-only connection, so the ranges of the same map can be processed at the same time.
+fn create_rdf_staged(map: &Mapping, location: Option<&str>, range: RowRange, config: &config::AppConfiguration, write: &JobSender, tables: Arc<HashMap<String, Mapping>>) -> ResultApp<()>{
+    let db = match location{
+        Some(location) => input::open_reader(location)?,
+        None => {
+            error!("The map {} requieres the database but it was not created", map.get_identifier());
+            return Err(ApplicationErrors::CantOpenDatabase)
         }
     };
-   
-    let subject_map = map.get_subject();
-    let (temp, input) = if let Parts::Template{template, input_fields} = subject_map.get_template().unwrap(){
-        (template, input_fields)
-    }else{
-        write.send(Vec::new())?;
-        rc.send(id)?; 
-        return Err(ApplicationErrors::IncorrectMappingFormat)
-    };
-
-    // Add the class definition as new predicate
-    let class_term = add_definition_predicate(subject_map, &map, &mut warn);
-
-    let predicates = map.get_predicates();
 
-    let join_stmts = generete_join_statements(&map, &predicates, &table_name, &tables);
-
-    let mut buffer = String::with_capacity(1000);
-    for val in rows.iter(){
-        // Getting the subject
-        let url = get_subject(temp, val, input, &id_col);
-        if url.is_empty(){
-            continue
-        }
-        
-        if let Some(class) = &class_term{
-            buffer.extend(url.chars());
-            buffer.extend(class.chars());
-            buffer.push_str(".\n");
+    let table_name = map.get_table_name()?;
+    info!("RDF FROM DB TABLE: {:<30} AND MAP: {} ROWS: {} - {}", &table_name, map.get_identifier(), range.0, range.1);
+    let main_columns = main_columns(map)?;
+    let id_col = column_positions(&main_columns);
+    let plans = plan_joins(map, &table_name, &tables)?;
+    let mut renderer = RowRenderer::new(map, config)?;
+
+    // The rows are read by pages, so only a page and its joined rows are kept in memory.
+    let mut first = range.0;
+    while first <= range.1{
+        let (rows, last) = match select_page(&table_name, &main_columns, (first, range.1), &db){
+            Ok(page) => page,
+            Err(error) => {
+                error!("Something Ocurred while selecting the main columns from the table {}.", &table_name);
+                return Err(error)
+            }
+        };
+        let last = match last{
+            Some(last) => last,
+            None => break // There are no more rows in the range
+        };
+
+        let joins = prepare_joins(&plans, &table_name, (first, last), &db)?;
+        for val in rows.iter(){
+            let triples = renderer.render(val, &id_col, Some(&joins))?;
+            if !triples.is_empty(){
+                write.send(triples)?;
+            }
         }
+        first = last + 1;
+    }
+    Ok(())
+}
 
-        for (i, &pre) in predicates.iter().enumerate(){
-            buffer.extend(url.chars());
+// Creates the RDF of a map reading its data file row by row, without storing it in the database.
+fn create_rdf_streamed(map: &Mapping, config: &config::AppConfiguration, write: &JobSender) -> ResultApp<()>{
+    let source = map.source_file()?;
+    info!("RDF STREAMED FROM FILE: {:<30} AND MAP: {}", source.display(), map.get_identifier());
+    let specs = match config.get_data_files().get(source){
+        Some(specs) => specs,
+        None => {
+            error!("The data file {} was not registered in the configuration", source.display());
+            return Err(ApplicationErrors::FileNotFound)
+        }
+    };
 
-            let term;
-            if pre.is_parent(){
-                term = term_from_join_object(&map, pre, Arc::clone(&db), val, &id_col, &join_stmts[&i], &mut warn);
+    // The fields are sorted, so the hash of the rows, used by the blank nodes, is the same in every run.
+    let fields = main_columns(map)?;
+    let id_col = fields.iter()
+        .map(|key| {
+            if key.contains("||"){
+                key.split("||").nth(1).unwrap().to_string()
             }else{
-                term = rdf_term(&map, pre, val,&id_col, &mut warn);
+                key.clone()
             }
-            let term = match term {
-                Ok(t) => {
-                    if t.is_empty(){ // Remove if data is empty
-                        continue
-                    }else{
-                        t
-                    }
-                },
-                Err(error) => {
-                    write.send(Vec::new())?;    
-                    rc.send(id)?;
-                    return Err(error)
-                }
-            };
-            buffer.extend(term.chars());
-            buffer.push_str(".\n");
+        })
+        .enumerate()
+        .map(|(i, key)| (key, i))
+        .collect::<HashMap<_, _>>();
+
+    // As the staged maps skip their repeated rows, the triples that the map has given recently are not given again.
+    let mut recent = rdf::dedupe::RecentTriples::new();
+    let mut renderer = RowRenderer::new(map, config)?;
+    input::stream_data_file(source, specs, &fields, |row| {
+        let triples = recent.filter(renderer.render(&row, &id_col, None)?);
+        if !triples.is_empty(){
+            write.send(triples)?;
         }
-        buffer.push_str("\n\n");            
-
-        write.send(buffer.bytes().collect())?;
-        buffer.clear();
-    }
-
-    // Close and Finish Signal
-    write.send(Vec::new())?;
-    rc.send(id)?; 
-    Ok(())
+        Ok(())
+    })
 }
 
+// How a referencing object map is solved: the parent map and the columns of both tables that are joined.
+pub struct JoinPlan<'a>{
+    pub parent: &'a Mapping,
+    pub other_table: String,
+    // Columns of the parent table with the parent subject fields
+    input: Vec<String>,
+    // Position of the parent subject fields in the matched rows
+    columns: HashMap<String, usize>,
+    pub conditions: Vec<JoinKey>
+}
 
-fn create_rdf_ttl<'a>(id: usize, map: Mapping, rc: mpsc::Sender<usize>, db: Arc<Mutex<rusqlite::Connection>>, write: mpsc::Sender<Vec<u8>>, tables: Arc<HashMap<String, (String, String, Parts)>>) -> ResultApp<()>{
+// Result of the join between the rows of a map and the rows of its parent map.
+struct JoinMatches<'a>{
+    plan: &'a JoinPlan<'a>,
+    // Parent subject fields for each col_id of the child rows.
+    matches: HashMap<String, Vec<Vec<String>>>
+}
 
-    let table_name = map.get_table_name()?;
-    info!("RDF FROM DB TABLE: {:<30} AND MAP: {}", &table_name, map.get_identifier());
-    let main_columns = map.get_all_desired_fields()?;
+// Transforms the rows of a map into RDF triples.
+struct RowRenderer<'a>{
+    map: &'a Mapping,
+    encoding: IriEncoding,
+    subject: &'a Parts,
+    class_term: Option<Term>,
+    predicates: Vec<&'a Parts>,
+    // Sort the triples of each row, so they do not depend on the order of the joined rows
+    sorted: bool,
+    warn: bool
+}
 
-    let mut warn = true;
-    let (rows, id_col) = match select_main_columns(&table_name, main_columns, &db){
-        Ok((row, dict)) => (row, dict),
-        Err(error) => {
-            error!("Something Ocurred while selecting the main columns from the table {}.", &table_name);
-            rc.send(id)?;
-            write.send(Vec::new())?;
-            return Err(error)
+impl<'a> RowRenderer<'a>{
+    fn new(map: &'a Mapping, config: &config::AppConfiguration) -> ResultApp<Self>{
+        let subject_map = map.get_subject();
+        if subject_fields(subject_map).is_none(){
+            error!("The subject map of the map {} requieres a rr:template, rml:reference, rr:constant or a rr:termType rr:BlankNode", map.get_identifier());
+            return Err(ApplicationErrors::IncorrectMappingFormat)
         }
 
-    };
-   
-    let subject_map = map.get_subject();
-    let (temp, input) = if let Parts::Template{template, input_fields} = subject_map.get_template().unwrap(){
-        (template, input_fields)
-    }else{
-        write.send(Vec::new())?;
-        rc.send(id)?; 
-        return Err(ApplicationErrors::IncorrectMappingFormat)
-    };
-
-    // Add the class definition as new predicate
-    let class_term = add_definition_predicate(subject_map, &map, &mut warn);
+        // Add the class definition as new predicate
+        let mut warn = true;
+        let class_term = add_definition_predicate(subject_map, map, &mut warn);
+
+        Ok(Self{
+            map,
+            encoding: config.get_iri_encoding(),
+            subject: subject_map,
+            class_term,
+            predicates: map.get_predicates(),
+            sorted: config.deterministic_mode(),
+            warn
+        })
+    }
 
-    let predicates = map.get_predicates();
-    let join_stmts = generete_join_statements(&map, &predicates, &table_name, &tables);
-    let mut buffer = String::with_capacity(1000);
-    for val in rows.iter(){
-        let url = get_subject(temp, val, input, &id_col);
-        if url.is_empty(){
-            continue
-        }
-        if let Some(class) = &class_term{
-            buffer.extend(url.chars());
-            buffer.extend(class.chars());
-            if predicates.is_empty(){
-                buffer.push_str(".\n");
-            }else{
-                buffer.push_str(";\n\t\t");
-            }
+    // Returns the triples of a row. If the row has no subject, there are no triples.
+    fn render(&mut self, val: &[String], id_col: &HashMap<String, usize>, joins: Option<&HashMap<usize, JoinMatches<'_>>>) -> ResultApp<Vec<Triple>>{
+        let subject = match generate_subject(self.subject, self.map, val, id_col, self.encoding, &mut self.warn)?{
+            Some(subject) => subject,
+            None => return Ok(Vec::new())
+        };
+
+        let subject_graphs = match self.subject{
+            Parts::SubjectMap{components} => generate_graphs(components, self.map, val, id_col, self.encoding, &mut self.warn)?,
+            _ => Vec::new()
+        };
+
+        let mut triples = Vec::with_capacity(self.predicates.len() + 1);
+        if let Some(class) = &self.class_term{
+            let term = (Term::Iri(rdf::RDF_TYPE.to_string()), class.clone());
+            add_triples(&mut triples, &subject, vec![term], &subject_graphs);
         }
-
-        for (i, &pre) in predicates.iter().enumerate(){
-            if i == 0 && class_term.is_none(){
-                buffer.extend(url.chars());
+        for (i, &pre) in self.predicates.iter().enumerate(){
+            // The triples go to the graphs of the subject map and the graphs of the predicate object map.
+            let mut graphs = subject_graphs.clone();
+            if let Parts::PredicateObjectMap{object_map, ..} = pre{
+                for graph in generate_graphs(object_map, self.map, val, id_col, self.encoding, &mut self.warn)?{
+                    if !graphs.contains(&graph){
+                        graphs.push(graph);
+                    }
+                }
             }
 
-            let term;
             if pre.is_parent(){ // CONNECTION BETWEEN MAPS
-                term = term_from_join_object(&map, pre, Arc::clone(&db), val, &id_col, &join_stmts[&i], &mut warn);
-            }else{
-                term = rdf_term(&map, pre, val,&id_col, &mut warn);
-            }
-
-            let term = match term {
-                Ok(t) => {
-                    if t.is_empty(){ // Remove if data is empty
-                        if i == predicates.len() - 1 && i == 0{
-                            buffer.clear();
-                            break
-                        }else{
-                            continue
-                        }
-                    }else{
-                        t
-                    }
-                },
-                Err(error) => {
-                    write.send(Vec::new())?;    
-                    rc.send(id)?;
-                    return Err(error)
+                if let Some(joins) = joins{
+                    let terms = term_from_join_object(self.map, pre, val, id_col, &joins[&i], self.encoding, &mut self.warn)?;
+                    add_triples(&mut triples, &subject, terms, &graphs);
                 }
-            };
-            buffer.extend(term.chars());
-            if i != predicates.len() - 1{
-                buffer.push_str(";\n\t\t");
-            }else{
-                buffer.push_str(".\n");
+                continue
             }
-            if i == predicates.len() - 1{
-                buffer.push_str("\n\n");                            
+            // Remove if data is empty
+            if let Some(term) = rdf_term(self.map, pre, val, id_col, self.encoding, &mut self.warn)?{
+                add_triples(&mut triples, &subject, vec![term], &graphs);
             }
-
         }
-
-        write.send(buffer.bytes().collect())?;
-        buffer.clear();
+        if self.sorted{
+            triples.sort();
+        }
+        Ok(triples)
     }
+}
 
-    // Close and Finish Signal
-    write.send(Vec::new())?;
-    rc.send(id)?; 
-    Ok(())
+// Adds a triple for each term and graph. Without graphs, the triples go to the default graph.
+fn add_triples(triples: &mut Vec<Triple>, subject: &Term, terms: Vec<(Term, Term)>, graphs: &[Option<Term>]){
+    for (predicate, object) in terms{
+        if graphs.is_empty(){
+            triples.push(Triple{subject: subject.clone(), predicate, object, graph: None});
+            continue
+        }
+        for graph in graphs.iter(){
+            triples.push(Triple{subject: subject.clone(), predicate: predicate.clone(), object: object.clone(), graph: graph.clone()});
+        }
+    }
 }
 
-fn generete_join_statements(map: &Mapping, predicates: &Vec<&Parts>, table_name: &String, tables: &Arc<HashMap<String, (String, String, Parts)>>) -> HashMap<usize, (bool, String, String, Vec<String>)>{
-    predicates.iter().enumerate().filter(|(_, obj)| obj.is_parent())
-    .map(|(i, &obj)|{
-        if let Parts::PredicateObjectMap{predicate:_ , object_map} = obj{
-            let other_map = object_map.iter().filter(|obj| obj.is_parent()).map(|obj|{
-                if let Parts::ParentMap(other) = obj{
-                    other.clone()
+// Generates the graphs of the graph maps of a subject map or a predicate object map. rr:defaultGraph is None.
+// A graph map without value in this row does not add any graph.
+fn generate_graphs(parts: &[Parts], map: &Mapping, val: &[String], id_col: &HashMap<String, usize>, encoding: IriEncoding, warn: &mut bool) -> ResultApp<Vec<Option<Term>>>{
+    let mut graphs = Vec::new();
+    for part in parts.iter(){
+        let graph = match part{
+            Parts::GraphMap(graph) => graph,
+            _ => continue
+        };
+        let iri = match graph.as_ref(){
+            Parts::ConstantTerm(term) => Some(expand_iri(term, map, warn)),
+            Parts::ConstantString(term) => Some(term.clone()),
+            Parts::Reference(field) => Some(val[id_col[field]].clone()).filter(|value| !value.is_empty()),
+            Parts::Template{template, input_fields} => {
+                let input_data = input_fields.iter().map(|p| val[id_col[p]].clone()).collect::<Vec<_>>();
+                if input_data.iter().any(|data| data.is_empty()){
+                    None
                 }else{
-                    String::new()
+                    format_uri(template.clone(), &input_data, encoding)?
                 }
-            }).nth(0).unwrap();
-            let iter = map.get_iterator().unwrap();
-            let query_table = generate_join_template_query(&table_name, &other_map, &iter, object_map, &tables);
-
-            (i, query_table)
-        }else{ // This will never 
-            (0, (true, String::new(), String::new(), Vec::new()))
+            }
+            _ => None
+        };
+        let graph = match iri{
+            Some(iri) if iri == DEFAULT_GRAPH => None,
+            Some(iri) => Some(Term::Iri(iri)),
+            None => continue
+        };
+        if !graphs.contains(&graph){
+            graphs.push(graph);
         }
-    })
-    .collect::<HashMap<_, _>>()
-}
-
-
-fn select_main_columns(table_name: &String, main_columns: HashSet<String>, db: &Arc<Mutex<rusqlite::Connection>>) -> ResultApp<(Vec<Vec<String>>, HashMap<String, usize>)>{
-    let mut colum_idx = Vec::with_capacity(main_columns.len());
-    let fk = db.lock()?;
-
-    // Main columns in the main query.
-    let mut columns = String::with_capacity(main_columns.len() * 50);
-    for col in main_columns.iter(){
-        columns.push('"');
-        columns.push_str(&col);
-        columns.push_str("\" ,");
     }
-    columns.pop();
-
-
-    let select = format!("SELECT DISTINCT {0} , CAST(col_id as TEXT) as col_id FROM {1} GROUP BY {0}HAVING MIN(col_id) ORDER BY col_id;", columns, &table_name);
-    let mut smt = fk.prepare(&select)?;
+    Ok(graphs)
+}
 
-    let mut main_columns = main_columns.into_iter().collect::<Vec<_>>();
-    main_columns.push("col_id".to_string());
+// Fields of the data file used by a map, always in the same order.
+pub fn main_columns(map: &Mapping) -> ResultApp<Vec<String>>{
+    let mut columns = map.get_all_desired_fields()?.into_iter().collect::<Vec<_>>();
+    columns.sort();
+    Ok(columns)
+}
 
-    for col in main_columns.iter(){
-        colum_idx.push(smt.column_index(&col).unwrap())
-    }
-
-    let raw_rows= smt.query_map([], |row|{
-        let mut values: Vec<String> = Vec::with_capacity(main_columns.len());
-        for col in colum_idx.iter(){
-            values.push(row.get(*col).unwrap_or(String::new()))
-        }
-        Ok(values)
-    })?
-    .filter(|row| row.is_ok())
-    .map(|row| row.unwrap())
-    .collect::<Vec<Vec<String>>>();
-    
-    let id_col = main_columns
-    .into_iter()
+// Position of each field in the selected rows. The col_id always goes last.
+pub fn column_positions(main_columns: &[String]) -> HashMap<String, usize>{
+    main_columns.iter()
+    .chain(std::iter::once(&"col_id".to_string()))
     .map(|key| {
         if key.contains("||"){
             key.split("||").nth(1).unwrap().to_string()
@@ -385,387 +719,732 @@ fn select_main_columns(table_name: &String, main_columns: HashSet<String>, db: &
             key.clone()
         }
     })
-    .zip(colum_idx)
-    .collect::<HashMap<_, _>>();
-
-    Ok((raw_rows, id_col))
+    .enumerate()
+    .map(|(i, key)| (key, i))
+    .collect()
 }
 
-fn get_subject(template: &String, val: &Vec<String>, input: &Vec<String>, id_col: &HashMap<String, usize>) -> String{
-    // Getting the subject
-    let input_data = input.iter()
-    .map(|p|{
-        let idx = id_col[p];
-        val[idx].clone()
-    }).collect::<Vec<_>>();
-    
-    let url = format_uri(template.clone(), &input_data);
-    url
-}
+// Selects the next page of rows of a range that are not repeated, using the col_id as cursor. A row is repeated if
+// there is a previous row in the table, even in other range, with the same values in the main columns.
+// It also returns the col_id of the last row that was read, which is None if there were no rows left.
+fn select_page(table_name: &str, main_columns: &[String], range: RowRange, db: &rusqlite::Connection) -> ResultApp<(Vec<Vec<String>>, Option<i64>)>{
+    // Main columns in the main query.
+    let mut columns = String::with_capacity(main_columns.len() * 50);
+    let mut repeated = String::with_capacity(main_columns.len() * 50);
+    for col in main_columns.iter(){
+        columns.push_str(&format!("main.\"{0}\", ", col));
+        repeated.push_str(&format!("prev.\"{0}\" IS main.\"{0}\" AND ", col));
+    }
+
+    // Constant and blank node subjects may not use any field, so every row is used. The repeated rows are also read,
+    // so a page with many repeated rows does not scan the rest of the range.
+    let is_repeated = if main_columns.is_empty(){
+        "0".to_string()
+    }else{
+        format!("EXISTS (SELECT 1 FROM {} AS prev WHERE {}prev.col_id < main.col_id)", table_name, repeated)
+    };
+    let select = format!(
+        "SELECT {}main.col_id, {} FROM {} AS main WHERE main.col_id BETWEEN ?1 AND ?2 ORDER BY main.col_id LIMIT ?3;",
+        columns, is_repeated, table_name
+    );
+
+    let mut smt = db.prepare_cached(&select)?;
+    let mut rows = smt.query(rusqlite::params![range.0, range.1, PAGE_ROWS])?;
+    let mut page = Vec::new();
+    let mut last_read = None;
+    while let Some(row) = rows.next()?{
+        let col_id: i64 = row.get(main_columns.len())?;
+        last_read = Some(col_id);
+        if row.get(main_columns.len() + 1)?{
+            continue
+        }
 
-fn format_uri(mut url: String, input: &Vec<String>) -> String{
-    let mut input_id = 0;
-    while let Some(pos) = url.chars().position(|f| f == '{'){
+        let mut values: Vec<String> = Vec::with_capacity(main_columns.len() + 1);
+        for (i, col) in main_columns.iter().enumerate(){
+            match row.get::<_, Option<String>>(i){
+                Ok(value) => values.push(value.unwrap_or_default()),
+                Err(error) => {
+                    warning!("The row {} of the table {} was skipped, the field \"{}\" could not be read: {}", col_id, table_name, col, error);
+                    break
+                }
+            }
+        }
+        if values.len() == main_columns.len(){
+            values.push(col_id.to_string());
+            page.push(values);
+        }
+    }
 
-        url.insert_str(pos, &input[input_id]);
+    Ok((page, last_read))
+}
 
-        url.remove(pos + input[input_id].len());
-        url.remove(pos + input[input_id].len());
-        // This remove the {} symbol.
-        input_id += 1;
-        if input_id >= input.len(){
-            break
+// Fields of the data file needed to generate the subject. It returns None if the subject map can not generate a subject.
+pub fn subject_fields(subject: &Parts) -> Option<Vec<String>>{
+    let components = match subject{
+        Parts::SubjectMap{components} => components,
+        _ => return None
+    };
+    let mut blank = false;
+    for comp in components.iter(){
+        match comp{
+            Parts::Template{input_fields, ..} => return Some(input_fields.clone()),
+            Parts::Reference(field) => return Some(vec![field.clone()]),
+            Parts::ConstantTerm(_) | Parts::ConstantString(_) => return Some(Vec::new()),
+            Parts::TermType(kind) if kind.contains("BlankNode") => blank = true,
+            _ => {}
         }
     }
-    url.insert(0, '<');
-    url.push('>');
-    if url.contains(' '){
-        warning!("INVALID URI. The folowing URI Contains Spaces so it will be discarded. URI: {}", url);
-        return String::new();
+    // A blank node without any other term is identified by its row.
+    if blank{
+        Some(vec!["col_id".to_string()])
+    }else{
+        None
     }
-
-    url.push(' ');
-    url 
 }
 
-fn add_definition_predicate(subject: &Parts, map: &Mapping, warn: &mut bool) -> Option<String>{
-    if let Parts::SubjectMap{components } = subject{
-        let class = components.iter()
-        .filter(|comp|{
-            if let Parts::Class(_) = comp{
-                true
-            }else{
-                false
-            }
-        }).nth(0);
-        if let Some(Parts::Class(data)) = class{
-            let mut definition = "a ".to_string();
-            let class_uri = get_predicate(data, map, true, warn); 
-            definition.extend(class_uri.chars());
-            return Some(definition)
+// Generates the subject of a row. The subject map can use a template, a reference, a constant or only a blank node.
+pub fn generate_subject(subject: &Parts, map: &Mapping, val: &[String], id_col: &HashMap<String, usize>, encoding: IriEncoding, warn: &mut bool) -> ResultApp<Option<Term>>{
+    let components = match subject{
+        Parts::SubjectMap{components} => components,
+        _ => return Ok(None)
+    };
+    let blank = components.iter().any(|comp| matches!(comp, Parts::TermType(kind) if kind.contains("BlankNode")));
 
+    for comp in components.iter(){
+        let value = match comp{
+            Parts::Template{template, input_fields} => {
+                let input_data = input_fields.iter().map(|p| val[id_col[p]].clone()).collect::<Vec<_>>();
+                if input_data.iter().any(|data| data.is_empty()){ // NULL values do not generate a subject
+                    return Ok(None)
+                }
+                if !blank{
+                    return Ok(format_uri(template.clone(), &input_data, encoding)?.map(Term::Iri))
+                }
+                fill_template(template.clone(), &input_data)
+            }
+            Parts::Reference(field) => val[id_col[field]].clone(),
+            Parts::ConstantTerm(term) => return Ok(Some(Term::Iri(expand_iri(term, map, warn)))),
+            Parts::ConstantString(term) => return Ok(Some(Term::Iri(term.clone()))),
+            _ => continue
+        };
+        if value.is_empty(){
+            return Ok(None)
+        }
+        return if blank{
+            Ok(Some(Term::BlankNode(value)))
         }else{
-            return None
+            Ok(Some(Term::Iri(value)))
         }
+    }
+
+    if blank{
+        // Only the row identifies the blank node. The streamed maps do not have a col_id, so the hash of the row is used.
+        let row_id = match id_col.get("col_id"){
+            Some(&i) => val[i].clone(),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                val.hash(&mut hasher);
+                format!("{:x}", hasher.finish())
+            }
+        };
+        return Ok(Some(Term::BlankNode(format!("{}-{}", map.get_identifier(), row_id))))
+    }
+    Ok(None)
+}
 
+// Replaces the {} of the template with the input values. The placeholders are only looked for in the template, so a
+// value with braces does not take the place of the next ones.
+fn fill_template(url: String, input: &[String]) -> String{
+    let mut filled = String::with_capacity(url.len() + input.iter().map(|value| value.len()).sum::<usize>());
+    let mut values = input.iter();
+    let mut rest = url.as_str();
+    while let Some(pos) = rest.find('{'){
+        let value = match values.next(){
+            Some(value) => value,
+            None => break
+        };
+        filled.push_str(&rest[..pos]);
+        filled.push_str(value);
+        rest = rest.get(pos + 2..).unwrap_or_default();
     }
-    return None
+    filled.push_str(rest);
+    filled
 }
 
+// Fills the template of an IRI. The values are percent-encoded, or the IRI is checked, according to the configuration.
+fn format_uri(url: String, input: &[String], encoding: IriEncoding) -> ResultApp<Option<String>>{
+    if encoding == IriEncoding::Encode{
+        let input = input.iter().map(|value| rdf::iri_safe(value)).collect::<Vec<_>>();
+        return Ok(Some(fill_template(url, &input)))
+    }
 
+    let url = fill_template(url, input);
+    if rdf::is_valid_iri(&url){
+        Ok(Some(url))
+    }else if encoding == IriEncoding::Skip{
+        warning!("INVALID URI. The folowing URI Contains invalid characters so it will be discarded. URI: {}", url);
+        Ok(None)
+    }else{
+        error!("INVALID URI. The folowing URI Contains invalid characters: {}", url);
+        Err(ApplicationErrors::InvalidIRI)
+    }
+}
 
-fn rdf_term(map: &Mapping, predicate_part: &Parts, from_table: &Vec<String>, columns: &HashMap<String, usize>, warn: &mut bool) -> ResultApp<String>{
-    if let Parts::PredicateObjectMap{predicate, object_map} = predicate_part{
-        let mut rdf_term = String::with_capacity(100); 
-        
-        let pred = get_predicate(predicate, map, true, warn);
-        if pred.is_empty(){
-            return Ok(rdf_term);
-        }
-        rdf_term.extend(pred.chars());
-       
-        let object = term_from_object(map, &object_map, from_table, columns, warn);
-        if object.is_empty(){
-            return Ok(object)
-        }
-        rdf_term.extend(object.chars());
-        Ok(rdf_term)
+fn add_definition_predicate(subject: &Parts, map: &Mapping, warn: &mut bool) -> Option<Term>{
+    if let Parts::SubjectMap{components} = subject{
+        components.iter().find_map(|comp| {
+            match comp{
+                Parts::Class(data) => Some(Term::Iri(expand_iri(data, map, warn))),
+                _ => None
+            }
+        })
     }else{
-        Ok(String::new())
+        None
     }
 }
 
+// Returns the predicate and the object of a predicate object map for a row.
+fn rdf_term(map: &Mapping, predicate_part: &Parts, from_table: &[String], columns: &HashMap<String, usize>, encoding: IriEncoding, warn: &mut bool) -> ResultApp<Option<(Term, Term)>>{
+    if let Parts::PredicateObjectMap{predicate, object_map} = predicate_part{
+        let object = term_from_object(map, object_map, from_table, columns, encoding, warn)?;
+        Ok(object.map(|object| (Term::Iri(expand_iri(predicate, map, warn)), object)))
+    }else{
+        Ok(None)
+    }
+}
 
-fn term_from_object(map: &Mapping, objects: &Vec<Parts>, from_table: &Vec<String>, columns: &HashMap<String, usize>, warn: &mut bool) -> String{
-    let mut term_kind: u8 = 3; // true: datatype false: uri
-    let mut term_type = String::from("xsd:string");
-    let mut object = String::new();
+// Generates the object of a row. References and constant strings are literals, and templates and constant terms are IRIs,
+// unless the rr:termType says otherwise. Only the values of IRI templates are encoded.
+pub fn term_from_object(map: &Mapping, objects: &[Parts], from_table: &[String], columns: &HashMap<String, usize>, encoding: IriEncoding, warn: &mut bool) -> ResultApp<Option<Term>>{
+    let mut term_type: Option<&String> = None;
+    let mut datatype = None;
+    let mut language = None;
+    let mut object = None;
+    let mut template = None;
 
     for element in objects{
         match element{
-            Parts::Template{template, input_fields} => {
-                term_kind = 0;
+            Parts::Template{template: url, input_fields} => {
                 let input_data = input_fields.iter()
-                .map(|f| columns[f])
-                .map(|i| from_table[i].clone())
+                .map(|f| from_table[columns[f]].clone())
                 .collect::<Vec<_>>();
-                
-                object = format_uri(template.clone(), &input_data);
-                if object.is_empty(){
-                    return String::new()
+                if input_data.iter().any(|data| data.is_empty()){
+                    return Ok(None)
                 }
-                break
+                template = Some((url, input_data));
             }
+            Parts::Reference(obj) => object = Some(from_table[columns[obj]].clone()),
+            Parts::DataType(type_data) => datatype = Some(expand_iri(type_data, map, warn)),
+            Parts::Language(tag) => language = Some(tag.clone()),
+            Parts::TermType(type_term) => term_type = Some(type_term),
+            Parts::ConstantString(obj) => object = Some(obj.clone()),
+            Parts::ConstantTerm(obj) => return Ok(Some(Term::Iri(expand_iri(obj, map, warn)))),
+            _=>{}
+        }
+    }
 
-            Parts::Reference(obj) => {
-                let i = columns[obj];
-                object = from_table[i].clone();
-                if object.is_empty(){
-                    return object
-                }
+    let is_literal = matches!(term_type, Some(kind) if kind.contains("Literal"));
+    let object = match template{
+        Some((url, input_data)) if !is_literal && !matches!(term_type, Some(kind) if kind.contains("BlankNode")) => {
+            return Ok(format_uri(url.clone(), &input_data, encoding)?.map(Term::Iri))
+        }
+        Some((url, input_data)) => Some(fill_template(url.clone(), &input_data)),
+        None => object
+    };
+
+    let object = match object.filter(|obj| !obj.is_empty()){
+        Some(object) => object,
+        None => return Ok(None)
+    };
+    Ok(match term_type{
+        Some(kind) if kind.contains("BlankNode") => Some(Term::BlankNode(object)),
+        Some(kind) if kind.contains("IRI") => Some(Term::Iri(object)),
+        _ => match language{
+            Some(language) => Some(Term::LangLiteral{value: object, language}),
+            None => Some(Term::Literal{value: object, datatype})
+        }
+    })
+}
+
+// Returns a term for each of the parent rows that were joined with the current row.
+fn term_from_join_object(map: &Mapping, pre: &Parts, from_table: &[String], columns: &HashMap<String, usize>, join: &JoinMatches<'_>, encoding: IriEncoding, warn: &mut bool) -> ResultApp<Vec<(Term, Term)>>{
+    let id = &from_table[columns["col_id"]];
+    let parents = match join.matches.get(id){
+        Some(parents) => parents,
+        None => return Ok(Vec::new())
+    };
+
+    let predicate = if let Parts::PredicateObjectMap{predicate, ..} = pre{
+        Term::Iri(expand_iri(predicate, map, warn))
+    }else{
+        return Ok(Vec::new())
+    };
+
+    // Several parent rows can produce the same subject, but only one triple is generated for each one.
+    let mut subjects = HashSet::with_capacity(parents.len());
+    let mut terms = Vec::with_capacity(parents.len());
+    let parent_subject = join.plan.parent.get_subject();
+    for row in parents.iter(){
+        if let Some(subject) = generate_subject(parent_subject, join.plan.parent, row, &join.plan.columns, encoding, warn)?{
+            if subjects.insert(subject.clone()){
+                terms.push((predicate.clone(), subject));
             }
-            Parts::DataType(type_data) => {
-                term_type = type_data.clone();
+        }
+    }
+    Ok(terms)
+}
+
+// Finds the parent map and the join conditions of every referencing object map of a map. The key is the position
+// of the predicate in the map.
+pub fn plan_joins<'a>(map: &Mapping, table_name: &str, tables: &'a HashMap<String, Mapping>) -> ResultApp<HashMap<usize, JoinPlan<'a>>>{
+    let iterator = map.get_iterator()?;
+    let mut plans = HashMap::new();
+
+    for (i, &pre) in map.get_predicates().iter().enumerate(){
+        let object_map = match pre{
+            Parts::PredicateObjectMap{object_map, ..} if pre.is_parent() => object_map,
+            _ => continue
+        };
+        let other_map = object_map.iter().find_map(|obj| {
+            match obj{
+                Parts::ParentMap(other) => Some(other),
+                _ => None
             }
-            Parts::TermType(type_term) => {
-                if type_term.contains("IRI") {
-                    term_kind = 1;
-                }else if type_term.contains("BlankNode"){
-                    term_kind = 1;
-                }else{
-                    term_kind = 3;
-                }
+        }).unwrap();
+
+        let parent = match tables.get(other_map){
+            Some(parent) => parent,
+            None => {
+                error!("The parent map {} used in the map {} was not found", other_map, map.get_identifier());
+                return Err(ApplicationErrors::MappingNotFound)
             }
-            Parts::ConstantString(obj) => {
-                object = obj.clone();
-                term_kind = 1;
-                break
+        };
+        let other_table = parent.get_table_name()?;
+        let other_iter = parent.get_iterator()?;
+        let fields = match subject_fields(parent.get_subject()){
+            Some(fields) => fields,
+            None => {
+                error!("The subject map of the parent map {} can not generate a subject", other_map);
+                return Err(ApplicationErrors::IncorrectMappingFormat)
             }
-            Parts::ConstantTerm(obj) => {
-                object = get_predicate(&obj, map, false, warn);
-                term_kind = 3;
-                break
+        };
+        let input = fields.iter().map(|f| column_with_iterator(&other_iter, f)).collect::<Vec<_>>();
+
+        let conditions = object_map.iter().filter_map(|obj| {
+            match obj{
+                Parts::JoinCondition(child, parent, normalisation) => Some(JoinKey{
+                    child: column_with_iterator(&iterator, child),
+                    parent: column_with_iterator(&other_iter, parent),
+                    normalisation: *normalisation
+                }),
+                _ => None
             }
-            _=>{}
+        }).collect::<Vec<_>>();
+
+        if conditions.is_empty() && other_table != table_name{
+            error!("The map {} references the map {} without a join condition, but they do not share the same logical source", map.get_identifier(), other_map);
+            return Err(ApplicationErrors::IncorrectMappingFormat)
         }
-    }
 
-    if term_kind == 3{
-        let kind = get_predicate(&term_type, map, true, warn);
-        return format!("\"{}\"^^{}", object, kind)
+        plans.insert(i, JoinPlan{
+            parent,
+            other_table,
+            input,
+            columns: fields.into_iter().enumerate().map(|(i, f)| (f, i)).collect(),
+            conditions
+        });
     }
-    else if term_kind == 1{
-        object.insert(0, '<');
-        object.push('>');
-        return object
-    }else{
-        return object
+
+    Ok(plans)
+}
+
+// Solves the referencing object maps for a range of rows of a map. Each one of them is solved with a single join
+// query between the table of the map and the table of its parent map.
+fn prepare_joins<'a>(plans: &'a HashMap<usize, JoinPlan<'a>>, table_name: &str, range: RowRange, db: &rusqlite::Connection) -> ResultApp<HashMap<usize, JoinMatches<'a>>>{
+    let mut joins = HashMap::with_capacity(plans.len());
+    for (&i, plan) in plans.iter(){
+        let query = generate_join_query(table_name, &plan.other_table, &plan.input, &plan.conditions);
+        let mut smt = db.prepare(&query)?;
+        let max = smt.column_count();
+        let mut matches: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+        let mut rows = smt.query([range.0, range.1])?;
+        while let Some(row) = rows.next()?{
+            let id: String = row.get(0)?;
+            let mut values = Vec::with_capacity(max - 1);
+            for (k, field) in (1..max).zip(plan.input.iter()){
+                match row.get::<_, Option<String>>(k){
+                    Ok(value) => values.push(value.unwrap_or_default()),
+                    Err(error) => {
+                        warning!("A parent row of the row {} of the table {} was skipped, the field \"{}\" of the table {} could not be read: {}", id, table_name, field, plan.other_table, error);
+                        break
+                    }
+                }
+            }
+            if values.len() == max - 1{
+                matches.entry(id).or_default().push(values);
+            }
+        }
+
+        joins.insert(i, JoinMatches{plan, matches});
     }
 
+    Ok(joins)
 }
 
-fn term_from_join_object(map: &Mapping, pre: &Parts, db: Arc<Mutex<rusqlite::Connection>>, from_table: &Vec<String>, columns: &HashMap<String, usize>, join_data: &(bool, String, String, Vec<String>), warn: &mut bool) -> ResultApp<String>{
-    
-    let mut data = Vec::new();
-    let template = &join_data.2;
-    let query = join_data.1.clone();
+// Columns of both tables used in a rr:joinCondition
+pub struct JoinKey{
+    pub child: String,
+    pub parent: String,
+    pub normalisation: KeyNormalisation
+}
 
-    // data.push(id);
+pub fn column_with_iterator(iterator: &str, field: &str) -> String{
+    if iterator.is_empty() || field == "col_id"{
+        field.to_string()
+    }else{
+        format!("{}||{}", iterator, field)
+    }
+}
 
-    
-    for f in join_data.3.iter(){
-        let i = columns[f];
-        let v = &from_table[i];
-        data.push(v); 
+// Indexes the join columns of both tables, so the join does not requiere a full scan for each row.
+// The index uses the same normalisation as the join condition.
+fn create_join_indexes(db: &rusqlite::Connection, table_name: &str, other_table: &str, conditions: &[JoinKey]) -> ResultApp<()>{
+    if conditions.is_empty(){
+        return Ok(()) // The primary key is used.
     }
 
-    // Generate the query.
-    let query = add_values_to_query(query, data, &join_data.0);
-    if query.is_empty(){
-        return Ok(String::new());
+    let mut query = String::with_capacity(255);
+    for (table, is_child) in [(table_name, true), (other_table, false)]{
+        let columns = conditions.iter()
+            .map(|key| if is_child{ &key.child }else{ &key.parent })
+            .collect::<Vec<_>>();
+        let expressions = conditions.iter().zip(columns.iter())
+            .map(|(key, column)| key.normalisation.sql_expression(&format!("\"{}\"", column)))
+            .collect::<Vec<_>>();
+        let normalisations = conditions.iter()
+            .map(|key| format!("{:?}", key.normalisation))
+            .collect::<Vec<_>>();
+
+        query.push_str(&format!(
+            "CREATE INDEX IF NOT EXISTS \"idx-{}-{}-{}\" ON {} ({});",
+            table.replace('"', ""),
+            columns.iter().map(|c| c.as_str()).collect::<Vec<_>>().join("-"),
+            normalisations.join("-"),
+            table,
+            expressions.join(", ")
+        ));
     }
+    db.execute_batch(&query)?;
+    Ok(())
+}
 
-    // Request Of the Template input fields.
-    let fk = db.lock()?;
-    let mut smt = fk.prepare(&query)?;
-    
-    let max = smt.column_count();
-    let row = smt.query_row([], |row| {
-        let mut values = Vec::with_capacity(max);
-        for k in 0..max{
-            let d = row.get(k).unwrap_or(String::new());
-            values.push(d);
+// Generates the join query that returns the col_id of the child rows and the subject fields of the parent map.
+fn generate_join_query(table_name: &str, other_table: &str, input: &[String], conditions: &[JoinKey]) -> String{
+    let mut query = String::with_capacity(255);
+    query.push_str("SELECT DISTINCT CAST(child.col_id AS TEXT)");
+    for f in input.iter(){
+        if f == "col_id"{
+            query.push_str(", CAST(parent.col_id AS TEXT)");
+        }else{
+            query.push_str(", parent.\"");
+            query.push_str(f);
+            query.push('"');
         }
-        Ok(values)
-    });
+    }
+    query.push_str(&format!(" FROM {} AS child JOIN {} AS parent ON ", table_name, other_table));
 
-    let row = match row{
-        Ok(rows) => rows,
-        Err(error) => {
-            match error{
-                rusqlite::Error::QueryReturnedNoRows => {
-                    return Ok(String::new())
-                }
-                _ => return Err(error.into())
+    if conditions.is_empty(){
+        // Same logical source: the parent subject is generated from the same row.
+        query.push_str("child.col_id = parent.col_id");
+    }else{
+        let on = conditions.iter()
+            .map(|key| format!(
+                "{} = {}",
+                key.normalisation.sql_expression(&format!("child.\"{}\"", key.child)),
+                key.normalisation.sql_expression(&format!("parent.\"{}\"", key.parent))
+            ))
+            .collect::<Vec<_>>();
+        query.push_str(&on.join(" AND "));
+    }
+    query.push_str(" WHERE child.col_id BETWEEN ?1 AND ?2 ORDER BY child.col_id;");
+    query
+}
+
+// Indexes the main columns of a table, so the repeated rows can be found without a full scan for each row.
+fn create_main_index(db: &rusqlite::Connection, table_name: &str, main_columns: &[String]) -> ResultApp<()>{
+    if main_columns.is_empty(){
+        return Ok(())
+    }
+    let columns = main_columns.iter().map(|col| format!("\"{}\"", col)).collect::<Vec<_>>();
+    db.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS \"idx-{}-{}\" ON {} ({});",
+        table_name.replace('"', ""),
+        main_columns.join("-"),
+        table_name,
+        columns.join(", ")
+    ))?;
+    Ok(())
+}
+
+// Expands a prefixed name with the prefixes of the map. IRIs between <> are used as they are.
+pub fn expand_iri(name: &str, map: &Mapping, warn: &mut bool) -> String{
+    if name.starts_with('<') && name.ends_with('>'){
+        return name[1..name.len() - 1].to_string()
+    }
+    let (prefix, local) = match name.find(':'){
+        Some(pos) => name.split_at(pos + 1),
+        None => return name.to_string()
+    };
+    match map.get_prefixes().get(prefix){
+        Some(url) => format!("{}{}", url, local),
+        None => {
+            if *warn{
+                warning!("There is a missing prefix definition \"{}\" for the following map: {}", prefix, map.get_identifier());
+                *warn = false;
             }
+            name.to_string()
         }
-    };
-    
+    }
+}
 
-    let mut term = String::new();
+#[cfg(test)]
+mod test_materialiser{
+    use super::*;
+    use crate::mappings::AcceptedType;
+
+    fn join_tables() -> rusqlite::Connection{
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        db.execute_batch("
+            CREATE TABLE \"db-child-CSV\" (col_id INTEGER PRIMARY KEY AUTOINCREMENT, \"id\" TEXT, \"city\" TEXT, \"country\" TEXT);
+            CREATE TABLE \"db-parent-CSV\" (col_id INTEGER PRIMARY KEY AUTOINCREMENT, \"route\" TEXT, \"city\" TEXT, \"country\" TEXT, \"stop\" TEXT);
+            INSERT INTO \"db-child-CSV\" (\"id\", \"city\", \"country\") VALUES ('1', 'Paris', 'FR'), ('2', 'Madrid', 'ES'), ('3', 'Rome', 'IT'), ('4', ' PARIS ', 'fr');
+            INSERT INTO \"db-parent-CSV\" (\"route\", \"city\", \"country\", \"stop\") VALUES
+                ('r1', 'Paris', 'FR', 'a'), ('r2', 'Paris', 'FR', 'a'), ('r2', 'Paris', 'FR', 'b'),
+                ('r3', 'Madrid', 'ES', 'a'), ('r4', 'Paris', 'US', 'a'), ('r5', 'Rome', 'FR', 'a');
+        ").unwrap();
+        db
+    }
 
-    let predicate = if let Parts::PredicateObjectMap{predicate, ..} = pre{
-        predicate
-    }else{
-        return Ok(term)
-    };
+    fn child_map(conditions: Vec<Parts>) -> Mapping{
+        let mut map = Mapping::new("Airport".to_string());
+        map.add_component(Parts::LogicalSource{
+            source: std::path::PathBuf::from("child.csv"),
+            reference_formulation: AcceptedType::CSV,
+            iterator: String::new()
+        });
+        let mut object_map = vec![Parts::ParentMap("Route".to_string())];
+        object_map.extend(conditions);
+        map.add_component(Parts::PredicateObjectMap{
+            predicate: "ex:served".to_string(),
+            object_map
+        });
+        let prefixes = vec![("ex:".to_string(), "http://example.com/".to_string())].into_iter().collect();
+        map.change_prefixes(Arc::new(prefixes));
+        map
+    }
 
-    let pred = get_predicate(predicate, map, true, warn);
-    if pred.is_empty(){
-        return Ok(term);
+    fn parent_tables(subject: Vec<Parts>) -> HashMap<String, Mapping>{
+        let mut map = Mapping::new("Route".to_string());
+        map.add_component(Parts::LogicalSource{
+            source: std::path::PathBuf::from("parent.csv"),
+            reference_formulation: AcceptedType::CSV,
+            iterator: String::new()
+        });
+        map.add_component(Parts::SubjectMap{components: subject});
+        let mut tables = HashMap::new();
+        tables.insert("Route".to_string(), map);
+        tables
     }
-    term.extend(pred.chars());
-   
-    let url = format_uri(template.clone(), &row);
-    if url.is_empty(){
-        return Ok(String::new())
+
+    fn route_template() -> Vec<Parts>{
+        vec![Parts::Template{
+            template: "http://example.com/route/{}".to_string(),
+            input_fields: vec!["route".to_string()]
+        }]
     }
-    term.extend(url.chars());
-    
-    Ok(term)
-}
 
-fn add_values_to_query(query: String, data: Vec<&String>, same_table: &bool) -> String{
-    // let idx = query.chars().enumerate().filter(|(_, c)| *c == '~').map(|(i,_)| i).collect::<Vec<_>>();
+    fn joined_subjects(map: &Mapping, db: &rusqlite::Connection, col_id: &str) -> Vec<String>{
+        joined_subjects_with(map, db, col_id, route_template())
+    }
 
-    let parts = query.split('~');
-    let mut que = String::with_capacity(query.len() + data.len() * 10);
-    for (p, d) in parts.zip(data){
-        que.push_str(p);
-        if *same_table{
-            que.push_str(d);
-        }else{
-            que.push('"');
-            que.push_str(d);
-            que.push('"');    
-        }
+    fn joined_subjects_with(map: &Mapping, db: &rusqlite::Connection, col_id: &str, subject: Vec<Parts>) -> Vec<String>{
+        let predicates = map.get_predicates();
+        let tables = parent_tables(subject);
+        let plans = plan_joins(map, "\"db-child-CSV\"", &tables).unwrap();
+        let joins = prepare_joins(&plans, "\"db-child-CSV\"", (1, 4), db).unwrap();
+        let columns = vec![("col_id".to_string(), 0)].into_iter().collect::<HashMap<_, _>>();
+        let mut warn = false;
+        term_from_join_object(map, predicates[0], &[col_id.to_string()], &columns, &joins[&0], IriEncoding::Encode, &mut warn).unwrap()
+        .into_iter()
+        .map(|(predicate, object)| format!("{} {}", predicate.to_ntriples(), object.to_ntriples()))
+        .collect()
     }
-    que
-}
 
-fn generate_join_template_query(table_name: &String, other_map: &String, map_iterator: &String, objects: &Vec<Parts>, tables: &Arc<HashMap<String, (String, String, Parts)>>) -> (bool, String, String, Vec<String>){
-    
-    let (other_table, other_iter, other_temp) = &tables[other_map];
-    
-    let (other_template, input) = if let Parts::Template{template, input_fields} = other_temp{
-        let input = input_fields.iter().map(|f|{
-            let mut new_field;
-            if other_iter.is_empty(){
-                new_field = f.clone();
-            }else{
-                new_field = other_iter.clone();
-                new_field.push_str("||");
-                new_field.extend(f.chars());
-            }
-            new_field
-        }).collect::<Vec<_>>();
+    #[test]
+    fn join_conditions_are_combined(){
+        let query = generate_join_query("child", "parent", &["route".to_string()], &[
+            JoinKey{child: "city".to_string(), parent: "town".to_string(), normalisation: KeyNormalisation::default()},
+            JoinKey{child: "country".to_string(), parent: "nation".to_string(), normalisation: KeyNormalisation::default()}
+        ]);
+        assert_eq!(query, "SELECT DISTINCT CAST(child.col_id AS TEXT), parent.\"route\" FROM child AS child JOIN parent AS parent ON child.\"city\" = parent.\"town\" AND child.\"country\" = parent.\"nation\" WHERE child.col_id BETWEEN ?1 AND ?2 ORDER BY child.col_id;");
+    }
 
-        (template.clone(), input)
-    }else{
-        return (true, String::new(), String::new(), Vec::new())
-    };
+    #[test]
+    fn join_returns_every_parent_subject_once(){
+        let db = join_tables();
+        let map = child_map(vec![Parts::JoinCondition("city".to_string(), "city".to_string(), KeyNormalisation::default())]);
+        let terms = joined_subjects(&map, &db, "1");
+        assert_eq!(terms, vec![
+            "<http://example.com/served> <http://example.com/route/r1>".to_string(),
+            "<http://example.com/served> <http://example.com/route/r2>".to_string(),
+            "<http://example.com/served> <http://example.com/route/r4>".to_string(),
+        ]);
+    }
 
-    let mut query = String::with_capacity(255);
-    query.push_str("SELECT ");
-    for f in input{
-        query.push_str(&other_table);
-        query.push('.');
-        query.push('"');
-        query.push_str(&f);
-        query.push_str("\", ")
-    }
-
-    query.pop();
-    query.pop();
-    query.pop();
-    query.push_str("\" FROM ");
-    query.push_str(&other_table);
-    let joins;
-    if other_table == table_name{
-        joins = vec!["col_id".to_string()];
-        query.extend(format!(" WHERE {}.col_id == ~", other_table).chars())
-    }
-    else{
-        query.push(',');
-        query.push_str(&table_name);
-        
-        joins = objects.iter()
-        .filter(|obj| obj.is_join())
-        .map(|obj|{
-            match obj{
-                Parts::JoinCondition(child, _) => {
-                    let mut new_child;
-                    if map_iterator.is_empty(){
-                        new_child = child.clone();
-                    }else{
-                        new_child = map_iterator.clone();
-                        new_child.push_str("||");
-                        new_child.extend(child.chars());
-                    }
-                    new_child
-                },
-                _ =>  String::new()
-            }
-        }).collect::<Vec<_>>();
+    #[test]
+    fn join_requires_all_conditions(){
+        let db = join_tables();
+        let map = child_map(vec![
+            Parts::JoinCondition("city".to_string(), "city".to_string(), KeyNormalisation::default()),
+            Parts::JoinCondition("country".to_string(), "country".to_string(), KeyNormalisation::default())
+        ]);
+        assert_eq!(joined_subjects(&map, &db, "1").len(), 2);
+        assert_eq!(joined_subjects(&map, &db, "2").len(), 1);
+        assert!(joined_subjects(&map, &db, "3").is_empty());
+    }
 
-        let parents = objects.iter()
-        .filter(|obj| obj.is_join())
-        .map(|obj|{
-            match obj{
-                Parts::JoinCondition(_, parent) => {
-                    let mut new_parent;
-                    if other_iter.is_empty(){
-                        new_parent = parent.clone();
-                    }else{
-                        new_parent = other_iter.clone();
-                        new_parent.push_str("||");
-                        new_parent.extend(parent.chars());
-                    }
+    #[test]
+    fn join_with_non_template_parent_subjects(){
+        let db = join_tables();
+        let map = child_map(vec![Parts::JoinCondition("city".to_string(), "city".to_string(), KeyNormalisation::default())]);
 
-                    new_parent
-                },
-                _ =>  String::new()
-            }
-        }).collect::<Vec<_>>();
+        let constant = vec![Parts::ConstantString("http://example.com/network".to_string())];
+        assert_eq!(joined_subjects_with(&map, &db, "1", constant), vec!["<http://example.com/served> <http://example.com/network>".to_string()]);
 
-        query.push_str(" WHERE ");
-        if !parents.is_empty(){
-            // query.push_str(" AND ");
-            for (i, parent) in parents.iter().enumerate(){
-                query.push_str(&other_table);
-                query.push('.');
-                query.push('"');
-                query.push_str(&parent);
-                query.push('"');
-                query.push_str(" == ~");
-                if i != joins.len() - 1{
-                    query.push_str(" AND ")
-                }
-            }
-        }
+        let reference = vec![Parts::Reference("route".to_string()), Parts::TermType("rr:BlankNode".to_string())];
+        assert_eq!(joined_subjects_with(&map, &db, "2", reference), vec!["<http://example.com/served> _:br3".to_string()]);
     }
 
-    query.push(';');
-    (other_table == table_name, query, other_template, joins)
-}
+    #[test]
+    fn join_keys_are_normalised(){
+        let db = join_tables();
+        let mut normalisation = KeyNormalisation::default();
+        assert!(joined_subjects(&child_map(vec![Parts::JoinCondition("city".to_string(), "city".to_string(), normalisation)]), &db, "4").is_empty());
+
+        normalisation.add("trim");
+        normalisation.add("lowercase");
+        let map = child_map(vec![
+            Parts::JoinCondition("city".to_string(), "city".to_string(), normalisation),
+            Parts::JoinCondition("country".to_string(), "country".to_string(), normalisation)
+        ]);
+        assert_eq!(joined_subjects(&map, &db, "4").len(), 2);
+    }
 
-fn get_predicate(predicate: &String, map: &Mapping, tags: bool, warn: &mut bool) -> String{
-    let mut pre = String::with_capacity(predicate.len() + 30);
-    let mut parts = predicate.split(':');
-    let mut prefix = parts.next().unwrap().to_string();
-    if let Some(url) = parts.next(){
-        let prefixes = map.get_prefixes();
-        prefix.push(':');
-        if let Some(pre_url) = prefixes.get(&prefix){
-            pre.extend(pre_url.chars());
-        }else{
-            if *warn{
-                warning!("There is a missing prefix definition \"{}\" for the following map: {}", prefix, map.get_identifier());
-                *warn = false;
-            }
-            let mut p = predicate.clone();
-            p.push(' ');
-            p.push(' ');
-            return p
-        }
-        pre.extend(url.chars());
-        if tags{
-            pre.insert(0,'<');
-            pre.push('>');
-            pre.push(' ');
-        }
-        pre
-    }else{
-        predicate.clone()
+    #[test]
+    fn numeric_keys_are_only_casted_when_they_are_numbers(){
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        let mut normalisation = KeyNormalisation::default();
+        normalisation.add("numeric");
+        let expression = normalisation.sql_expression("?1");
+        let equal = |left: &str, right: &str| -> bool{
+            db.query_row(&format!("SELECT {} = {};", expression, expression.replace("?1", "?2")), [left, right], |row| row.get(0)).unwrap()
+        };
+        assert!(equal("7", "7.0"));
+        assert!(equal(" 007 ", "7"));
+        assert!(equal("-1.5e2", "-150"));
+        assert!(equal("+.5", "0.5"));
+        // Dates and phone numbers are not compared by their first digits
+        assert!(!equal("2024-01-05", "2024-12-31"));
+        assert!(!equal("2024-01-05", "2024"));
+        assert!(!equal("91 555 1234", "91 000"));
+        assert!(!equal("1-2", "1"));
+        assert!(!equal("1e", "1"));
+        assert!(!equal("abc", "0"));
+        assert!(equal("2024-01-05", "2024-01-05"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn template_values_follow_iri_encoding(){
+        let template = "http://example.com/city/{}".to_string();
+        let input = vec!["San Sebastián".to_string()];
+        assert_eq!(format_uri(template.clone(), &input, IriEncoding::Encode).unwrap(), Some("http://example.com/city/San%20Sebastián".to_string()));
+        assert_eq!(format_uri(template.clone(), &input, IriEncoding::Skip).unwrap(), None);
+        assert!(format_uri(template.clone(), &input, IriEncoding::Fail).is_err());
+        assert_eq!(format_uri(template, &["Madrid".to_string()], IriEncoding::Fail).unwrap(), Some("http://example.com/city/Madrid".to_string()));
+        // The braces of a value are not placeholders
+        let values = ["{a}".to_string(), "b".to_string()];
+        assert_eq!(fill_template("{} and {}".to_string(), &values), "{a} and b");
+        assert_eq!(format_uri("http://example.com/{}/{}".to_string(), &values, IriEncoding::Skip).unwrap(), None);
+
+        let map = child_map(Vec::new());
+        let columns = vec![("city".to_string(), 0)].into_iter().collect::<HashMap<_, _>>();
+        let objects = vec![
+            Parts::Template{template: "{} city".to_string(), input_fields: vec!["city".to_string()]},
+            Parts::TermType("rr:Literal".to_string())
+        ];
+        let mut warn = false;
+        let term = term_from_object(&map, &objects, &["San Sebastián".to_string()], &columns, IriEncoding::Encode, &mut warn).unwrap();
+        assert_eq!(term, Some(Term::Literal{value: "San Sebastián city".to_string(), datatype: None}));
+    }
+
+    #[test]
+    fn rows_are_partitioned_without_gaps(){
+        assert_eq!(partition_rows(1, 10, 4), vec![(1, 10)]);
+        let ranges = partition_rows(1, 4 * MIN_PARTITION_ROWS + 3, 4);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].0, 1);
+        assert_eq!(ranges[3].1, 4 * MIN_PARTITION_ROWS + 3);
+        assert!(ranges.windows(2).all(|pair| pair[0].1 + 1 == pair[1].0));
+    }
+
+    #[test]
+    fn repeated_rows_are_skipped_across_ranges(){
+        let db = join_tables();
+        let columns = vec!["route".to_string()];
+        let id_col = column_positions(&columns);
+        let (first, last) = select_page("\"db-parent-CSV\"", &columns, (1, 3), &db).unwrap();
+        assert_eq!(last, Some(3));
+        let (second, _) = select_page("\"db-parent-CSV\"", &columns, (4, 6), &db).unwrap();
+        let routes = first.iter().chain(second.iter()).map(|row| row[id_col["route"]].clone()).collect::<Vec<_>>();
+        assert_eq!(routes, vec!["r1", "r2", "r3", "r4", "r5"]);
+        assert_eq!(select_page("\"db-parent-CSV\"", &columns, (7, 9), &db).unwrap().1, None);
+    }
+
+    #[test]
+    fn numeric_normalisation_only_casts_numbers(){
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        let mut normalisation = KeyNormalisation::default();
+        normalisation.add("numeric");
+        let equal = |a: &str, b: &str| -> bool {
+            let query = format!("SELECT {} = {}", normalisation.sql_expression(&format!("'{}'", a)), normalisation.sql_expression(&format!("'{}'", b)));
+            db.query_row(&query, [], |row| row.get(0)).unwrap()
+        };
+        assert!(equal("1", "1.0"));
+        assert!(equal("25", "2.5e1"));
+        assert!(!equal("abc", "xyz"));
+        assert!(!equal("abc", "0"));
+    }
+
+    #[test]
+    fn ordered_channel_follows_the_jobs(){
+        let triple = |value: &str| Triple{
+            subject: Term::Iri("http://example.com/a".to_string()),
+            predicate: Term::Iri("http://example.com/b".to_string()),
+            object: Term::Literal{value: value.to_string(), datatype: None},
+            graph: None
+        };
+        let (sender, mut receiver) = triple_channel(true);
+        let first = sender.job_sender(0).unwrap();
+        let second = sender.job_sender(1).unwrap();
+        // The second job finishes before the first one
+        second.send(vec![triple("2")]).unwrap();
+        second.send(Vec::new()).unwrap();
+        first.send(vec![triple("1")]).unwrap();
+        first.send(Vec::new()).unwrap();
+
+        assert_eq!(receiver.recv().unwrap(), (0, vec![triple("1")]));
+        assert!(receiver.recv().unwrap().1.is_empty());
+        assert_eq!(receiver.recv().unwrap(), (1, vec![triple("2")]));
+        assert!(receiver.recv().unwrap().1.is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+// Metadata of a run, written in Turtle next to the outputs: a VoID description of the dataset, with the number of
+// triples, subjects and objects and the partitions of its classes and properties, and a PROV-O activity with the
+// times of the run, the files it has used with their checksums and the version of rossete.
+use crate::ResultApp;
+use crate::rdf::{Term, Triple, RDF_TYPE};
+use crate::rdf::terms::escape_literal;
+use crate::rdf::runs::ExternalSort;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use sha2::{Digest, Sha256};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+const PREFIXES: [(&str, &str); 8] = [
+    ("void", "http://rdfs.org/ns/void#"),
+    ("prov", "http://www.w3.org/ns/prov#"),
+    ("dcterms", "http://purl.org/dc/terms/"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ("spdx", "http://spdx.org/rdf/terms#"),
+    ("doap", "http://usefulinc.com/ns/doap#"),
+    ("rossete", "https://github.com/RubenCid35/rossete-rdf/ns#")
+];
+
+// Counters of the triples written by a run. The subjects, the objects and the subjects of each class are kept as
+// N-Triples lines in external sorts, so a large run does not keep all its terms in memory and they are counted once.
+pub struct DatasetStatistics{
+    triples: u64,
+    subjects: ExternalSort,
+    objects: ExternalSort,
+    // Lines with the number of the class and the subject
+    class_subjects: ExternalSort,
+    classes: Vec<String>,
+    class_ids: HashMap<String, usize>,
+    // Triples of each property
+    properties: BTreeMap<String, u64>
+}
+
+// Distinct counts of the triples written by a run.
+pub struct DatasetCounts{
+    triples: u64,
+    subjects: u64,
+    objects: u64,
+    // Entities of each class
+    classes: BTreeMap<String, u64>,
+    properties: BTreeMap<String, u64>
+}
+
+impl DatasetStatistics{
+    pub fn new() -> Self{
+        Self{
+            triples: 0,
+            subjects: ExternalSort::new(),
+            objects: ExternalSort::new(),
+            class_subjects: ExternalSort::new(),
+            classes: Vec::new(),
+            class_ids: HashMap::new(),
+            properties: BTreeMap::new()
+        }
+    }
+
+    pub fn push(&mut self, triples: &[Triple]) -> ResultApp<()>{
+        for triple in triples.iter(){
+            self.triples += 1;
+            let subject = triple.subject.to_ntriples();
+            self.objects.push(triple.object.to_ntriples())?;
+            if let Term::Iri(predicate) = &triple.predicate{
+                *self.properties.entry(predicate.clone()).or_default() += 1;
+                if let (true, Term::Iri(class)) = (predicate == RDF_TYPE, &triple.object){
+                    let id = match self.class_ids.get(class){
+                        Some(&id) => id,
+                        None => {
+                            self.classes.push(class.clone());
+                            self.class_ids.insert(class.clone(), self.classes.len() - 1);
+                            self.classes.len() - 1
+                        }
+                    };
+                    self.class_subjects.push(format!("{} {}", id, subject))?;
+                }
+            }
+            self.subjects.push(subject)?;
+        }
+        Ok(())
+    }
+
+    // Sorts the lines and counts the distinct ones. The lines of a class are next to each other once they are sorted.
+    pub fn counts(self) -> ResultApp<DatasetCounts>{
+        let count = |sort: ExternalSort| -> ResultApp<u64>{
+            let mut count = 0;
+            for line in sort.into_sorted()?{
+                line?;
+                count += 1;
+            }
+            Ok(count)
+        };
+        let mut entities = vec![0; self.classes.len()];
+        for line in self.class_subjects.into_sorted()?{
+            let line = line?;
+            if let Some(id) = line.split(' ').next().and_then(|id| id.parse::<usize>().ok()){
+                entities[id] += 1;
+            }
+        }
+        Ok(DatasetCounts{
+            triples: self.triples,
+            subjects: count(self.subjects)?,
+            objects: count(self.objects)?,
+            classes: self.classes.into_iter().zip(entities).collect(),
+            properties: self.properties
+        })
+    }
+}
+
+// What the run has used and written, besides the triples.
+pub struct RunDescription{
+    pub started: DateTime<Local>,
+    pub ended: DateTime<Local>,
+    pub mapping_files: Vec<PathBuf>,
+    pub source_files: Vec<PathBuf>,
+    pub config_file: Option<PathBuf>,
+    // Files with the RDF of the dataset
+    pub dumps: Vec<PathBuf>,
+    pub arguments: Vec<String>
+}
+
+pub fn write_metadata(path: &Path, statistics: DatasetStatistics, run: &RunDescription) -> ResultApp<()>{
+    let statistics = statistics.counts()?;
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    for (name, url) in PREFIXES.iter(){
+        writeln!(out, "@prefix {}: <{}> .", name, url)?;
+    }
+    writeln!(out)?;
+
+    let time = |time: &DateTime<Local>| format!("\"{}\"^^xsd:dateTime", time.to_rfc3339_opts(SecondsFormat::Millis, false));
+    writeln!(out, "<#dataset> a void:Dataset, prov:Entity ;")?;
+    writeln!(out, "    dcterms:created {} ;", time(&run.ended))?;
+    writeln!(out, "    void:triples {} ;", statistics.triples)?;
+    writeln!(out, "    void:distinctSubjects {} ;", statistics.subjects)?;
+    writeln!(out, "    void:distinctObjects {} ;", statistics.objects)?;
+    writeln!(out, "    void:classes {} ;", statistics.classes.len())?;
+    writeln!(out, "    void:properties {} ;", statistics.properties.len())?;
+    for dump in run.dumps.iter(){
+        writeln!(out, "    void:dataDump {} ;", file_iri(dump)?)?;
+    }
+    for (class, entities) in statistics.classes.iter(){
+        writeln!(out, "    void:classPartition [ void:class {} ; void:entities {} ] ;", Term::Iri(class.clone()).to_ntriples(), entities)?;
+    }
+    for (property, triples) in statistics.properties.iter(){
+        writeln!(out, "    void:propertyPartition [ void:property {} ; void:triples {} ] ;", Term::Iri(property.clone()).to_ntriples(), triples)?;
+    }
+    writeln!(out, "    prov:wasGeneratedBy <#run> .")?;
+    writeln!(out)?;
+
+    let mut used = run.mapping_files.iter().map(|file| (file, "Mapping file")).collect::<Vec<_>>();
+    used.extend(run.source_files.iter().map(|file| (file, "Source file")));
+    used.extend(run.config_file.iter().map(|file| (file, "Configuration file")));
+    writeln!(out, "<#run> a prov:Activity ;")?;
+    writeln!(out, "    prov:startedAtTime {} ;", time(&run.started))?;
+    writeln!(out, "    prov:endedAtTime {} ;", time(&run.ended))?;
+    writeln!(out, "    prov:wasAssociatedWith <#rossete> ;")?;
+    for (file, _) in used.iter(){
+        writeln!(out, "    prov:used {} ;", file_iri(file)?)?;
+    }
+    writeln!(out, "    rossete:arguments \"{}\" .", escape_literal(&run.arguments.join(" ")))?;
+    writeln!(out)?;
+
+    writeln!(out, "<#rossete> a prov:SoftwareAgent, doap:Version ;")?;
+    writeln!(out, "    rdfs:label \"{}\" ;", env!("CARGO_PKG_NAME"))?;
+    writeln!(out, "    doap:revision \"{}\" ;", env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, "    rdfs:seeAlso <{}> .", env!("CARGO_PKG_REPOSITORY"))?;
+
+    for (file, role) in used.iter(){
+        writeln!(out)?;
+        writeln!(out, "{} a prov:Entity ;", file_iri(file)?)?;
+        writeln!(out, "    rdfs:comment \"{}\" ;", role)?;
+        writeln!(out, "    spdx:checksum [")?;
+        writeln!(out, "        a spdx:Checksum ;")?;
+        writeln!(out, "        spdx:algorithm spdx:checksumAlgorithm_sha256 ;")?;
+        writeln!(out, "        spdx:checksumValue \"{}\"", sha256(file)?)?;
+        writeln!(out, "    ] .")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// IRI of a file with its canonical path, so the same file always has the same IRI. If the path can not be resolved,
+// the . components are removed from the absolute path.
+fn file_iri(path: &Path) -> ResultApp<String>{
+    let path = match std::fs::canonicalize(path){
+        Ok(path) => path,
+        Err(_) => std::env::current_dir()?.join(path).components().filter(|component| *component != Component::CurDir).collect()
+    };
+    Ok(Term::Iri(format!("file://{}", path.display())).to_ntriples())
+}
+
+fn sha256(path: &Path) -> ResultApp<String>{
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop{
+        let read = file.read(&mut buffer)?;
+        if read == 0{
+            break
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod test_metadata{
+    use super::*;
+    use crate::rdf::reader;
+
+    #[test]
+    fn metadata_counts_the_triples_and_checksums_the_files(){
+        let iri = |name: &str| Term::Iri(format!("http://example.com/{}", name));
+        let triple = |s: &str, p: Term, o: Term| Triple{subject: iri(s), predicate: p, object: o, graph: None};
+        let statistics = || {
+            let mut statistics = DatasetStatistics::new();
+            statistics.push(&[
+                triple("ann", Term::Iri(RDF_TYPE.to_string()), iri("Person")),
+                triple("ann", iri("knows"), iri("bob")),
+                triple("bob", Term::Iri(RDF_TYPE.to_string()), iri("Person")),
+                triple("bob", iri("knows"), iri("ann")),
+                triple("ann", Term::Iri(RDF_TYPE.to_string()), iri("Person"))
+            ]).unwrap();
+            statistics
+        };
+        let counts = statistics().counts().unwrap();
+        assert_eq!((counts.triples, counts.subjects, counts.objects), (5, 2, 3));
+        assert_eq!(counts.classes["http://example.com/Person"], 2);
+
+        let dir = std::env::temp_dir();
+        let source = dir.join(format!("rossete-test-metadata-{}.csv", std::process::id()));
+        std::fs::write(&source, "abc").unwrap();
+        let path = dir.join(format!("rossete-test-metadata-{}.ttl", std::process::id()));
+        let now = Local::now();
+        let run = RunDescription{started: now, ended: now, mapping_files: Vec::new(), source_files: vec![source.clone()], config_file: None, dumps: Vec::new(), arguments: vec!["rossete-rdf".to_string()]};
+        write_metadata(&path, statistics(), &run).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        // The files are named by their canonical path
+        assert_eq!(file_iri(&dir.join(".").join(source.file_name().unwrap())).unwrap(), file_iri(&source).unwrap());
+        assert!(!file_iri(Path::new("./missing/./file.csv")).unwrap().contains("/./"));
+        assert!(text.contains("void:propertyPartition [ void:property <http://example.com/knows> ; void:triples 2 ]"));
+        assert!(text.contains("void:classPartition [ void:class <http://example.com/Person> ; void:entities 2 ]"));
+        assert!(text.contains("spdx:checksumValue \"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\""));
+        assert!(reader::read_turtle(&text, Some("file:///metadata.ttl")).is_ok());
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+
+use crate::ResultApp;
+use crate::config::{Compression, OutputFormat, OutputTarget};
+use crate::{warning, info};
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+// Size of the pieces of data sent to the compression thread
+const CHUNK_SIZE: usize = 64 * 1024;
+// Pieces that can wait to be compressed before the writer is blocked
+const PENDING_CHUNKS: usize = 16;
+
+// Number of the next file written for an upload, so the files of the same process do not collide.
+static NEXT_UPLOAD: AtomicUsize = AtomicUsize::new(0);
+
+// An output of the run. The files are written in a temporary file that only replaces the output file when all
+// the maps have succeeded, unless the partial output is kept. The uploads are also written in a temporary file,
+// which is sent when all the maps have succeeded.
+#[derive(Debug, Clone)]
+pub struct Output{
+    target: OutputTarget,
+    // None for the standard output
+    written_path: Option<PathBuf>
+}
+
+impl Output{
+    pub fn new(target: OutputTarget, keep_partial: bool) -> Self{
+        let written_path = match (target.path(), target.get_upload()){
+            (Some(path), _) if keep_partial => Some(path.clone()),
+            (Some(path), _) => Some(partial_path(path)),
+            (None, Some(_)) => Some(upload_path()),
+            (None, None) => None
+        };
+        Self{target, written_path}
+    }
+
+    pub fn get_format(&self) -> &OutputFormat{
+        self.target.get_format()
+    }
+
+    pub fn name(&self) -> String{
+        self.target.name()
+    }
+
+    // File where the output is written while the run has not finished
+    pub fn written_path(&self) -> Option<&PathBuf>{
+        self.written_path.as_ref()
+    }
+
+    // IRI of the file of the output. The standard output does not have one.
+    pub fn file_iri(&self) -> ResultApp<Option<String>>{
+        match self.target.path(){
+            Some(path) => Ok(Some(format!("file://{}", std::env::current_dir()?.join(path).display()))),
+            None => Ok(None)
+        }
+    }
+
+    pub fn open(&self) -> ResultApp<OutputWriter>{
+        let writer = match &self.written_path{
+            Some(path) => {
+                if self.target.path().map(|target| target.exists()).unwrap_or(false){
+                    warning!("It was found a file with the same name as the output file \"{}\", it will be overwritten", self.name());
+                }
+                let file = fs::OpenOptions::new().write(true).truncate(true).create(true).open(path)?;
+                Ok(OutputWriter::File(BufWriter::new(file)))
+            }
+            None => Ok(OutputWriter::Stdout(BufWriter::new(io::stdout())))
+        };
+        match self.target.get_compression(){
+            Some(compression) => Ok(OutputWriter::Compressed(CompressedWriter::new(compression, writer?))),
+            None => writer
+        }
+    }
+
+    // Moves the written file to the output file if the run has succeeded, or removes it otherwise. The uploads
+    // are sent and their file is removed in any case.
+    pub fn close(&self, succeeded: bool) -> ResultApp<()>{
+        if let (Some(store), Some(written_path)) = (self.target.get_upload(), &self.written_path){
+            let result = if succeeded{
+                info!("Uploading the output to {}", store.name());
+                store.upload(written_path, self.get_format())
+            }else{
+                warning!("The output was not uploaded to {} because the RDF could not be created", store.name());
+                Ok(())
+            };
+            if written_path.exists(){
+                fs::remove_file(written_path)?;
+            }
+            return result
+        }
+        let (written_path, path) = match (&self.written_path, self.target.path()){
+            (Some(written_path), Some(path)) if written_path != path => (written_path, path),
+            _ => return Ok(())
+        };
+        if succeeded{
+            replace_output(written_path, path)
+        }else{
+            warning!("The output file \"{}\" was not written because the RDF could not be created. Use --keep-partial to keep the partial output", path.display());
+            if written_path.exists(){
+                fs::remove_file(written_path)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub enum OutputWriter{
+    File(BufWriter<fs::File>),
+    Stdout(BufWriter<io::Stdout>),
+    Compressed(CompressedWriter)
+}
+
+impl OutputWriter{
+    // Flushes the output. The files are also synchronised with the disk, so the rename only happens with all the data.
+    pub fn finish(self) -> io::Result<()>{
+        match self{
+            Self::File(mut file) => {
+                file.flush()?;
+                file.get_ref().sync_all()?;
+            }
+            Self::Stdout(mut stdout) => stdout.flush()?,
+            Self::Compressed(compressed) => compressed.finish()?
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        match self{
+            Self::File(file) => file.write(buf),
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::Compressed(compressed) => compressed.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        match self{
+            Self::File(file) => file.flush(),
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::Compressed(compressed) => compressed.flush()
+        }
+    }
+}
+
+// Sends the data in chunks to a thread that compresses it into the output, so the creation of the RDF does not
+// wait for the compression.
+pub struct CompressedWriter{
+    chunk: Vec<u8>,
+    sender: Option<SyncSender<Vec<u8>>>,
+    compressor: Option<JoinHandle<io::Result<()>>>
+}
+
+impl CompressedWriter{
+    fn new(compression: Compression, output: OutputWriter) -> Self{
+        let (sender, receiver) = mpsc::sync_channel(PENDING_CHUNKS);
+        let compressor = thread::spawn(move || compress(compression, output, receiver));
+        Self{chunk: Vec::with_capacity(CHUNK_SIZE), sender: Some(sender), compressor: Some(compressor)}
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()>{
+        if self.chunk.is_empty(){
+            return Ok(())
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        let sent = self.sender.as_ref().map(|sender| sender.send(chunk).is_ok()).unwrap_or(false);
+        if sent{
+            Ok(())
+        }else{
+            // The compression thread has stopped, its error is the one that is given.
+            match self.compressor.take().map(join_compressor){
+                Some(Err(error)) => Err(error),
+                _ => Err(io::Error::new(io::ErrorKind::BrokenPipe, "The compression of the output has stopped"))
+            }
+        }
+    }
+
+    // Sends the last chunk and waits until the compressed output is complete.
+    fn finish(mut self) -> io::Result<()>{
+        self.send_chunk()?;
+        self.sender = None;
+        match self.compressor.take(){
+            Some(compressor) => join_compressor(compressor),
+            None => Ok(())
+        }
+    }
+}
+
+impl Write for CompressedWriter{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_SIZE{
+            self.send_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    // The data is only written when the chunk is full, so it is compressed in large blocks.
+    fn flush(&mut self) -> io::Result<()>{
+        Ok(())
+    }
+}
+
+fn join_compressor(compressor: JoinHandle<io::Result<()>>) -> io::Result<()>{
+    compressor.join().unwrap_or_else(|_| Err(io::Error::other("The compression of the output has panicked")))
+}
+
+fn compress(compression: Compression, output: OutputWriter, receiver: Receiver<Vec<u8>>) -> io::Result<()>{
+    let output = match compression{
+        Compression::Gzip => encode(flate2::write::GzEncoder::new(output, flate2::Compression::default()), receiver, |encoder| encoder.finish())?,
+        Compression::Zstd => encode(zstd::Encoder::new(output, 0)?, receiver, |encoder| encoder.finish())?,
+        Compression::Bzip2 => encode(bzip2::write::BzEncoder::new(output, bzip2::Compression::default()), receiver, |encoder| encoder.finish())?
+    };
+    output.finish()
+}
+
+fn encode<E: Write, W>(mut encoder: E, receiver: Receiver<Vec<u8>>, finish: impl FnOnce(E) -> io::Result<W>) -> io::Result<W>{
+    for chunk in receiver{
+        encoder.write_all(&chunk)?;
+    }
+    finish(encoder)
+}
+
+// Temporary file in the same directory as the output file, so it can be renamed without copying it.
+fn partial_path(output_path: &Path) -> PathBuf{
+    let name = output_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    output_path.with_file_name(format!(".{}.{}.partial", name, std::process::id()))
+}
+
+fn upload_path() -> PathBuf{
+    std::env::temp_dir().join(format!("rossete-upload-{}-{}", std::process::id(), NEXT_UPLOAD.fetch_add(1, Ordering::Relaxed)))
+}
+
+// Replaces the output file with the written one. The directory is also synchronised, so the rename is not lost.
+fn replace_output(written_path: &Path, output_path: &Path) -> ResultApp<()>{
+    fs::rename(written_path, output_path)?;
+    #[cfg(unix)]
+    {
+        let directory = match output_path.parent(){
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new(".")
+        };
+        fs::File::open(directory)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_output{
+    use super::*;
+
+    #[test]
+    fn partial_output_is_written_next_to_the_output(){
+        let partial = partial_path(Path::new("out/data.ttl"));
+        assert_eq!(partial.parent(), Some(Path::new("out")));
+        assert_ne!(partial.file_name().unwrap(), "data.ttl");
+        assert!(partial.file_name().unwrap().to_string_lossy().starts_with(".data.ttl."));
+    }
+
+    #[test]
+    fn outputs_take_the_format_of_their_extension(){
+        let stdout = Output::new(OutputTarget::new("-"), false);
+        assert!(matches!(stdout.get_format(), OutputFormat::Other));
+        assert!(stdout.written_path.is_none());
+
+        let turtle = Output::new(OutputTarget::new("data.TTL"), true);
+        assert!(turtle.get_format().is_ttl());
+        assert_eq!(turtle.written_path, Some(PathBuf::from("data.TTL")));
+    }
+
+    #[test]
+    fn compressed_outputs_are_complete(){
+        let target = OutputTarget::new(&std::env::temp_dir().join(format!("rossete-test-{}.nt.gz", std::process::id())).display().to_string());
+        assert!(target.get_format().is_nt());
+        assert_eq!(target.get_compression(), Some(Compression::Gzip));
+
+        let output = Output::new(target.clone(), false);
+        let mut writer = output.open().unwrap();
+        let line = "<http://example.com/a> <http://example.com/b> \"c\" .\n";
+        for _ in 0..10000{
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        output.close(true).unwrap();
+
+        let path = target.path().unwrap();
+        let mut content = String::new();
+        io::Read::read_to_string(&mut flate2::read::GzDecoder::new(fs::File::open(path).unwrap()), &mut content).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(content, line.repeat(10000));
+    }
+}
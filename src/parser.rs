@@ -4,7 +4,8 @@ use lazy_static::lazy_static;
 use crate::mappings::{
     parts::Parts,
     maps::Mapping,
-    AcceptedType
+    AcceptedType,
+    KeyNormalisation
 };
 
 use crate::ResultApp;
@@ -326,8 +327,8 @@ fn parse_logical_source(tokens: &Vec<String>, init: usize, end: usize, last_map:
         if SOURCE.is_match(&tokens[idx]){
             let p = tokens[idx + 1].replace('"', "");
             file_path = path::PathBuf::from(&p);
-            if let Some(ext) = file_path.extension(){
-                file_type = AcceptedType::from_str(ext.to_str().unwrap());
+            if file_path.extension().is_some(){
+                file_type = AcceptedType::from_path(&file_path);
             }
             idx += 1;
         }else if ITERATOR.is_match(&tokens[idx]){
@@ -364,9 +365,10 @@ fn parse_subject_map(tokens: &Vec<String>, init: usize, end: usize, last_map: &s
         lazy_static!{
             static ref TEMPLATE: Regex = Regex::new("rr:template").unwrap();
             static ref CONSTANT: Regex = Regex::new("rr:constant").unwrap();
-            static ref GRAPHMAP: Regex = Regex::new("rr:GraphMap").unwrap();
+            static ref GRAPHMAP: Regex = Regex::new("^rr:graph(Map)?$").unwrap();
             static ref CLASSTYPE: Regex = Regex::new("rr:class").unwrap();
             static ref TERMTYPE: Regex = Regex::new("rr:term[tT]ype").unwrap();
+            static ref REFERENCE: Regex = Regex::new("rml:reference").unwrap();
         }
         if TEMPLATE.is_match(&tokens[idx]){
             let (template, input_fields) = parse_input_field(&tokens[idx + 1], last_map)?;
@@ -377,26 +379,30 @@ fn parse_subject_map(tokens: &Vec<String>, init: usize, end: usize, last_map: &s
             idx += 1; 
         }
         else if GRAPHMAP.is_match(&tokens[idx]){
-            let comp: Parts;
-            if CONSTANT.is_match(&tokens[idx + 1]){
-                if tokens[idx + 2].contains('"'){
-                    comp = Parts::ConstantString(tokens[idx + 2].replace('"', ""));
-                }else{
-                    comp = Parts::ConstantTerm(tokens[idx + 2].clone());
-                }
-               idx += 2; 
-            }else{
-                comp = Parts::Term(tokens[idx + 1].clone());
-                idx += 1;
-            }
-
-            let graph = Parts::GraphMap(Box::new(comp));
+            let (graph, last) = parse_graph_map(tokens, idx, last_map)?;
             comps.push(graph);
+            idx = last;
         }
         else if CLASSTYPE.is_match(&tokens[idx]){
             comps.push(Parts::Class(tokens[idx + 1].clone()));
             idx += 1;
         }
+        else if TERMTYPE.is_match(&tokens[idx]){
+            comps.push(Parts::TermType(tokens[idx + 1].clone()));
+            idx += 1;
+        }
+        else if REFERENCE.is_match(&tokens[idx]){
+            comps.push(Parts::Reference(tokens[idx + 1].replace('"', "")));
+            idx += 1;
+        }
+        else if CONSTANT.is_match(&tokens[idx]){
+            if tokens[idx + 1].contains('"'){
+                comps.push(Parts::ConstantString(tokens[idx + 1].replace('"', "")));
+            }else{
+                comps.push(Parts::ConstantTerm(tokens[idx + 1].clone()));
+            }
+            idx += 1;
+        }
 
         idx += 1;
     }
@@ -406,6 +412,53 @@ fn parse_subject_map(tokens: &Vec<String>, init: usize, end: usize, last_map: &s
     })
 }
 
+// Parses a rr:graphMap with a constant, a template or a reference, or the rr:graph shortcut.
+// It returns the graph map and the index of its last token.
+fn parse_graph_map(tokens: &[String], init: usize, last_map: &str) -> ResultApp<(Parts, usize)>{
+    if init + 1 >= tokens.len(){
+        error!("A graph map without a graph has appeared. LAST MAP: {}", last_map);
+        return Err(ApplicationErrors::IncorrectMappingFormat)
+    }
+    if tokens[init + 1] != "["{
+        return Ok((Parts::GraphMap(Box::new(Parts::ConstantTerm(tokens[init + 1].clone()))), init + 1))
+    }
+
+    let end = match find_closing_bracket(&tokens.to_vec(), init + 1){
+        Some(end) => end,
+        None => {
+            error!("Missing Closing Bracket in a graph map in this map: {}", last_map);
+            return Err(ApplicationErrors::MissingClosingBracket)
+        }
+    };
+    let mut graph = None;
+    let mut i = init + 2;
+    while i + 1 < end{
+        match tokens[i].as_str(){
+            "rr:constant" if tokens[i + 1].contains('"') => graph = Some(Parts::ConstantString(tokens[i + 1].replace('"', ""))),
+            "rr:constant" => graph = Some(Parts::ConstantTerm(tokens[i + 1].clone())),
+            "rml:reference" => graph = Some(Parts::Reference(tokens[i + 1].replace('"', ""))),
+            "rr:template" => {
+                let (template, input_fields) = parse_input_field(&tokens[i + 1], last_map)?;
+                graph = Some(Parts::Template{template, input_fields});
+            }
+            _ => {
+                warning!("Some unknown tokens has appeared in a graph map, TOKEN: {} LAST MAP: {}", &tokens[i], last_map);
+                i += 1;
+                continue
+            }
+        }
+        i += 2;
+    }
+
+    match graph{
+        Some(graph) => Ok((Parts::GraphMap(Box::new(graph)), end)),
+        None => {
+            error!("The graph map requieres a rr:constant, rr:template or rml:reference. LAST MAP: {}", last_map);
+            Err(ApplicationErrors::IncorrectMappingFormat)
+        }
+    }
+}
+
 fn parse_input_field(elem_uri: &str, last_map: &str) -> ResultApp<(String, Vec<String>)>{
     let mut fields = Vec::new();
     let mut current_field = String::new();
@@ -466,6 +519,10 @@ fn parse_predicate_map(tokens: &Vec<String>, init: usize, end: usize, last_map:
                 object_map = vec![Parts::Term(tokens[i + 1].clone())];
                 i += 1;
             }
+        }else if tokens[i] == "rr:graphMap" || tokens[i] == "rr:graph"{
+            let (graph, last) = parse_graph_map(tokens, i, last_map)?;
+            object_map.push(graph);
+            i = last;
         }else{
             error!("Unknown Token has Appeared in a PredicateMap: {} LAST MAP: {}", &tokens[i], last_map);
             return Err(ApplicationErrors::IncorrectMappingFormat);
@@ -490,6 +547,7 @@ fn parse_object_map(tokens: &Vec<String>, init: usize, end: usize, last_map: &st
         static ref REFERENCE: Regex = Regex::new("rml:reference").unwrap();
         static ref TERMTYPE: Regex = Regex::new("rr:term[tT]ype").unwrap();
         static ref DATATYPE: Regex = Regex::new("rr:data[tT]ype").unwrap();    
+        static ref LANGUAGE: Regex = Regex::new("rr:language").unwrap();
         static ref TEMPLATE: Regex = Regex::new("rr:template").unwrap();    
     };
 
@@ -510,6 +568,9 @@ fn parse_object_map(tokens: &Vec<String>, init: usize, end: usize, last_map: &st
         else if DATATYPE.is_match(&tokens[i]){
             objs.push(Parts::DataType(tokens[i+1].clone()));
             i += 1;
+        }else if LANGUAGE.is_match(&tokens[i]){
+            objs.push(Parts::Language(tokens[i+1].replace('"', "")));
+            i += 1;
         }else if TERMTYPE.is_match(&tokens[i]){
             objs.push(Parts::TermType(tokens[i+1].clone()));
             i += 1;
@@ -556,14 +617,25 @@ fn parse_join_condition(tokens: &Vec<String>, init: usize, end: usize, last_map:
     lazy_static!{
         static ref CHILD: Regex = Regex::new("rr:child").unwrap();
         static ref PARENT_CON: Regex = Regex::new("rr:parent").unwrap();
+        static ref NORMALISE: Regex = Regex::new("rossete:normali[sz]e").unwrap();
     };
     let mut i = init;
     let mut child = String::new();
     let mut parent = String::new();
+    let mut normalisation = KeyNormalisation::default();
     while i < end{
         if CHILD.is_match(&tokens[i]){
             child = tokens[i + 1].replace('"', "");
             i += 1;
+        }else if NORMALISE.is_match(&tokens[i]){
+            // rossete:normalise "trim,lowercase"
+            for name in tokens[i + 1].replace('"', "").split(',').filter(|name| !name.is_empty()){
+                if !normalisation.add(name){
+                    error!("JOIN CONDITION ERROR: Unknown key normalisation: {}. Valid values: trim, lowercase, numeric. LAST MAP: {}", name, last_map);
+                    return Err(ApplicationErrors::IncorrectMappingFormat)
+                }
+            }
+            i += 1;
         }else if PARENT_CON.is_match(&tokens[i]){
             parent = tokens[i + 1].replace('"', "");
             i += 1;
@@ -574,7 +646,7 @@ fn parse_join_condition(tokens: &Vec<String>, init: usize, end: usize, last_map:
         i += 1;
     }
 
-    Ok(Parts::JoinCondition(child, parent))
+    Ok(Parts::JoinCondition(child, parent, normalisation))
 }
 
 
@@ -609,4 +681,30 @@ mod test_parser{
         let result = remove_comments(test);
         assert_eq!(&result, test);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn graph_maps_and_shortcuts(){
+        let tokens = ["rr:graphMap", "[", "rr:template", "\"http://ex.com/{year}\"", "]", "rr:graph", "ex:Graph"]
+            .iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let (graph, last) = super::parse_graph_map(&tokens, 0, "test").unwrap();
+        assert_eq!(last, 4);
+        assert!(matches!(graph, super::Parts::GraphMap(inner) if matches!(*inner, super::Parts::Template{..})));
+        let (graph, last) = super::parse_graph_map(&tokens, 5, "test").unwrap();
+        assert_eq!(last, 6);
+        assert!(matches!(graph, super::Parts::GraphMap(inner) if matches!(*inner, super::Parts::ConstantTerm(ref g) if g == "ex:Graph")));
+    }
+
+    #[test]
+    fn join_condition_normalisation(){
+        let tokens = ["rr:child", "\"city\"", "rr:parent", "\"name\"", "rossete:normalise", "\"trim,lowercase\""]
+            .iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        match super::parse_join_condition(&tokens, 0, tokens.len(), "test").unwrap(){
+            super::Parts::JoinCondition(child, parent, normalisation) => {
+                assert_eq!(child, "city");
+                assert_eq!(parent, "name");
+                assert!(normalisation.trim && normalisation.lowercase && !normalisation.numeric);
+            }
+            _ => panic!("A join condition was expected")
+        }
+    }
+}
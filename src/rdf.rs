@@ -0,0 +1,37 @@
+
+// RDF terms, the writers of each output syntax and the reader of Turtle
+pub mod terms;
+pub mod spool;
+pub mod runs;
+pub mod dedupe;
+pub mod changes;
+pub mod turtle;
+pub mod jsonld;
+pub mod rdfxml;
+pub mod hdt;
+pub mod store;
+pub mod reader;
+
+pub use terms::{Term, Triple, RDF_TYPE, to_ntriples, iri_safe, is_valid_iri};
+#[cfg(feature = "sparql")]
+pub use terms::percent_decode;
+
+use std::collections::HashMap;
+
+// Namespaces of the mapping languages. The generated triples do not use them.
+const MAPPING_NAMESPACES: [&str; 3] = [
+    "http://www.w3.org/ns/r2rml#",
+    "http://semweb.mmlab.be/ns/rml#",
+    "http://semweb.mmlab.be/ns/ql#"
+];
+
+// Prefixes of the mappings that can be used to compact the output, from the longest namespace to the shortest one.
+// The base, stored with an empty name, and the namespaces of the mapping languages are not included.
+pub fn output_prefixes(prefixes: &HashMap<String, String>) -> Vec<(String, String)>{
+    let mut compact = prefixes.iter()
+        .filter(|(name, url)| !name.is_empty() && !url.is_empty() && !MAPPING_NAMESPACES.contains(&url.as_str()))
+        .map(|(name, url)| (name.clone(), url.clone()))
+        .collect::<Vec<_>>();
+    compact.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    compact
+}
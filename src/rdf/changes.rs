@@ -0,0 +1,230 @@
+
+use crate::ResultApp;
+use crate::config::OutputFormat;
+use crate::errors::ApplicationErrors;
+use crate::{error, warning};
+use super::{reader, Term, Triple};
+use super::runs::{ExternalSort, SortedLines};
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+// Changes of each SPARQL Update operation, so the endpoints do not receive a single huge request.
+const UPDATE_BLOCK: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change{
+    Removed,
+    Added
+}
+
+// Writes the changes in one of the formats of the changes: RDF Patch, SPARQL Update or only the added or the
+// removed triples in N-Triples.
+pub struct ChangeWriter<'a>{
+    format: &'a OutputFormat,
+    out: &'a mut dyn Write,
+    // Changes in the open SPARQL Update operation and operations already written
+    block: usize,
+    operations: usize,
+    // Removed triples that can not be written in the SPARQL Update
+    skipped: usize
+}
+
+impl<'a> ChangeWriter<'a>{
+    pub fn new(format: &'a OutputFormat, out: &'a mut dyn Write) -> Self{
+        Self{format, out, block: 0, operations: 0, skipped: 0}
+    }
+
+    fn begin(&mut self) -> ResultApp<()>{
+        if let OutputFormat::RdfPatch = self.format{
+            writeln!(self.out, "TX .")?;
+        }
+        Ok(())
+    }
+
+    fn change(&mut self, change: Change, line: &str) -> ResultApp<()>{
+        match (self.format, change){
+            (OutputFormat::RdfPatch, Change::Removed) => writeln!(self.out, "D {}", line)?,
+            (OutputFormat::RdfPatch, Change::Added) => writeln!(self.out, "A {}", line)?,
+            (OutputFormat::SparqlUpdate, _) => {
+                // DELETE DATA does not accept blank nodes, they would not match the ones of the store.
+                if change == Change::Removed && has_blank_node(line){
+                    self.skipped += 1;
+                    return Ok(())
+                }
+                if self.block == 0{
+                    let separator = if self.operations > 0 { " ;\n" } else { "" };
+                    let operation = if change == Change::Removed { "DELETE" } else { "INSERT" };
+                    writeln!(self.out, "{}{} DATA {{", separator, operation)?;
+                }
+                writeln!(self.out, "  {}", line)?;
+                self.block += 1;
+                if self.block >= UPDATE_BLOCK{
+                    self.close_operation()?;
+                }
+            }
+            (OutputFormat::AddedTriples, Change::Added) | (OutputFormat::RemovedTriples, Change::Removed) => writeln!(self.out, "{}", line)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // The removed and the added triples are written in different operations.
+    fn close_operation(&mut self) -> ResultApp<()>{
+        if self.block > 0{
+            write!(self.out, "}}")?;
+            self.block = 0;
+            self.operations += 1;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> ResultApp<()>{
+        match self.format{
+            OutputFormat::RdfPatch => writeln!(self.out, "TC .")?,
+            OutputFormat::SparqlUpdate => {
+                self.close_operation()?;
+                if self.operations > 0{
+                    writeln!(self.out)?;
+                }
+                if self.skipped > 0{
+                    warning!("{} removed triples with blank nodes can not be deleted with SPARQL Update DELETE DATA, they were not written", self.skipped);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// Lines of the previous output in the same form as the lines of this run, sorted on disk. Each line is read as a
+// triple and written again, so the spaces, the escapes or an explicit xsd:string of an older output do not make it
+// differ from the same triple of this run.
+pub fn read_previous<R: Read>(previous: R) -> ResultApp<SortedLines>{
+    let mut lines = ExternalSort::new();
+    for (number, line) in BufReader::new(previous).lines().enumerate(){
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#'){
+            continue
+        }
+        let triples = match reader::read_turtle(line, None){
+            Ok(triples) if triples.len() == 1 => triples,
+            _ => {
+                error!("The line {} of the previous output is not a N-Triples triple: {}", number + 1, line);
+                return Err(ApplicationErrors::InvalidNTriplesLine)
+            }
+        };
+        for triple in triples.iter(){
+            lines.push(canonical_line(triple))?;
+        }
+    }
+    lines.into_sorted()
+}
+
+// Line of a triple as it is written by a run. The labels of the blank nodes are kept as they were read, as they are
+// already escaped. The maps give the same labels to the same rows, so they can be compared between runs.
+fn canonical_line(triple: &Triple) -> String{
+    let term = |term: &Term| match term{
+        Term::BlankNode(label) => format!("_:{}", label),
+        term => term.to_ntriples()
+    };
+    format!("{} {} {} .", term(&triple.subject), term(&triple.predicate), term(&triple.object))
+}
+
+// Compares the sorted lines of both runs and writes the changes. The removed triples go first, while the added
+// ones are kept on disk until all the removed have been written. It returns the number of removed and added triples.
+pub fn write_changes(mut previous: SortedLines, mut current: SortedLines, writers: &mut [ChangeWriter]) -> ResultApp<(usize, usize)>{
+    for writer in writers.iter_mut(){
+        writer.begin()?;
+    }
+
+    let mut added = ExternalSort::new();
+    let (mut num_removed, mut num_added) = (0, 0);
+    let mut old = previous.next().transpose()?;
+    let mut new = current.next().transpose()?;
+    loop{
+        match (old.take(), new.take()){
+            (None, None) => break,
+            (Some(line), next) if next.as_ref().is_none_or(|next| &line < next) => {
+                for writer in writers.iter_mut(){
+                    writer.change(Change::Removed, &line)?;
+                }
+                num_removed += 1;
+                old = previous.next().transpose()?;
+                new = next;
+            }
+            (next, Some(line)) if next.as_ref().is_none_or(|next| &line < next) => {
+                added.push(line)?;
+                num_added += 1;
+                old = next;
+                new = current.next().transpose()?;
+            }
+            _ => { // The triple is in both runs
+                old = previous.next().transpose()?;
+                new = current.next().transpose()?;
+            }
+        }
+    }
+
+    for writer in writers.iter_mut(){
+        writer.close_operation()?;
+    }
+    for line in added.into_sorted()?{
+        let line = line?;
+        for writer in writers.iter_mut(){
+            writer.change(Change::Added, &line)?;
+        }
+    }
+    for writer in writers.iter_mut(){
+        writer.end()?;
+    }
+    Ok((num_removed, num_added))
+}
+
+// The subject is the first term and the object goes after the predicate, which is always an IRI.
+fn has_blank_node(line: &str) -> bool{
+    let mut terms = line.splitn(3, ' ');
+    let subject = terms.next().unwrap_or_default();
+    let object = terms.nth(1).unwrap_or_default();
+    subject.starts_with("_:") || object.starts_with("_:")
+}
+
+#[cfg(test)]
+mod test_changes{
+    use super::*;
+
+    fn lines(lines: &[&str]) -> SortedLines{
+        let mut sort = ExternalSort::new();
+        for line in lines{
+            sort.push(line.to_string()).unwrap();
+        }
+        sort.into_sorted().unwrap()
+    }
+
+    #[test]
+    fn removed_triples_go_before_the_added_ones(){
+        let previous = read_previous("<http://example.com/a> <http://example.com/p> <http://example.com/b> .\n\n<http://example.com/a>\t<http://example.com/p>   \"x\"^^<http://www.w3.org/2001/XMLSchema#string>.\n_:n <http://example.com/p> <http://example.com/c> .\n".as_bytes()).unwrap();
+        let current = lines(&["<http://example.com/a> <http://example.com/p> \"x\" .", "<http://example.com/d> <http://example.com/p> <http://example.com/e> .", "<http://example.com/a> <http://example.com/p> <http://example.com/f> ."]);
+        let (patch_format, update_format) = (OutputFormat::RdfPatch, OutputFormat::SparqlUpdate);
+        let (mut patch, mut update) = (Vec::new(), Vec::new());
+        let mut writers = [ChangeWriter::new(&patch_format, &mut patch), ChangeWriter::new(&update_format, &mut update)];
+        assert_eq!(write_changes(previous, current, &mut writers).unwrap(), (2, 2));
+
+        assert_eq!(String::from_utf8(patch).unwrap(), "TX .\nD <http://example.com/a> <http://example.com/p> <http://example.com/b> .\nD _:n <http://example.com/p> <http://example.com/c> .\nA <http://example.com/a> <http://example.com/p> <http://example.com/f> .\nA <http://example.com/d> <http://example.com/p> <http://example.com/e> .\nTC .\n");
+        assert_eq!(String::from_utf8(update).unwrap(), "DELETE DATA {\n  <http://example.com/a> <http://example.com/p> <http://example.com/b> .\n} ;\nINSERT DATA {\n  <http://example.com/a> <http://example.com/p> <http://example.com/f> .\n  <http://example.com/d> <http://example.com/p> <http://example.com/e> .\n}\n");
+    }
+
+    #[test]
+    fn previous_lines_are_written_as_in_a_run(){
+        let triple = Triple{
+            subject: Term::BlankNode("map-1".to_string()),
+            predicate: Term::Iri("http://example.com/p".to_string()),
+            object: Term::Literal{value: "a\"b".to_string(), datatype: Some("http://www.w3.org/2001/XMLSchema#integer".to_string())},
+            graph: None
+        };
+        let written = format!("{}\n", triple.to_ntriples());
+        let read = read_previous(written.replace(' ', "  ").replace("\\\"", "\\u0022").as_bytes()).unwrap().collect::<std::io::Result<Vec<_>>>().unwrap();
+        assert_eq!(read, [triple.to_ntriples()]);
+        assert!(read_previous("<a> <p> .\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+
+use crate::ResultApp;
+use super::{Term, Triple};
+use super::runs::ExternalSort;
+
+use std::collections::HashSet;
+
+// Triples that are kept in memory to find the duplicates. The rest are sorted on disk.
+const MAX_SEEN: usize = 500_000;
+// Triples given at once when the runs are merged
+const MERGE_BATCH: usize = 10_000;
+// Triples of a streamed map that are remembered to remove the ones of its repeated rows
+const MAX_RECENT: usize = 100_000;
+
+// Removes the triples that were already given in the run. The first ones are kept in a hash set and the triples
+// are given back as soon as they arrive. When the set is full, the new triples are sorted on disk and given
+// without the duplicates at the end of the run.
+pub struct TripleDedupe{
+    seen: HashSet<Triple>,
+    max_seen: usize,
+    // Encoded triples that did not fit in the set
+    disk: ExternalSort
+}
+
+impl TripleDedupe{
+    pub fn new() -> Self{
+        Self{seen: HashSet::new(), max_seen: MAX_SEEN, disk: ExternalSort::new()}
+    }
+
+    // Triples that can be written now. The ones that are sent to disk are given by finish.
+    pub fn filter(&mut self, triples: Vec<Triple>) -> ResultApp<Vec<Triple>>{
+        let mut unique = Vec::with_capacity(triples.len());
+        for triple in triples{
+            if self.seen.contains(&triple){
+                continue
+            }
+            if self.seen.len() < self.max_seen{
+                self.seen.insert(triple.clone());
+                unique.push(triple);
+            }else{
+                self.disk.push(encode_triple(&triple))?;
+            }
+        }
+        Ok(unique)
+    }
+
+    // Calls the function with the triples sent to disk, without the duplicates.
+    pub fn finish<F>(self, mut batch_fn: F) -> ResultApp<()>
+    where F: FnMut(Vec<Triple>) -> ResultApp<()>{
+        let mut batch = Vec::with_capacity(MERGE_BATCH);
+        for line in self.disk.into_sorted()?{
+            batch.push(decode_triple(&line?));
+            if batch.len() >= MERGE_BATCH{
+                batch_fn(std::mem::replace(&mut batch, Vec::with_capacity(MERGE_BATCH)))?;
+            }
+        }
+        if !batch.is_empty(){
+            batch_fn(batch)?;
+        }
+        Ok(())
+    }
+}
+
+// Removes the triples that a map has given recently, with bounded memory. The triples are kept in two generations:
+// when the current one is full it replaces the previous one, so at least the last max_recent triples are remembered.
+// The repeated rows of a data file are usually close to each other, and --dedupe removes the rest.
+pub struct RecentTriples{
+    current: HashSet<Triple>,
+    previous: HashSet<Triple>,
+    max_recent: usize
+}
+
+impl RecentTriples{
+    pub fn new() -> Self{
+        Self{current: HashSet::new(), previous: HashSet::new(), max_recent: MAX_RECENT}
+    }
+
+    pub fn filter(&mut self, triples: Vec<Triple>) -> Vec<Triple>{
+        triples.into_iter()
+            .filter(|triple| {
+                if self.current.contains(triple) || self.previous.contains(triple){
+                    return false
+                }
+                if self.current.len() >= self.max_recent{
+                    self.previous = std::mem::take(&mut self.current);
+                }
+                self.current.insert(triple.clone());
+                true
+            })
+            .collect()
+    }
+}
+
+// A triple is written in a line with three tab separated fields for each term: the kind, the value and the
+// datatype or language. The tabs, new lines and backslashes of the values are escaped, so equal lines are equal triples.
+fn encode_triple(triple: &Triple) -> String{
+    let mut line = String::with_capacity(100);
+    encode_term(&mut line, Some(&triple.subject));
+    encode_term(&mut line, Some(&triple.predicate));
+    encode_term(&mut line, Some(&triple.object));
+    encode_term(&mut line, triple.graph.as_ref());
+    line.pop(); // Last tab
+    line
+}
+
+fn encode_term(line: &mut String, term: Option<&Term>){
+    let (kind, value, extra) = match term{
+        Some(Term::Iri(iri)) => ("I", iri.as_str(), ""),
+        Some(Term::BlankNode(label)) => ("B", label.as_str(), ""),
+        Some(Term::Literal{value, datatype: None}) => ("L", value.as_str(), ""),
+        Some(Term::Literal{value, datatype: Some(datatype)}) => ("T", value.as_str(), datatype.as_str()),
+        Some(Term::LangLiteral{value, language}) => ("G", value.as_str(), language.as_str()),
+        None => ("-", "", "")
+    };
+    for field in [kind, value, extra]{
+        for c in field.chars(){
+            match c{
+                '\\' => line.push_str("\\\\"),
+                '\t' => line.push_str("\\t"),
+                '\n' => line.push_str("\\n"),
+                c => line.push(c)
+            }
+        }
+        line.push('\t');
+    }
+}
+
+fn decode_triple(line: &str) -> Triple{
+    let mut fields = line.split('\t').map(unescape);
+    let mut term = || -> Option<Term>{
+        let (kind, value, extra) = (fields.next()?, fields.next()?, fields.next()?);
+        match kind.as_str(){
+            "I" => Some(Term::Iri(value)),
+            "B" => Some(Term::BlankNode(value)),
+            "L" => Some(Term::Literal{value, datatype: None}),
+            "T" => Some(Term::Literal{value, datatype: Some(extra)}),
+            "G" => Some(Term::LangLiteral{value, language: extra}),
+            _ => None
+        }
+    };
+    let (subject, predicate, object) = (term(), term(), term());
+    let graph = term();
+    Triple{
+        subject: subject.expect("Malformed triple in a deduplication run"),
+        predicate: predicate.expect("Malformed triple in a deduplication run"),
+        object: object.expect("Malformed triple in a deduplication run"),
+        graph
+    }
+}
+
+fn unescape(field: &str) -> String{
+    let mut value = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next(){
+        if c == '\\'{
+            match chars.next(){
+                Some('t') => value.push('\t'),
+                Some('n') => value.push('\n'),
+                Some(c) => value.push(c),
+                None => {}
+            }
+        }else{
+            value.push(c);
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod test_dedupe{
+    use super::*;
+
+    fn triple(object: Term, graph: Option<Term>) -> Triple{
+        Triple{
+            subject: Term::Iri("http://example.com/a".to_string()),
+            predicate: Term::Iri("http://example.com/b".to_string()),
+            object,
+            graph
+        }
+    }
+
+    #[test]
+    fn encoded_triples_are_decoded_back(){
+        let triples = [
+            triple(Term::Literal{value: "a\tb\\n\nc".to_string(), datatype: None}, None),
+            triple(Term::Literal{value: String::new(), datatype: Some("http://example.com/t".to_string())}, Some(Term::BlankNode("g".to_string()))),
+            triple(Term::LangLiteral{value: "x".to_string(), language: "en".to_string()}, Some(Term::Iri("http://example.com/g".to_string())))
+        ];
+        for triple in triples.iter(){
+            assert_eq!(&decode_triple(&encode_triple(triple)), triple);
+        }
+    }
+
+    #[test]
+    fn duplicates_are_removed_from_the_runs(){
+        let value = |n: usize| triple(Term::Literal{value: n.to_string(), datatype: None}, None);
+        let mut dedupe = TripleDedupe{seen: HashSet::new(), max_seen: 100, disk: ExternalSort::with_run_size(30)};
+        let mut given = Vec::new();
+        // More triples than the ones kept in memory, each of them given twice
+        let total = 250;
+        for start in (0..total).step_by(20){
+            let batch = (start..total.min(start + 20)).map(value).collect::<Vec<_>>();
+            given.extend(dedupe.filter(batch.clone()).unwrap());
+            given.extend(dedupe.filter(batch).unwrap());
+        }
+        assert_eq!(given.len(), 100);
+        dedupe.finish(|batch| {
+            given.extend(batch);
+            Ok(())
+        }).unwrap();
+        assert_eq!(given.len(), total);
+        assert_eq!(given.into_iter().collect::<HashSet<_>>().len(), total);
+    }
+
+    #[test]
+    fn recent_duplicates_are_removed(){
+        let value = |n: usize| triple(Term::Literal{value: n.to_string(), datatype: None}, None);
+        let mut recent = RecentTriples{current: HashSet::new(), previous: HashSet::new(), max_recent: 2};
+        assert_eq!(recent.filter(vec![value(0), value(1), value(0)]), [value(0), value(1)]);
+        // The previous generation is still remembered
+        assert_eq!(recent.filter(vec![value(2), value(1), value(3)]), [value(2), value(3)]);
+        // The first triples have been forgotten
+        assert_eq!(recent.filter(vec![value(4), value(0), value(3)]), [value(4), value(0)]);
+    }
+}
@@ -0,0 +1,431 @@
+
+use crate::ResultApp;
+use super::spool::TripleSpool;
+
+use crc::{Crc, CRC_8_SMBUS, CRC_16_ARC, CRC_32_ISCSI};
+
+use std::io::Write;
+
+// Checksums used by the HDT sections
+const CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_ARC);
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+// Strings of each block of a dictionary section. Only the first one of a block is written complete.
+const BLOCK_SIZE: usize = 16;
+
+// Types of the control information
+const GLOBAL: u8 = 1;
+const HEADER: u8 = 2;
+const DICTIONARY: u8 = 3;
+const TRIPLES: u8 = 4;
+
+// Sections of the four section dictionary, in the order they are written.
+const SECTIONS: [&str; 4] = ["hdt_shared", "hdt_subjects", "hdt_predicates", "hdt_objects"];
+
+// Writes HDT with a four section dictionary and bitmap triples in SPO order. The dictionary and the triples are
+// built with the database of the spool, so SQLite moves them to disk when they are large.
+pub struct HdtWriter{
+    base_uri: String
+}
+
+// Sizes of the dictionary and of the triples.
+struct Counts{
+    shared: i64,
+    subjects: i64,
+    predicates: i64,
+    objects: i64,
+    pairs: i64,
+    triples: i64
+}
+
+impl HdtWriter{
+    pub fn new(base_uri: &str) -> Self{
+        Self{base_uri: base_uri.to_string()}
+    }
+
+    pub fn write<W: Write>(&self, spool: &mut TripleSpool, out: &mut W) -> ResultApp<()>{
+        let db = spool.database()?;
+        build_dictionary(db)?;
+        let counts = Counts::read(db)?;
+
+        write_control(out, GLOBAL, "<http://purl.org/HDT/hdt#HDTv1>", &[])?;
+        let header = self.header(&counts);
+        write_control(out, HEADER, "ntriples", &[("length", header.len() as i64)])?;
+        out.write_all(header.as_bytes())?;
+
+        let elements = counts.shared + counts.subjects + counts.predicates + counts.objects;
+        write_control(out, DICTIONARY, "<http://purl.org/HDT/hdt#dictionaryFour>", &[("elements", elements)])?;
+        for section in SECTIONS.iter(){
+            write_section(db, section, out)?;
+        }
+
+        write_control(out, TRIPLES, "<http://purl.org/HDT/hdt#triplesBitmap>", &[("order", 1)])?; // SPO
+        write_triples(db, &counts, out)
+    }
+
+    // Description of the dataset in N-Triples.
+    fn header(&self, counts: &Counts) -> String{
+        let hdt = "http://purl.org/HDT/hdt#";
+        let void = "http://rdfs.org/ns/void#";
+        let dataset = format!("<{}>", self.base_uri);
+        let lines = [
+            (dataset.as_str(), format!("<{}>", super::RDF_TYPE), format!("<{}Dataset>", hdt)),
+            (dataset.as_str(), format!("<{}>", super::RDF_TYPE), format!("<{}Dataset>", void)),
+            (dataset.as_str(), format!("<{}triples>", void), format!("\"{}\"", counts.triples)),
+            (dataset.as_str(), format!("<{}properties>", void), format!("\"{}\"", counts.predicates)),
+            (dataset.as_str(), format!("<{}distinctSubjects>", void), format!("\"{}\"", counts.shared + counts.subjects)),
+            (dataset.as_str(), format!("<{}distinctObjects>", void), format!("\"{}\"", counts.shared + counts.objects)),
+            (dataset.as_str(), format!("<{}formatInformation>", hdt), "_:format".to_string()),
+            ("_:format", format!("<{}dictionary>", hdt), "_:dictionary".to_string()),
+            ("_:format", format!("<{}triples>", hdt), "_:triples".to_string()),
+            ("_:dictionary", "<http://purl.org/dc/terms/format>".to_string(), format!("<{}dictionaryFour>", hdt)),
+            ("_:dictionary", format!("<{}dictionarynumSharedSubjectObject>", hdt), format!("\"{}\"", counts.shared)),
+            ("_:triples", "<http://purl.org/dc/terms/format>".to_string(), format!("<{}triplesBitmap>", hdt)),
+            ("_:triples", format!("<{}triplesnumTriples>", hdt), format!("\"{}\"", counts.triples)),
+            ("_:triples", format!("<{}triplesOrder>", hdt), "\"SPO\"".to_string())
+        ];
+        lines.iter().map(|(s, p, o)| format!("{} {} {} .\n", s, p, o)).collect()
+    }
+}
+
+impl Counts{
+    fn read(db: &rusqlite::Connection) -> ResultApp<Self>{
+        let count = |query: &str| -> ResultApp<i64> { Ok(db.query_row(query, [], |row| row.get(0))?) };
+        Ok(Self{
+            shared: count("SELECT COUNT(*) FROM hdt_shared;")?,
+            subjects: count("SELECT COUNT(*) FROM hdt_subjects;")?,
+            predicates: count("SELECT COUNT(*) FROM hdt_predicates;")?,
+            objects: count("SELECT COUNT(*) FROM hdt_objects;")?,
+            pairs: count("SELECT COUNT(*) FROM (SELECT DISTINCT s, p FROM hdt_ids);")?,
+            triples: count("SELECT COUNT(*) FROM hdt_ids;")?
+        })
+    }
+}
+
+// Creates the four sections of the dictionary, sorted by their UTF-8 bytes, and the triples with the ids of their
+// terms. The shared terms are the ones used as subject and object, and they go first in both roles.
+fn build_dictionary(db: &rusqlite::Connection) -> ResultApp<()>{
+    db.execute_batch("
+        CREATE TABLE hdt_triples AS SELECT DISTINCT
+            CASE subject_kind WHEN 1 THEN '_:' || subject ELSE subject END AS s,
+            predicate AS p,
+            CASE object_kind
+                WHEN 0 THEN object
+                WHEN 1 THEN '_:' || object
+                WHEN 3 THEN '\"' || object || '\"@' || datatype
+                ELSE '\"' || object || '\"' || IFNULL('^^<' || datatype || '>', '')
+            END AS o
+        FROM triples;
+
+        CREATE TABLE hdt_shared (id INTEGER PRIMARY KEY, term TEXT UNIQUE);
+        CREATE TABLE hdt_subjects (id INTEGER PRIMARY KEY, term TEXT UNIQUE);
+        CREATE TABLE hdt_predicates (id INTEGER PRIMARY KEY, term TEXT UNIQUE);
+        CREATE TABLE hdt_objects (id INTEGER PRIMARY KEY, term TEXT UNIQUE);
+        INSERT INTO hdt_shared (term) SELECT s FROM hdt_triples INTERSECT SELECT o FROM hdt_triples ORDER BY 1;
+        INSERT INTO hdt_subjects (term) SELECT s FROM hdt_triples EXCEPT SELECT term FROM hdt_shared ORDER BY 1;
+        INSERT INTO hdt_predicates (term) SELECT DISTINCT p FROM hdt_triples ORDER BY 1;
+        INSERT INTO hdt_objects (term) SELECT o FROM hdt_triples EXCEPT SELECT term FROM hdt_shared ORDER BY 1;
+
+        CREATE TABLE hdt_ids (s INTEGER, p INTEGER, o INTEGER, PRIMARY KEY (s, p, o)) WITHOUT ROWID;
+        INSERT INTO hdt_ids SELECT
+            IFNULL(shared_s.id, subject.id + (SELECT COUNT(*) FROM hdt_shared)),
+            predicate.id,
+            IFNULL(shared_o.id, object.id + (SELECT COUNT(*) FROM hdt_shared))
+        FROM hdt_triples AS t
+        LEFT JOIN hdt_shared AS shared_s ON shared_s.term = t.s
+        LEFT JOIN hdt_subjects AS subject ON subject.term = t.s
+        JOIN hdt_predicates AS predicate ON predicate.term = t.p
+        LEFT JOIN hdt_shared AS shared_o ON shared_o.term = t.o
+        LEFT JOIN hdt_objects AS object ON object.term = t.o;
+        DROP TABLE hdt_triples;
+    ")?;
+    Ok(())
+}
+
+// Control information of a part of the file: type, format, properties and their CRC16.
+fn write_control<W: Write>(out: &mut W, kind: u8, format: &str, properties: &[(&str, i64)]) -> ResultApp<()>{
+    let mut control = b"$HDT".to_vec();
+    control.push(kind);
+    control.extend_from_slice(format.as_bytes());
+    control.push(0);
+    for (key, value) in properties.iter(){
+        control.extend_from_slice(format!("{}={};", key, value).as_bytes());
+    }
+    control.push(0);
+    control.extend_from_slice(&CRC16.checksum(&control).to_le_bytes());
+    out.write_all(&control)?;
+    Ok(())
+}
+
+// Plain front coding section. The offsets of the blocks and the text are written with their own passes over the
+// section, so it is never kept in memory.
+fn write_section<W: Write>(db: &rusqlite::Connection, table: &str, out: &mut W) -> ResultApp<()>{
+    let mut strings = 0;
+    let mut length = 0;
+    let mut blocks = 0;
+    for_each_encoded(db, table, |start, bytes| {
+        strings += 1;
+        length += bytes.len() as u64;
+        blocks += start as u64;
+        Ok(())
+    })?;
+
+    let mut section = vec![2]; // Plain front coding
+    vbyte(&mut section, strings);
+    vbyte(&mut section, length);
+    vbyte(&mut section, BLOCK_SIZE as u64);
+    section.push(CRC8.checksum(&section));
+    out.write_all(&section)?;
+
+    // The offsets of the blocks end with the length of the text.
+    let numbits = bits(length);
+    let mut offsets = BitWriter::sequence(out, numbits, blocks + 1)?;
+    let mut offset = 0;
+    for_each_encoded(db, table, |start, bytes| {
+        if start{
+            offsets.push(offset, numbits)?;
+        }
+        offset += bytes.len() as u64;
+        Ok(())
+    })?;
+    offsets.push(length, numbits)?;
+    offsets.finish()?;
+
+    let mut digest = CRC32.digest();
+    for_each_encoded(db, table, |_, bytes| {
+        digest.update(bytes);
+        out.write_all(bytes)?;
+        Ok(())
+    })?;
+    out.write_all(&digest.finalize().to_le_bytes())?;
+    Ok(())
+}
+
+// Calls the function with the encoded bytes of each string of a section and if the string starts a block.
+fn for_each_encoded<F>(db: &rusqlite::Connection, table: &str, mut string_fn: F) -> ResultApp<()>
+where F: FnMut(bool, &[u8]) -> ResultApp<()>{
+    let mut smt = db.prepare(&format!("SELECT term FROM {} ORDER BY id;", table))?;
+    let mut rows = smt.query([])?;
+    let mut previous = Vec::new();
+    let mut encoded = Vec::new();
+    let mut position = 0;
+    while let Some(row) = rows.next()?{
+        let term: String = row.get(0)?;
+        let term = term.as_bytes();
+        let start = position % BLOCK_SIZE == 0;
+        encoded.clear();
+        if start{
+            encoded.extend_from_slice(term);
+        }else{
+            let common = previous.iter().zip(term.iter()).take_while(|(a, b)| a == b).count();
+            vbyte(&mut encoded, common as u64);
+            encoded.extend_from_slice(&term[common..]);
+        }
+        encoded.push(0);
+        string_fn(start, &encoded)?;
+
+        previous.clear();
+        previous.extend_from_slice(term);
+        position += 1;
+    }
+    Ok(())
+}
+
+// Bitmap triples: the bitmaps mark the last predicate of each subject and the last object of each pair, and the
+// sequences have the ids of the predicates and the objects.
+fn write_triples<W: Write>(db: &rusqlite::Connection, counts: &Counts, out: &mut W) -> ResultApp<()>{
+    let pairs = "SELECT DISTINCT s, p FROM hdt_ids ORDER BY s, p;";
+    let triples = "SELECT s, p, o FROM hdt_ids ORDER BY s, p, o;";
+
+    let mut bitmap_y = BitWriter::bitmap(out, counts.pairs as u64)?;
+    let mut previous: Option<i64> = None;
+    scan(db, pairs, |row| {
+        if let Some(subject) = previous{
+            bitmap_y.push((subject != row[0]) as u64, 1)?;
+        }
+        previous = Some(row[0]);
+        Ok(())
+    })?;
+    if previous.is_some(){
+        bitmap_y.push(1, 1)?;
+    }
+    bitmap_y.finish()?;
+
+    let mut bitmap_z = BitWriter::bitmap(out, counts.triples as u64)?;
+    let mut previous: Option<(i64, i64)> = None;
+    scan(db, triples, |row| {
+        if let Some(pair) = previous{
+            bitmap_z.push((pair != (row[0], row[1])) as u64, 1)?;
+        }
+        previous = Some((row[0], row[1]));
+        Ok(())
+    })?;
+    if previous.is_some(){
+        bitmap_z.push(1, 1)?;
+    }
+    bitmap_z.finish()?;
+
+    let numbits = bits(counts.predicates as u64);
+    let mut sequence_y = BitWriter::sequence(out, numbits, counts.pairs as u64)?;
+    scan(db, pairs, |row| sequence_y.push(row[1] as u64, numbits))?;
+    sequence_y.finish()?;
+
+    let numbits = bits((counts.shared + counts.objects) as u64);
+    let mut sequence_z = BitWriter::sequence(out, numbits, counts.triples as u64)?;
+    scan(db, triples, |row| sequence_z.push(row[2] as u64, numbits))?;
+    sequence_z.finish()
+}
+
+fn scan<F>(db: &rusqlite::Connection, query: &str, mut row_fn: F) -> ResultApp<()>
+where F: FnMut([i64; 3]) -> ResultApp<()>{
+    let mut smt = db.prepare(query)?;
+    let columns = smt.column_count();
+    let mut rows = smt.query([])?;
+    while let Some(row) = rows.next()?{
+        let mut values = [0; 3];
+        for (i, value) in values.iter_mut().enumerate().take(columns){
+            *value = row.get(i)?;
+        }
+        row_fn(values)?;
+    }
+    Ok(())
+}
+
+// Writes the data of a bitmap or a log sequence. The values are packed from the lowest bit of each byte.
+struct BitWriter<'a, W: Write>{
+    out: &'a mut W,
+    digest: crc::Digest<'static, u32>,
+    buffer: Vec<u8>,
+    current: u128,
+    used: u32
+}
+
+impl<'a, W: Write> BitWriter<'a, W>{
+    fn bitmap(out: &'a mut W, numbits: u64) -> ResultApp<Self>{
+        let mut preamble = vec![1]; // Plain bitmap
+        vbyte(&mut preamble, numbits);
+        Self::new(out, preamble)
+    }
+
+    fn sequence(out: &'a mut W, numbits: u32, entries: u64) -> ResultApp<Self>{
+        let mut preamble = vec![1, numbits as u8]; // Log sequence
+        vbyte(&mut preamble, entries);
+        Self::new(out, preamble)
+    }
+
+    fn new(out: &'a mut W, mut preamble: Vec<u8>) -> ResultApp<Self>{
+        preamble.push(CRC8.checksum(&preamble));
+        out.write_all(&preamble)?;
+        Ok(Self{out, digest: CRC32.digest(), buffer: Vec::with_capacity(1 << 16), current: 0, used: 0})
+    }
+
+    fn push(&mut self, value: u64, numbits: u32) -> ResultApp<()>{
+        self.current |= (value as u128) << self.used;
+        self.used += numbits;
+        while self.used >= 8{
+            self.buffer.push(self.current as u8);
+            self.current >>= 8;
+            self.used -= 8;
+        }
+        if self.buffer.len() >= 1 << 16{
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> ResultApp<()>{
+        self.digest.update(&self.buffer);
+        self.out.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> ResultApp<()>{
+        if self.used > 0{
+            self.buffer.push(self.current as u8);
+        }
+        self.flush()?;
+        self.out.write_all(&self.digest.finalize().to_le_bytes())?;
+        Ok(())
+    }
+}
+
+// Variable length numbers of HDT: 7 bits per byte from the lowest ones, and the last byte has the highest bit set.
+fn vbyte(out: &mut Vec<u8>, mut value: u64){
+    while value > 127{
+        out.push((value & 127) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8 | 0x80);
+}
+
+// Number of bits needed to write the value.
+fn bits(value: u64) -> u32{
+    64 - value.leading_zeros()
+}
+
+#[cfg(test)]
+mod test_hdt{
+    use super::*;
+    use crate::rdf::{Term, Triple};
+
+    fn spool() -> TripleSpool{
+        let iri = |value: &str| Term::Iri(format!("http://example.com/{}", value));
+        let literal = Term::Literal{value: "x".to_string(), datatype: None};
+        let mut spool = TripleSpool::new().unwrap();
+        spool.push(&[
+            Triple{subject: iri("a"), predicate: iri("p1"), object: iri("b"), graph: None},
+            Triple{subject: iri("a"), predicate: iri("p2"), object: literal.clone(), graph: None},
+            Triple{subject: iri("b"), predicate: iri("p1"), object: literal.clone(), graph: None},
+            Triple{subject: iri("b"), predicate: iri("p1"), object: literal, graph: None}
+        ]).unwrap();
+        spool
+    }
+
+    #[test]
+    fn vbytes_mark_the_last_byte(){
+        let mut out = Vec::new();
+        vbyte(&mut out, 3);
+        vbyte(&mut out, 300);
+        assert_eq!(out, vec![0x83, 0x2C, 0x82]);
+    }
+
+    #[test]
+    fn sections_use_front_coding(){
+        let mut spool = spool();
+        let db = spool.database().unwrap();
+        build_dictionary(db).unwrap();
+        let mut strings = Vec::new();
+        for_each_encoded(db, "hdt_predicates", |start, bytes| {
+            strings.push((start, bytes.to_vec()));
+            Ok(())
+        }).unwrap();
+        assert_eq!(strings, vec![
+            (true, b"http://example.com/p1\0".to_vec()),
+            (false, vec![0x80 | 20, b'2', 0])
+        ]);
+    }
+
+    #[test]
+    fn bitmap_triples_follow_the_dictionary(){
+        let mut spool = spool();
+        let db = spool.database().unwrap();
+        build_dictionary(db).unwrap();
+        let counts = Counts::read(db).unwrap();
+        assert_eq!((counts.shared, counts.subjects, counts.predicates, counts.objects), (1, 1, 2, 1));
+
+        // Triples: b p1 "x" (1 1 2), a p1 b (2 1 1) and a p2 "x" (2 2 2)
+        let mut out = Vec::new();
+        write_triples(db, &counts, &mut out).unwrap();
+        let data = |start: usize, preamble: &[u8]| -> Vec<u8>{
+            assert_eq!(&out[start..start + preamble.len()], preamble);
+            out[start + preamble.len() + 1..start + preamble.len() + 2].to_vec()
+        };
+        assert_eq!(data(0, &[1, 0x83]), vec![0b101]);
+        assert_eq!(data(8, &[1, 0x83]), vec![0b111]);
+        assert_eq!(data(16, &[1, 2, 0x83]), vec![0b10_01_01]);
+        assert_eq!(data(25, &[1, 2, 0x83]), vec![0b10_01_10]);
+        assert_eq!(out.len(), 34);
+    }
+}
@@ -0,0 +1,229 @@
+
+use crate::ResultApp;
+use crate::config::JsonLdForm;
+use super::{Term, Triple, RDF_TYPE};
+use super::spool::TripleSpool;
+
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::io::Write;
+
+// Writes JSON-LD with a node object for each subject. The named graphs are written as nodes with a @graph.
+pub struct JsonLdWriter{
+    // Prefix name (without the colon) and namespace, from the longest namespace to the shortest one.
+    prefixes: Vec<(String, String)>,
+    form: JsonLdForm
+}
+
+// Part of the document that is being written: the graph and the node of the last triples.
+struct Document{
+    graph: Option<Option<Term>>,
+    node: Option<(Term, Map<String, Value>)>,
+    // There are no items written yet in the top level array and in the array of the named graph
+    first_top: bool,
+    first_named: bool
+}
+
+impl JsonLdWriter{
+    pub fn new(prefixes: &HashMap<String, String>, form: JsonLdForm) -> Self{
+        let prefixes = super::output_prefixes(prefixes).into_iter()
+            .map(|(name, url)| (name.trim_end_matches(':').to_string(), url))
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        Self{prefixes, form}
+    }
+
+    // Writes all the triples of the spool. Only one node is kept in memory, the rest is written as soon as it is complete.
+    pub fn write<W: Write>(&self, spool: &mut TripleSpool, out: &mut W) -> ResultApp<()>{
+        if self.form == JsonLdForm::Compacted{
+            let context = self.prefixes.iter()
+                .map(|(name, url)| (name.clone(), Value::String(url.clone())))
+                .collect::<Map<_, _>>();
+            write!(out, "{{\n  \"@context\": {},\n  \"@graph\": [", Value::Object(context))?;
+        }else{
+            out.write_all(b"[")?;
+        }
+
+        let mut document = Document{graph: None, node: None, first_top: true, first_named: true};
+        spool.for_each_sorted(true, |triple| self.push(&mut document, triple, out))?;
+        self.write_node(&mut document, out)?;
+        if matches!(document.graph, Some(Some(_))){
+            write!(out, "\n{}]}}", self.indent(0))?;
+        }
+
+        if self.form == JsonLdForm::Compacted{
+            out.write_all(b"\n  ]\n}\n")?;
+        }else{
+            out.write_all(b"\n]\n")?;
+        }
+        Ok(())
+    }
+
+    fn push<W: Write>(&self, document: &mut Document, triple: Triple, out: &mut W) -> ResultApp<()>{
+        if document.graph.as_ref() != Some(&triple.graph){
+            self.write_node(document, out)?;
+            if matches!(document.graph, Some(Some(_))){
+                write!(out, "\n{}]}}", self.indent(0))?;
+            }
+            if let Some(graph) = &triple.graph{
+                let separator = if document.first_top { "" } else { "," };
+                write!(out, "{}\n{}{{\"@id\": {}, \"@graph\": [", separator, self.indent(0), Value::String(self.id(graph)))?;
+                document.first_top = false;
+                document.first_named = true;
+            }
+            document.graph = Some(triple.graph.clone());
+        }
+
+        if !matches!(&document.node, Some((subject, _)) if *subject == triple.subject){
+            self.write_node(document, out)?;
+            let mut node = Map::new();
+            node.insert("@id".to_string(), Value::String(self.id(&triple.subject)));
+            document.node = Some((triple.subject.clone(), node));
+        }
+
+        let (key, value) = match (&triple.predicate, &triple.object){
+            (Term::Iri(predicate), object @ (Term::Iri(_) | Term::BlankNode(_))) if predicate == RDF_TYPE => {
+                ("@type".to_string(), Value::String(self.id(object)))
+            }
+            (predicate, object) => (self.id(predicate), self.value(object))
+        };
+        if let Some((_, node)) = document.node.as_mut(){
+            match node.entry(key).or_insert_with(|| Value::Array(Vec::new())){
+                Value::Array(values) => values.push(value),
+                _ => unreachable!("The values of a node are always stored in arrays")
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the current node. The compacted form uses a single value instead of an array when it is possible.
+    fn write_node<W: Write>(&self, document: &mut Document, out: &mut W) -> ResultApp<()>{
+        let (_, mut node) = match document.node.take(){
+            Some(node) => node,
+            None => return Ok(())
+        };
+        if self.form == JsonLdForm::Compacted{
+            for value in node.values_mut(){
+                if matches!(value, Value::Array(values) if values.len() == 1){
+                    *value = value.as_array_mut().unwrap().remove(0);
+                }
+            }
+        }
+
+        let (first, level) = if matches!(document.graph, Some(Some(_))){
+            (&mut document.first_named, 1)
+        }else{
+            (&mut document.first_top, 0)
+        };
+        let separator = if *first { "" } else { "," };
+        *first = false;
+        write!(out, "{}\n{}{}", separator, self.indent(level), Value::Object(node))?;
+        Ok(())
+    }
+
+    fn indent(&self, level: usize) -> String{
+        let base = if self.form == JsonLdForm::Compacted { 4 } else { 2 };
+        " ".repeat(base + level * 2)
+    }
+
+    fn value(&self, term: &Term) -> Value{
+        let mut value = Map::new();
+        match term{
+            Term::Iri(_) | Term::BlankNode(_) => {
+                value.insert("@id".to_string(), Value::String(self.id(term)));
+            }
+            Term::Literal{value: literal, datatype: None} if self.form == JsonLdForm::Compacted => {
+                return Value::String(literal.clone())
+            }
+            Term::Literal{value: literal, datatype} => {
+                value.insert("@value".to_string(), Value::String(literal.clone()));
+                if let Some(datatype) = datatype{
+                    value.insert("@type".to_string(), Value::String(self.iri(datatype)));
+                }
+            }
+            Term::LangLiteral{value: literal, language} => {
+                value.insert("@value".to_string(), Value::String(literal.clone()));
+                value.insert("@language".to_string(), Value::String(language.clone()));
+            }
+        }
+        Value::Object(value)
+    }
+
+    fn id(&self, term: &Term) -> String{
+        match term{
+            Term::Iri(iri) => self.iri(iri),
+            term => term.to_ntriples()
+        }
+    }
+
+    // The compacted form uses compact IRIs, unless the rest of the IRI could be read as an absolute IRI.
+    fn iri(&self, iri: &str) -> String{
+        if self.form == JsonLdForm::Compacted{
+            for (name, url) in self.prefixes.iter(){
+                if let Some(local) = iri.strip_prefix(url.as_str()){
+                    if !local.is_empty() && !local.starts_with("//"){
+                        return format!("{}:{}", name, local)
+                    }
+                }
+            }
+        }
+        iri.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test_jsonld{
+    use super::*;
+
+    fn spool() -> TripleSpool{
+        let iri = |value: &str| Term::Iri(format!("http://example.com/{}", value));
+        let mut spool = TripleSpool::new().unwrap();
+        spool.push(&[
+            Triple{subject: iri("a"), predicate: iri("name"), object: Term::LangLiteral{value: "Ana".to_string(), language: "es".to_string()}, graph: None},
+            Triple{subject: iri("a"), predicate: Term::Iri(RDF_TYPE.to_string()), object: iri("Person"), graph: None},
+            Triple{subject: iri("b"), predicate: iri("age"), object: Term::Literal{
+                value: "3".to_string(), datatype: Some("http://www.w3.org/2001/XMLSchema#int".to_string())
+            }, graph: Some(iri("g"))},
+            Triple{subject: iri("a"), predicate: iri("knows"), object: iri("b"), graph: None},
+            Triple{subject: iri("a"), predicate: iri("knows"), object: iri("c"), graph: None}
+        ]).unwrap();
+        spool
+    }
+
+    fn write(form: JsonLdForm) -> Value{
+        let prefixes = vec![
+            ("ex:".to_string(), "http://example.com/".to_string()),
+            ("xsd:".to_string(), "http://www.w3.org/2001/XMLSchema#".to_string())
+        ].into_iter().collect();
+        let mut out = Vec::new();
+        JsonLdWriter::new(&prefixes, form).write(&mut spool(), &mut out).unwrap();
+        serde_json::from_slice(&out).expect("The output is not valid JSON")
+    }
+
+    #[test]
+    fn compacted_form_uses_the_context(){
+        let document = write(JsonLdForm::Compacted);
+        assert_eq!(document["@context"]["ex"], "http://example.com/");
+        assert_eq!(document["@graph"][0], serde_json::json!({
+            "@id": "ex:a",
+            "@type": "ex:Person",
+            "ex:knows": [{"@id": "ex:b"}, {"@id": "ex:c"}],
+            "ex:name": {"@value": "Ana", "@language": "es"}
+        }));
+        assert_eq!(document["@graph"][1], serde_json::json!({
+            "@id": "ex:g",
+            "@graph": [{"@id": "ex:b", "ex:age": {"@value": "3", "@type": "xsd:int"}}]
+        }));
+    }
+
+    #[test]
+    fn expanded_form_uses_full_iris(){
+        let document = write(JsonLdForm::Expanded);
+        assert_eq!(document.as_array().unwrap().len(), 2);
+        assert_eq!(document[0]["@type"], serde_json::json!(["http://example.com/Person"]));
+        assert_eq!(document[1]["@graph"][0]["http://example.com/age"], serde_json::json!([
+            {"@value": "3", "@type": "http://www.w3.org/2001/XMLSchema#int"}
+        ]));
+    }
+}
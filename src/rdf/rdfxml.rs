@@ -0,0 +1,206 @@
+
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::error;
+use super::Term;
+use super::terms::escape_iri;
+use super::spool::TripleSpool;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+// Writes RDF/XML with a rdf:Description for each subject. The predicates are written as QNames of the namespaces
+// of the mappings, so a predicate that is not inside any of them can not be written.
+pub struct RdfXmlWriter{
+    // Prefix name (without the colon) and namespace, from the longest namespace to the shortest one.
+    prefixes: Vec<(String, String)>
+}
+
+impl RdfXmlWriter{
+    pub fn new(prefixes: &HashMap<String, String>) -> Self{
+        let mut namespaces = super::output_prefixes(prefixes).into_iter()
+            .map(|(name, url)| (name.trim_end_matches(':').to_string(), url))
+            .filter(|(name, url)| is_ncname(name) && !name.to_lowercase().starts_with("xml") && name != "rdf" && url != RDF_NAMESPACE)
+            .collect::<Vec<_>>();
+        // The rdf namespace is always needed for the elements of the syntax.
+        let position = namespaces.iter().position(|(_, url)| url.len() < RDF_NAMESPACE.len()).unwrap_or(namespaces.len());
+        namespaces.insert(position, ("rdf".to_string(), RDF_NAMESPACE.to_string()));
+        Self{prefixes: namespaces}
+    }
+
+    pub fn write<W: Write>(&self, spool: &mut TripleSpool, out: &mut W) -> ResultApp<()>{
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        write!(out, "<rdf:RDF")?;
+        let mut namespaces = self.prefixes.iter().collect::<Vec<_>>();
+        namespaces.sort();
+        for (name, url) in namespaces{
+            write!(out, "\n\txmlns:{}=\"{}\"", name, escape_xml(url, true)?)?;
+        }
+        writeln!(out, ">")?;
+
+        let mut last: Option<Term> = None; // Subject of the previous triple
+        spool.for_each_sorted(false, |triple| {
+            let mut buffer = String::with_capacity(200);
+            if last.as_ref() != Some(&triple.subject){
+                if last.is_some(){
+                    buffer.push_str("\t</rdf:Description>\n");
+                }
+                buffer.push_str(&format!("\n\t<rdf:Description {}>\n", self.node_attribute(&triple.subject, "about")?));
+            }
+
+            let predicate = match &triple.predicate{
+                Term::Iri(iri) => self.qname(iri)?,
+                predicate => {
+                    error!("The predicate {} is not an IRI, it can not be written in RDF/XML", predicate.to_ntriples());
+                    return Err(ApplicationErrors::InvalidQName)
+                }
+            };
+            match &triple.object{
+                object @ (Term::Iri(_) | Term::BlankNode(_)) => {
+                    buffer.push_str(&format!("\t\t<{} {}/>\n", predicate, self.node_attribute(object, "resource")?));
+                }
+                Term::Literal{value, datatype} => {
+                    let datatype = match datatype{
+                        Some(datatype) => format!(" rdf:datatype=\"{}\"", escape_xml(&escape_iri(datatype), true)?),
+                        None => String::new()
+                    };
+                    buffer.push_str(&format!("\t\t<{0}{1}>{2}</{0}>\n", predicate, datatype, escape_xml(value, false)?));
+                }
+                Term::LangLiteral{value, language} => {
+                    buffer.push_str(&format!("\t\t<{0} xml:lang=\"{1}\">{2}</{0}>\n", predicate, escape_xml(language, true)?, escape_xml(value, false)?));
+                }
+            }
+            out.write_all(buffer.as_bytes())?;
+            last = Some(triple.subject);
+            Ok(())
+        })?;
+        if last.is_some(){
+            writeln!(out, "\t</rdf:Description>")?;
+        }
+        writeln!(out, "\n</rdf:RDF>")?;
+        Ok(())
+    }
+
+    // The IRIs use rdf:about or rdf:resource, and the blank nodes use rdf:nodeID.
+    fn node_attribute(&self, term: &Term, iri_attribute: &str) -> ResultApp<String>{
+        match term{
+            Term::Iri(iri) => Ok(format!("rdf:{}=\"{}\"", iri_attribute, escape_xml(&escape_iri(iri), true)?)),
+            Term::BlankNode(_) => {
+                let label = term.to_ntriples();
+                Ok(format!("rdf:nodeID=\"{}\"", label.trim_start_matches("_:")))
+            }
+            term => {
+                error!("The literal {} can not be a subject in RDF/XML", term.to_ntriples());
+                Err(ApplicationErrors::FAiledToCreateRDF)
+            }
+        }
+    }
+
+    // Splits the predicate in one of the namespaces and a local name that is a valid XML name.
+    fn qname(&self, iri: &str) -> ResultApp<String>{
+        for (name, url) in self.prefixes.iter(){
+            if let Some(local) = iri.strip_prefix(url.as_str()){
+                if is_ncname(local){
+                    return Ok(format!("{}:{}", name, local))
+                }
+            }
+        }
+        error!("The predicate <{}> can not be written as a QName in RDF/XML. Add a prefix for its namespace to the mappings", iri);
+        Err(ApplicationErrors::InvalidQName)
+    }
+}
+
+// Name without colons of XML. Only the most common characters are accepted.
+fn is_ncname(name: &str) -> bool{
+    let mut chars = name.chars();
+    match chars.next(){
+        Some(c) if c.is_alphabetic() || c == '_' => {},
+        _ => return false
+    }
+    chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+// Escapes the markup characters. The whitespaces of the attributes are written as references, so they are not
+// normalised by the parsers. XML 1.0 can not contain the rest of the control characters.
+pub fn escape_xml(value: &str, attribute: bool) -> ResultApp<String>{
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars(){
+        match c{
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\n' | '\t' if attribute => escaped.push_str(&format!("&#{};", c as u32)),
+            '\n' | '\t' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => {
+                error!("The value {:?} contains characters that can not be written in XML", value);
+                return Err(ApplicationErrors::InvalidXMLCharacter)
+            }
+            c => escaped.push(c)
+        }
+    }
+    Ok(escaped)
+}
+
+#[cfg(test)]
+mod test_rdfxml{
+    use super::*;
+    use crate::rdf::{Triple, RDF_TYPE};
+
+    fn writer() -> RdfXmlWriter{
+        let prefixes = vec![
+            ("ex:".to_string(), "http://example.com/".to_string()),
+            ("rr:".to_string(), "http://www.w3.org/ns/r2rml#".to_string())
+        ].into_iter().collect();
+        RdfXmlWriter::new(&prefixes)
+    }
+
+    fn write(triples: &[Triple]) -> ResultApp<String>{
+        let mut spool = TripleSpool::new().unwrap();
+        spool.push(triples).unwrap();
+        let mut out = Vec::new();
+        writer().write(&mut spool, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn descriptions_with_attributes(){
+        let iri = |value: &str| Term::Iri(format!("http://example.com/{}", value));
+        let output = write(&[
+            Triple{subject: iri("a"), predicate: iri("name"), object: Term::LangLiteral{value: "A & <B>".to_string(), language: "en".to_string()}, graph: None},
+            Triple{subject: iri("a"), predicate: Term::Iri(RDF_TYPE.to_string()), object: iri("Thing"), graph: None},
+            Triple{subject: iri("a"), predicate: iri("age"), object: Term::Literal{
+                value: "3".to_string(), datatype: Some("http://www.w3.org/2001/XMLSchema#int".to_string())
+            }, graph: None},
+            Triple{subject: Term::BlankNode("x".to_string()), predicate: iri("of"), object: iri("a?b=1&c=2 <d>"), graph: None}
+        ]).unwrap();
+        assert_eq!(output, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rdf:RDF\n\
+            \txmlns:ex=\"http://example.com/\"\n\
+            \txmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+            \n\t<rdf:Description rdf:about=\"http://example.com/a\">\n\
+            \t\t<rdf:type rdf:resource=\"http://example.com/Thing\"/>\n\
+            \t\t<ex:age rdf:datatype=\"http://www.w3.org/2001/XMLSchema#int\">3</ex:age>\n\
+            \t\t<ex:name xml:lang=\"en\">A &amp; &lt;B&gt;</ex:name>\n\
+            \t</rdf:Description>\n\
+            \n\t<rdf:Description rdf:nodeID=\"bx\">\n\
+            \t\t<ex:of rdf:resource=\"http://example.com/a?b=1&amp;c=2%20%3Cd%3E\"/>\n\
+            \t</rdf:Description>\n\
+            \n</rdf:RDF>\n");
+    }
+
+    #[test]
+    fn predicates_must_be_qnames(){
+        let triple = |predicate: &str| Triple{
+            subject: Term::Iri("http://example.com/a".to_string()),
+            predicate: Term::Iri(predicate.to_string()),
+            object: Term::Literal{value: "1".to_string(), datatype: None},
+            graph: None
+        };
+        assert!(matches!(write(&[triple("http://other.com/name")]), Err(ApplicationErrors::InvalidQName)));
+        assert!(matches!(write(&[triple("http://example.com/1name")]), Err(ApplicationErrors::InvalidQName)));
+        assert!(write(&[triple("http://example.com/name")]).is_ok());
+    }
+}
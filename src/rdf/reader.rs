@@ -0,0 +1,88 @@
+// Reader of Turtle documents, like the SHACL shapes, with the Turtle parser of Oxigraph. The blank nodes of [] and of
+// the collections are given random labels, so they can not be confused with the labels of the document.
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::error;
+use super::Triple;
+use super::terms::triple_from_oxrdf;
+
+use oxttl::TurtleParser;
+
+pub fn read_turtle(text: &str, base: Option<&str>) -> ResultApp<Vec<Triple>>{
+    let mut parser = TurtleParser::new();
+    if let Some(base) = base{
+        parser = parser.with_base_iri(base).map_err(|err| {
+            error!("The base IRI {} is not valid: {}", base, err);
+            ApplicationErrors::InvalidTurtle
+        })?;
+    }
+    parser.for_slice(text)
+        .map(|triple| triple.map(triple_from_oxrdf).map_err(|err| {
+            error!("Invalid Turtle: {}", err);
+            ApplicationErrors::InvalidTurtle
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_reader{
+    use super::*;
+    use crate::rdf::{Term, RDF_TYPE};
+
+    const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+    const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+    #[test]
+    fn turtle_is_read_with_its_shorthands(){
+        let text = r#"
+            @prefix ex: <http://example.com/> .
+            PREFIX sh: <http://www.w3.org/ns/shacl#>
+            @base <http://example.com/shapes/> .
+
+            <Person> a sh:NodeShape ; # a comment
+                sh:property [ sh:path ex:age ; sh:maxCount 1 ; ] ;
+                sh:in ( "a"@en 2.5 ) ;
+                sh:name "b"^^<http://www.w3.org/2001/XMLSchema#string> ;
+                sh:group _:anon1, <../groups/./main>, ex:with\/slash .
+        "#;
+        let triples = read_turtle(text, None).unwrap();
+        let iri = |iri: &str| Term::Iri(iri.to_string());
+        let sh = |name: &str| iri(&format!("http://www.w3.org/ns/shacl#{}", name));
+        let person = iri("http://example.com/shapes/Person");
+        let objects = |subject: &Term, predicate: &Term| triples.iter()
+            .filter(|triple| &triple.subject == subject && &triple.predicate == predicate)
+            .map(|triple| triple.object.clone())
+            .collect::<Vec<_>>();
+        let object = |subject: &Term, predicate: &Term| match objects(subject, predicate).as_slice(){
+            [object] => object.clone(),
+            other => panic!("Expected a single object, found {:?}", other)
+        };
+
+        assert_eq!(triples.len(), 13);
+        assert_eq!(object(&person, &iri(RDF_TYPE)), sh("NodeShape"));
+        let property = object(&person, &sh("property"));
+        assert_eq!(object(&property, &sh("path")), iri("http://example.com/age"));
+        assert_eq!(object(&property, &sh("maxCount")), Term::Literal{value: "1".to_string(), datatype: Some(format!("{}integer", XSD))});
+
+        let first = object(&person, &sh("in"));
+        let (rdf_first, rdf_rest) = (iri(&format!("{}first", RDF)), iri(&format!("{}rest", RDF)));
+        assert_eq!(object(&first, &rdf_first), Term::LangLiteral{value: "a".to_string(), language: "en".to_string()});
+        let second = object(&first, &rdf_rest);
+        assert_eq!(object(&second, &rdf_first), Term::Literal{value: "2.5".to_string(), datatype: Some(format!("{}decimal", XSD))});
+        assert_eq!(object(&second, &rdf_rest), iri(&format!("{}nil", RDF)));
+
+        // The explicit xsd:string is the same as no datatype
+        assert_eq!(object(&person, &sh("name")), Term::Literal{value: "b".to_string(), datatype: None});
+        // The labels of the document are kept and the generated ones are different from them
+        let groups = objects(&person, &sh("group"));
+        assert_eq!(groups, [
+            Term::BlankNode("anon1".to_string()),
+            iri("http://example.com/groups/main"),
+            iri("http://example.com/with/slash")
+        ]);
+        assert!(![&property, &first, &second].contains(&&groups[0]));
+
+        assert!(read_turtle("<a> <b> ex:c .", None).is_err());
+        assert!(read_turtle(r#"<http://example.com/a> <http://example.com/b> "\q" ."#, None).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+
+use crate::ResultApp;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Lines of each sorted run written to disk
+const RUN_SIZE: usize = 200_000;
+
+// Number of the next run file, so the files of the same process do not collide.
+static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+
+// Sorts more lines than the ones that fit in memory. The lines are sorted in runs that are written to temporary
+// files, which are merged when the lines are read. The lines can not contain new lines.
+pub struct ExternalSort{
+    run_size: usize,
+    // Lines of the run that is being filled
+    run: Vec<String>,
+    runs: Vec<RunFile>
+}
+
+impl ExternalSort{
+    pub fn new() -> Self{
+        Self::with_run_size(RUN_SIZE)
+    }
+
+    pub fn with_run_size(run_size: usize) -> Self{
+        Self{run_size, run: Vec::new(), runs: Vec::new()}
+    }
+
+    pub fn push(&mut self, line: String) -> ResultApp<()>{
+        self.run.push(line);
+        if self.run.len() >= self.run_size{
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    // All the lines sorted and without repetitions. Without runs on disk, they are sorted in memory.
+    pub fn into_sorted(mut self) -> ResultApp<SortedLines>{
+        if self.runs.is_empty(){
+            self.run.sort_unstable();
+            self.run.dedup();
+            return Ok(SortedLines{memory: self.run.into_iter(), readers: Vec::new(), heap: BinaryHeap::new(), last: None, _runs: Vec::new()})
+        }
+        self.write_run()?;
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        for run in self.runs.iter_mut(){
+            run.file.rewind()?;
+            readers.push(BufReader::new(run.file.try_clone()?));
+        }
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (idx, reader) in readers.iter_mut().enumerate(){
+            if let Some(line) = next_line(reader)?{
+                heap.push(Reverse((line, idx)));
+            }
+        }
+        Ok(SortedLines{memory: Vec::new().into_iter(), readers, heap, last: None, _runs: self.runs})
+    }
+
+    fn write_run(&mut self) -> ResultApp<()>{
+        if self.run.is_empty(){
+            return Ok(())
+        }
+        self.run.sort_unstable();
+        self.run.dedup();
+        let mut file = RunFile::new()?;
+        let mut writer = BufWriter::new(&mut file.file);
+        for line in self.run.drain(..){
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.runs.push(file);
+        Ok(())
+    }
+}
+
+// Iterator over the sorted lines. The runs are removed when it is dropped.
+pub struct SortedLines{
+    memory: std::vec::IntoIter<String>,
+    readers: Vec<BufReader<fs::File>>,
+    // Next line of each run
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+    last: Option<String>,
+    _runs: Vec<RunFile>
+}
+
+impl SortedLines{
+    fn next_merged(&mut self) -> io::Result<Option<String>>{
+        while let Some(Reverse((line, idx))) = self.heap.pop(){
+            if let Some(next) = next_line(&mut self.readers[idx])?{
+                self.heap.push(Reverse((next, idx)));
+            }
+            if self.last.as_ref() != Some(&line){
+                self.last = Some(line.clone());
+                return Ok(Some(line))
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for SortedLines{
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        if let Some(line) = self.memory.next(){
+            return Some(Ok(line))
+        }
+        self.next_merged().transpose()
+    }
+}
+
+// Temporary file with a sorted run. It is removed when the run is dropped.
+struct RunFile{
+    path: PathBuf,
+    file: fs::File
+}
+
+impl RunFile{
+    fn new() -> ResultApp<Self>{
+        let path = std::env::temp_dir().join(format!("rossete-sort-{}-{}.run", std::process::id(), NEXT_RUN.fetch_add(1, Ordering::Relaxed)));
+        let file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(Self{path, file})
+    }
+}
+
+impl Drop for RunFile{
+    fn drop(&mut self){
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn next_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>>{
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0{
+        return Ok(None)
+    }
+    line.pop(); // New line
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod test_runs{
+    use super::*;
+
+    #[test]
+    fn runs_are_merged_without_repetitions(){
+        let mut sort = ExternalSort::with_run_size(3);
+        for line in ["d", "b", "a", "c", "b", "e", "a", "f"]{
+            sort.push(line.to_string()).unwrap();
+        }
+        assert_eq!(sort.runs.len(), 2);
+        let lines = sort.into_sorted().unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, ["a", "b", "c", "d", "e", "f"]);
+    }
+}
@@ -0,0 +1,109 @@
+
+use crate::ResultApp;
+use super::{Term, Triple, RDF_TYPE};
+
+// Number of inserted triples that are committed at once
+const MAX_BATCH: usize = 10_000;
+
+// Temporary table with all the triples of the output. It allows to write them sorted by subject, even if they were
+// generated by different maps. SQLite keeps it in memory while it is small and moves it to a temporary file when
+// it grows, so the output does not need to fit in memory.
+pub struct TripleSpool{
+    db: rusqlite::Connection,
+    pending: usize
+}
+
+impl TripleSpool{
+    pub fn new() -> ResultApp<Self>{
+        let db = rusqlite::Connection::open("")?; // Private temporary database, removed when it is closed
+        db.execute_batch("
+            CREATE TABLE triples (subject_kind INTEGER, subject TEXT, predicate TEXT, object_kind INTEGER, object TEXT, datatype TEXT, graph_kind INTEGER, graph TEXT);
+            BEGIN;
+        ")?;
+        Ok(Self{db, pending: 0})
+    }
+
+    pub fn push(&mut self, triples: &[Triple]) -> ResultApp<()>{
+        let mut smt = self.db.prepare_cached("INSERT INTO triples VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);")?;
+        for triple in triples.iter(){
+            let (subject_kind, subject, _) = term_columns(&triple.subject);
+            let (object_kind, object, datatype) = term_columns(&triple.object);
+            let (_, predicate, _) = term_columns(&triple.predicate);
+            let (graph_kind, graph) = match &triple.graph{
+                Some(graph) => {
+                    let (kind, graph, _) = term_columns(graph);
+                    (Some(kind), Some(graph))
+                }
+                None => (None, None)
+            };
+            smt.execute(rusqlite::params![subject_kind, subject, predicate, object_kind, object, datatype, graph_kind, graph])?;
+        }
+        self.pending += triples.len();
+        if self.pending >= MAX_BATCH{
+            self.db.execute_batch("COMMIT; BEGIN;")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    // Calls the function with every triple, sorted by subject. The triples of a subject are sorted by predicate,
+    // with rdf:type first, and the objects of a predicate keep the order in which they were pushed, except the
+    // literals of rdf:type that go after its IRIs. If by_graph is set, the triples are first sorted by graph,
+    // starting with the default graph.
+    pub fn for_each_sorted<F>(&mut self, by_graph: bool, mut row_fn: F) -> ResultApp<()>
+    where F: FnMut(Triple) -> ResultApp<()>{
+        self.finish()?;
+        let query = format!(
+            "SELECT subject_kind, subject, predicate, object_kind, object, datatype, graph_kind, graph FROM triples \
+            ORDER BY {}subject_kind, subject, predicate <> '{1}', predicate, predicate = '{1}' AND object_kind >= 2, rowid;",
+            if by_graph { "graph_kind, graph, " } else { "" }, RDF_TYPE
+        );
+        let mut smt = self.db.prepare(&query)?;
+        let mut rows = smt.query([])?;
+        while let Some(row) = rows.next()?{
+            row_fn(Triple{
+                subject: column_term(row.get(0)?, row.get(1)?, None),
+                predicate: Term::Iri(row.get(2)?),
+                object: column_term(row.get(3)?, row.get(4)?, row.get(5)?),
+                graph: match row.get::<_, Option<u8>>(6)?{
+                    Some(kind) => Some(column_term(kind, row.get(7)?, None)),
+                    None => None
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    // Database with the table of the triples, for the writers that need to process them with SQL.
+    pub fn database(&mut self) -> ResultApp<&rusqlite::Connection>{
+        self.finish()?;
+        Ok(&self.db)
+    }
+
+    // Commits the pushed triples, so they can be read.
+    fn finish(&mut self) -> ResultApp<()>{
+        if !self.db.is_autocommit(){
+            self.db.execute_batch("COMMIT;")?;
+        }
+        Ok(())
+    }
+}
+
+// The datatype column keeps the language of the language-tagged literals.
+pub fn term_columns(term: &Term) -> (u8, &str, Option<&str>){
+    match term{
+        Term::Iri(iri) => (0, iri, None),
+        Term::BlankNode(label) => (1, label, None),
+        Term::Literal{value, datatype} => (2, value, datatype.as_deref()),
+        Term::LangLiteral{value, language} => (3, value, Some(language))
+    }
+}
+
+pub fn column_term(kind: u8, value: String, datatype: Option<String>) -> Term{
+    match kind{
+        0 => Term::Iri(value),
+        1 => Term::BlankNode(value),
+        3 => Term::LangLiteral{value, language: datatype.unwrap_or_default()},
+        _ => Term::Literal{value, datatype}
+    }
+}
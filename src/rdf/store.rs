@@ -0,0 +1,195 @@
+use crate::ResultApp;
+use super::{Term, Triple};
+use super::spool::term_columns;
+#[cfg(feature = "sparql")]
+use super::spool::column_term;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// Number of inserted triples that are committed at once
+const MAX_BATCH: usize = 10_000;
+// Identifiers of the terms kept in memory while the store is written
+const MAX_CACHED_TERMS: usize = 200_000;
+// Identifier of the default graph in the quads
+const DEFAULT_GRAPH: i64 = 0;
+
+// SQLite database with the triples of a run, so they can be queried after it. Every term is stored once in a
+// dictionary and the quads only have their identifiers. The same quad is only stored once.
+pub struct TripleStore{
+    db: rusqlite::Connection,
+    term_ids: HashMap<Term, i64>,
+    pending: usize
+}
+
+impl TripleStore{
+    // Creates an empty store. A previous file in the same path is replaced.
+    pub fn create(path: &Path) -> ResultApp<Self>{
+        if path.exists(){
+            std::fs::remove_file(path)?;
+        }
+        let db = rusqlite::Connection::open(path)?;
+        db.execute_batch("
+            PRAGMA journal_mode = OFF;
+            PRAGMA synchronous = OFF;
+            CREATE TABLE terms (id INTEGER PRIMARY KEY, kind INTEGER NOT NULL, value TEXT NOT NULL, datatype TEXT NOT NULL, UNIQUE (kind, value, datatype));
+            CREATE TABLE quads (subject INTEGER NOT NULL, predicate INTEGER NOT NULL, object INTEGER NOT NULL, graph INTEGER NOT NULL, UNIQUE (subject, predicate, object, graph));
+            BEGIN;
+        ")?;
+        Ok(Self{db, term_ids: HashMap::new(), pending: 0})
+    }
+
+    #[cfg(feature = "sparql")]
+    pub fn open(path: &Path) -> ResultApp<Self>{
+        let db = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        db.query_row("SELECT count(*) FROM quads;", [], |row| row.get::<_, i64>(0))?;
+        Ok(Self{db, term_ids: HashMap::new(), pending: 0})
+    }
+
+    pub fn push(&mut self, triples: &[Triple]) -> ResultApp<()>{
+        for triple in triples.iter(){
+            let subject = self.insert_term(&triple.subject)?;
+            let predicate = self.insert_term(&triple.predicate)?;
+            let object = self.insert_term(&triple.object)?;
+            let graph = match &triple.graph{
+                Some(graph) => self.insert_term(graph)?,
+                None => DEFAULT_GRAPH
+            };
+            let mut smt = self.db.prepare_cached("INSERT OR IGNORE INTO quads VALUES (?1, ?2, ?3, ?4);")?;
+            smt.execute([subject, predicate, object, graph])?;
+        }
+        self.pending += triples.len();
+        if self.pending >= MAX_BATCH{
+            self.db.execute_batch("COMMIT; BEGIN;")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    // Commits the triples and creates the indexes of the patterns, which is faster once all of them are in.
+    pub fn finish(self) -> ResultApp<()>{
+        self.db.execute_batch("
+            COMMIT;
+            CREATE INDEX quads_pos ON quads (predicate, object, subject);
+            CREATE INDEX quads_osp ON quads (object, subject, predicate);
+            CREATE INDEX quads_graph ON quads (graph, subject);
+            ANALYZE;
+        ")?;
+        Ok(())
+    }
+
+    fn insert_term(&mut self, term: &Term) -> ResultApp<i64>{
+        if let Some(&id) = self.term_ids.get(term){
+            return Ok(id)
+        }
+        let (kind, value, datatype) = term_columns(term);
+        let datatype = datatype.unwrap_or_default();
+        self.db.prepare_cached("INSERT OR IGNORE INTO terms (kind, value, datatype) VALUES (?1, ?2, ?3);")?
+            .execute(rusqlite::params![kind, value, datatype])?;
+        let id = self.db.prepare_cached("SELECT id FROM terms WHERE kind = ?1 AND value = ?2 AND datatype = ?3;")?
+            .query_row(rusqlite::params![kind, value, datatype], |row| row.get(0))?;
+        if self.term_ids.len() >= MAX_CACHED_TERMS{
+            self.term_ids.clear();
+        }
+        self.term_ids.insert(term.clone(), id);
+        Ok(id)
+    }
+
+    // Identifier of a term, if it is in the store.
+    #[cfg(feature = "sparql")]
+    pub fn term_id(&self, term: &Term) -> ResultApp<Option<i64>>{
+        let (kind, value, datatype) = term_columns(term);
+        let mut smt = self.db.prepare_cached("SELECT id FROM terms WHERE kind = ?1 AND value = ?2 AND datatype = ?3;")?;
+        let mut rows = smt.query(rusqlite::params![kind, value, datatype.unwrap_or_default()])?;
+        Ok(match rows.next()?{
+            Some(row) => Some(row.get(0)?),
+            None => None
+        })
+    }
+
+    // Quads that match the given terms, with the graph as in the query engine: Some(None) is the default graph, which
+    // is the union of all the graphs with each triple given once, Some(Some(graph)) is a named graph and None is any
+    // named graph. The terms are read with the quads, so they are not looked up one by one.
+    #[cfg(feature = "sparql")]
+    pub fn quads_for_pattern(&self, subject: Option<&Term>, predicate: Option<&Term>, object: Option<&Term>, graph: Option<Option<&Term>>) -> ResultApp<Vec<Triple>>{
+        const COLUMNS: [&str; 4] = ["subject", "predicate", "object", "graph"];
+        let mut conditions = Vec::new();
+        for (term, column) in [subject, predicate, object, graph.flatten()].into_iter().zip(COLUMNS){
+            if let Some(term) = term{
+                match self.term_id(term)?{
+                    Some(id) => conditions.push(format!("q.{} = {}", column, id)),
+                    None => return Ok(Vec::new())
+                }
+            }
+        }
+        let union = graph == Some(None);
+        if graph.is_none(){
+            conditions.push(format!("q.graph <> {}", DEFAULT_GRAPH));
+        }
+
+        let mut selected = Vec::new();
+        let mut joins = Vec::new();
+        // The default graph is not in the terms
+        for (idx, column) in COLUMNS.iter().take(if union { 3 } else { 4 }).enumerate(){
+            selected.push(format!("t{0}.kind, t{0}.value, t{0}.datatype", idx));
+            joins.push(format!("LEFT JOIN terms t{0} ON t{0}.id = q.{1}", idx, column));
+        }
+        let mut query = format!("SELECT {}{} FROM quads q {}", if union { "DISTINCT " } else { "" }, selected.join(", "), joins.join(" "));
+        if !conditions.is_empty(){
+            query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+
+        let mut smt = self.db.prepare_cached(&query)?;
+        let mut rows = smt.query([])?;
+        let mut quads = Vec::new();
+        while let Some(row) = rows.next()?{
+            let term = |idx: usize| -> rusqlite::Result<Option<Term>>{
+                let kind: Option<u8> = row.get(idx * 3)?;
+                match kind{
+                    Some(kind) => {
+                        let datatype = row.get::<_, String>(idx * 3 + 2)?;
+                        Ok(Some(column_term(kind, row.get(idx * 3 + 1)?, Some(datatype).filter(|datatype| !datatype.is_empty()))))
+                    }
+                    None => Ok(None)
+                }
+            };
+            if let (Some(subject), Some(predicate), Some(object)) = (term(0)?, term(1)?, term(2)?){
+                let graph = if union { None } else { term(3)? };
+                quads.push(Triple{subject, predicate, object, graph});
+            }
+        }
+        Ok(quads)
+    }
+}
+
+#[cfg(all(test, feature = "sparql"))]
+mod test_store{
+    use super::*;
+
+    #[test]
+    fn patterns_are_matched_in_every_graph(){
+        let path = std::env::temp_dir().join(format!("rossete-test-store-{}.db", std::process::id()));
+        let iri = |name: &str| Term::Iri(format!("http://example.com/{}", name));
+        let triple = |s: &str, p: &str, o: Term, g: Option<Term>| Triple{subject: iri(s), predicate: iri(p), object: o, graph: g};
+        let mut store = TripleStore::create(&path).unwrap();
+        store.push(&[
+            triple("a", "knows", iri("b"), None),
+            triple("a", "knows", iri("b"), Some(iri("g"))),
+            triple("b", "name", Term::Literal{value: "Bob".to_string(), datatype: None}, Some(iri("g"))),
+            triple("a", "knows", iri("c"), None)
+        ]).unwrap();
+        store.finish().unwrap();
+
+        let store = TripleStore::open(&path).unwrap();
+        // The default graph has every triple once
+        let mut known = store.quads_for_pattern(Some(&iri("a")), Some(&iri("knows")), None, Some(None)).unwrap();
+        known.sort_by_key(|quad| quad.object.to_ntriples());
+        assert_eq!(known, [triple("a", "knows", iri("b"), None), triple("a", "knows", iri("c"), None)]);
+        assert_eq!(store.quads_for_pattern(None, Some(&iri("name")), None, Some(Some(&iri("g")))).unwrap(), [
+            triple("b", "name", Term::Literal{value: "Bob".to_string(), datatype: None}, Some(iri("g")))
+        ]);
+        assert_eq!(store.quads_for_pattern(None, Some(&iri("knows")), None, None).unwrap(), [triple("a", "knows", iri("b"), Some(iri("g")))]);
+        assert!(store.quads_for_pattern(Some(&iri("z")), None, None, Some(None)).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+
+// RDF terms generated by the maps, their serialisation in N-Triples and their conversion from the terms of Oxigraph.
+
+pub const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Term{
+    Iri(String),
+    // Label of the blank node before it is escaped
+    BlankNode(String),
+    // A literal without datatype is a xsd:string
+    Literal{
+        value: String,
+        datatype: Option<String>
+    },
+    // Literal with a language tag (rdf:langString)
+    LangLiteral{
+        value: String,
+        language: String
+    }
+}
+
+impl Term{
+    // Serialisation of the term in N-Triples. Turtle accepts the same escapes, so it is also used by it.
+    pub fn to_ntriples(&self) -> String{
+        match self{
+            Self::Iri(iri) => format!("<{}>", escape_iri(iri)),
+            Self::BlankNode(label) => format!("_:{}", escape_blank_node(label)),
+            Self::Literal{value, datatype: None} => format!("\"{}\"", escape_literal(value)),
+            Self::Literal{value, datatype: Some(datatype)} => format!("\"{}\"^^<{}>", escape_literal(value), escape_iri(datatype)),
+            Self::LangLiteral{value, language} => format!("\"{}\"@{}", escape_literal(value), language)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Triple{
+    pub subject: Term,
+    pub predicate: Term,
+    pub object: Term,
+    // Named graph of the triple, None is the default graph
+    pub graph: Option<Term>
+}
+
+impl Triple{
+    // Line of the triple in N-Triples, without the new line. The graph is not written.
+    pub fn to_ntriples(&self) -> String{
+        format!("{} {} {} .", self.subject.to_ntriples(), self.predicate.to_ntriples(), self.object.to_ntriples())
+    }
+}
+
+// Writes a group of triples, usually the ones generated by a row, in N-Triples. The graphs are not written.
+pub fn to_ntriples(triples: &[Triple]) -> String{
+    let mut buffer = String::with_capacity(triples.len() * 100);
+    for triple in triples.iter(){
+        buffer.push_str(&triple.to_ntriples());
+        buffer.push('\n');
+    }
+    buffer.push('\n');
+    buffer
+}
+
+// IRI-safe version of a template value (R2RML 7.3): every character that is not in the iunreserved production of
+// RFC 3987 is percent-encoded using its UTF-8 bytes.
+pub fn iri_safe(value: &str) -> String{
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars(){
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~') || is_ucschar(c){
+            encoded.push(c);
+        }else{
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes(){
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+// Decodes the %XX sequences of a value. None if a sequence is not valid or the bytes are not UTF-8.
+#[cfg(any(test, feature = "sparql"))]
+pub fn percent_decode(value: &str) -> Option<String>{
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len(){
+        if bytes[pos] == b'%'{
+            let hex = std::str::from_utf8(bytes.get(pos + 1..pos + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            pos += 3;
+        }else{
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn is_ucschar(c: char) -> bool{
+    let code = c as u32;
+    matches!(code, 0xA0..=0xD7FF | 0xF900..=0xFDCF | 0xFDF0..=0xFFEF) ||
+        (code >= 0x10000 && code & 0xFFFF <= 0xFFFD && code < 0xE0000) ||
+        (0xE1000..=0xEFFFD).contains(&code)
+}
+
+// An IRI is valid if it does not contain any of the characters that can never appear in an IRI.
+pub fn is_valid_iri(iri: &str) -> bool{
+    !iri.chars().any(is_forbidden_in_iri)
+}
+
+fn is_forbidden_in_iri(c: char) -> bool{
+    matches!(c, '\u{0}'..='\u{20}' | '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\')
+}
+
+// Quotes, backslashes and control characters are escaped. The rest of the characters are valid inside a string.
+pub fn escape_literal(value: &str) -> String{
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for c in value.chars(){
+        match c{
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+// The literals of xsd:string are written without datatype, as in the rest of rossete.
+pub fn from_oxrdf(term: oxrdf::Term) -> Term{
+    match term{
+        oxrdf::Term::NamedNode(iri) => Term::Iri(iri.into_string()),
+        oxrdf::Term::BlankNode(node) => Term::BlankNode(node.as_str().to_string()),
+        oxrdf::Term::Literal(literal) => match literal.destruct(){
+            (value, _, Some(language)) => Term::LangLiteral{value, language},
+            (value, datatype, None) => Term::Literal{value, datatype: datatype.map(|datatype| datatype.into_string()).filter(|datatype| datatype != XSD_STRING)}
+        }
+    }
+}
+
+// The terms of rossete were checked when they were generated, so they are not checked again.
+#[cfg(feature = "sparql")]
+pub fn to_oxrdf(term: &Term) -> oxrdf::Term{
+    match term{
+        Term::Iri(iri) => oxrdf::NamedNode::new_unchecked(iri.as_str()).into(),
+        Term::BlankNode(label) => oxrdf::BlankNode::new_unchecked(label.as_str()).into(),
+        Term::Literal{value, datatype: None} => oxrdf::Literal::new_simple_literal(value.as_str()).into(),
+        Term::Literal{value, datatype: Some(datatype)} => oxrdf::Literal::new_typed_literal(value.as_str(), oxrdf::NamedNode::new_unchecked(datatype.as_str())).into(),
+        Term::LangLiteral{value, language} => oxrdf::Literal::new_language_tagged_literal_unchecked(value.as_str(), language.as_str()).into()
+    }
+}
+
+pub fn triple_from_oxrdf(triple: oxrdf::Triple) -> Triple{
+    Triple{subject: from_oxrdf(triple.subject.into()), predicate: from_oxrdf(triple.predicate.into()), object: from_oxrdf(triple.object), graph: None}
+}
+
+// Characters that are not allowed in an IRI reference are percent-encoded, as the \u escapes would give back the same
+// invalid IRI to the parsers.
+pub fn escape_iri(iri: &str) -> String{
+    let mut escaped = String::with_capacity(iri.len());
+    for c in iri.chars(){
+        if is_forbidden_in_iri(c){
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        }else{
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// Blank node labels can only use some characters, the rest are replaced by their code point between underscores.
+fn escape_blank_node(label: &str) -> String{
+    let mut escaped = String::with_capacity(label.len() + 1);
+    escaped.push('b');
+    for c in label.chars(){
+        if c.is_ascii_alphanumeric(){
+            escaped.push(c);
+        }else{
+            escaped.push_str(&format!("_{:X}_", c as u32));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test_rdf{
+    use super::*;
+
+    // The line is read by the N-Triples parser of Oxigraph, and by its Turtle parser, which accepts the same escapes.
+    fn parse_line(line: &str) -> Triple{
+        let triples = oxttl::NTriplesParser::new().for_slice(line).collect::<Result<Vec<_>, _>>().unwrap();
+        let turtle = oxttl::TurtleParser::new().for_slice(line).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(triples, turtle);
+        assert_eq!(triples.len(), 1);
+        triple_from_oxrdf(triples.into_iter().next().unwrap())
+    }
+
+    fn round_trip(triple: &Triple) -> Triple{
+        let text = to_ntriples(std::slice::from_ref(triple));
+        let lines = text.lines().filter(|line| !line.is_empty()).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1, "A triple must be written in a single line: {}", text);
+        parse_line(lines[0])
+    }
+
+    const TRICKY: [&str; 9] = [
+        "plain",
+        "with \"quotes\" and \\backslashes\\",
+        "multi\nline\r\ntext\twith tabs",
+        "control \u{1} \u{8} \u{c} \u{7f} characters",
+        "already escaped \\u0041 and \\n",
+        "unicode: España, 日本, 🦀",
+        "",
+        " ",
+        "ends with a backslash \\"
+    ];
+
+    #[test]
+    fn literals_round_trip(){
+        for value in TRICKY.iter(){
+            for datatype in [None, Some(XSD_STRING.to_string())]{
+                let triple = Triple{
+                    subject: Term::Iri("http://example.com/s".to_string()),
+                    predicate: Term::Iri("http://example.com/p".to_string()),
+                    object: Term::Literal{value: value.to_string(), datatype},
+                    graph: None
+                };
+                assert_eq!(round_trip(&triple).object, Term::Literal{value: value.to_string(), datatype: None});
+            }
+        }
+    }
+
+    #[test]
+    fn iris_round_trip(){
+        let iris = [
+            ("http://example.com/España?q=1#a", "http://example.com/España?q=1#a"),
+            ("http://example.com/a b", "http://example.com/a%20b"),
+            ("http://example.com/<tag>", "http://example.com/%3Ctag%3E"),
+            ("http://example.com/{x}|^`\"\\", "http://example.com/%7Bx%7D%7C%5E%60%22%5C"),
+            ("http://example.com/\n", "http://example.com/%0A")
+        ];
+        for (value, written) in iris{
+            let triple = Triple{
+                subject: Term::Iri(value.to_string()),
+                predicate: Term::Iri("http://example.com/p".to_string()),
+                object: Term::Iri(value.to_string()),
+                graph: None
+            };
+            let parsed = round_trip(&triple);
+            assert_eq!((parsed.subject, parsed.object), (Term::Iri(written.to_string()), Term::Iri(written.to_string())));
+        }
+    }
+
+    #[test]
+    fn blank_nodes_are_valid_and_unique(){
+        let mut labels = std::collections::HashSet::new();
+        for value in TRICKY.iter().chain(["map-1", "map.1", "map_1", "1"].iter()){
+            let triple = Triple{
+                subject: Term::BlankNode(value.to_string()),
+                predicate: Term::Iri("http://example.com/p".to_string()),
+                object: Term::BlankNode(value.to_string()),
+                graph: None
+            };
+            let parsed = round_trip(&triple);
+            assert_eq!(parsed.subject, parsed.object);
+            assert!(labels.insert(parsed.subject), "Two blank nodes share the label of {:?}", value);
+        }
+    }
+
+    #[test]
+    fn template_values_are_iri_safe(){
+        assert_eq!(iri_safe("Madrid"), "Madrid");
+        assert_eq!(iri_safe("a b/c?d#e"), "a%20b%2Fc%3Fd%23e");
+        assert_eq!(iri_safe("-._~"), "-._~");
+        assert_eq!(iri_safe("100%"), "100%25");
+        assert_eq!(iri_safe("España"), "España");
+        assert_eq!(iri_safe("\u{7f}\u{e000}"), "%7F%EE%80%80");
+        assert!(is_valid_iri(&format!("http://example.com/{}", iri_safe("<a b>\"{|}^`\\"))));
+        assert!(!is_valid_iri("http://example.com/a b"));
+        assert_eq!(percent_decode(&iri_safe("a b/España%")).as_deref(), Some("a b/España%"));
+        assert_eq!(percent_decode("100%2"), None);
+    }
+}
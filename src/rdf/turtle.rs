@@ -0,0 +1,175 @@
+
+use crate::ResultApp;
+use super::{Term, RDF_TYPE};
+use super::terms::escape_literal;
+use super::spool::TripleSpool;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+// Writes Turtle using the prefixes and the base of the mappings to compact the IRIs.
+pub struct TurtleWriter{
+    // Prefix name (with the colon) and namespace, from the longest namespace to the shortest one.
+    prefixes: Vec<(String, String)>,
+    base: Option<String>
+}
+
+impl TurtleWriter{
+    // The prefixes are the ones of the mappings, where the base is stored with an empty name.
+    pub fn new(prefixes: &HashMap<String, String>) -> Self{
+        Self{
+            prefixes: super::output_prefixes(prefixes),
+            base: prefixes.get("").filter(|url| !url.is_empty()).cloned()
+        }
+    }
+
+    pub fn write_header<W: Write>(&self, out: &mut W) -> ResultApp<()>{
+        if let Some(base) = &self.base{
+            writeln!(out, "@base {} .", Term::Iri(base.clone()).to_ntriples())?;
+        }
+        let mut prefixes = self.prefixes.iter().collect::<Vec<_>>();
+        prefixes.sort();
+        for (name, url) in prefixes{
+            writeln!(out, "@prefix {} {} .", name, Term::Iri(url.clone()).to_ntriples())?;
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+
+    // Writes all the triples of the spool. Each subject is written once with all its predicates, and the objects
+    // of the same predicate are separated with commas.
+    pub fn write<W: Write>(&self, spool: &mut TripleSpool, out: &mut W) -> ResultApp<()>{
+        let mut last: Option<(Term, Term)> = None; // Subject and predicate of the previous triple
+        spool.for_each_sorted(false, |triple| {
+            let mut buffer = String::with_capacity(200);
+            match &last{
+                Some((subject, predicate)) if *subject == triple.subject && *predicate == triple.predicate => {
+                    buffer.push_str(" ,\n\t\t");
+                }
+                Some((subject, _)) if *subject == triple.subject => {
+                    buffer.push_str(" ;\n\t");
+                    buffer.push_str(&self.predicate(&triple.predicate));
+                    buffer.push(' ');
+                }
+                previous => {
+                    if previous.is_some(){
+                        buffer.push_str(" .\n\n");
+                    }
+                    buffer.push_str(&self.term(&triple.subject));
+                    buffer.push(' ');
+                    buffer.push_str(&self.predicate(&triple.predicate));
+                    buffer.push(' ');
+                }
+            }
+            buffer.push_str(&self.term(&triple.object));
+            out.write_all(buffer.as_bytes())?;
+            last = Some((triple.subject, triple.predicate));
+            Ok(())
+        })?;
+        if last.is_some(){
+            out.write_all(b" .\n")?;
+        }
+        Ok(())
+    }
+
+    fn predicate(&self, predicate: &Term) -> String{
+        match predicate{
+            Term::Iri(iri) if iri == RDF_TYPE => "a".to_string(),
+            predicate => self.term(predicate)
+        }
+    }
+
+    pub fn term(&self, term: &Term) -> String{
+        match term{
+            Term::Iri(iri) => self.iri(iri),
+            Term::Literal{value, datatype: Some(datatype)} => format!("\"{}\"^^{}", escape_literal(value), self.iri(datatype)),
+            term => term.to_ntriples()
+        }
+    }
+
+    // Uses a prefixed name if the rest of the IRI is a valid local name, or a relative IRI if it is inside the base.
+    fn iri(&self, iri: &str) -> String{
+        for (name, url) in self.prefixes.iter(){
+            if let Some(local) = iri.strip_prefix(url.as_str()){
+                if is_local_name(local){
+                    return format!("{}{}", name, local)
+                }
+            }
+        }
+        if let Some(base) = &self.base{
+            if let Some(relative) = iri.strip_prefix(base.as_str()){
+                if base.ends_with('/') && !relative.is_empty() && relative.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'){
+                    return format!("<{}>", relative)
+                }
+            }
+        }
+        Term::Iri(iri.to_string()).to_ntriples()
+    }
+}
+
+// Conservative version of the PN_LOCAL production of Turtle: letters, digits, '_', '-' and '.', without starting
+// with '-' or '.' and without ending with '.'.
+fn is_local_name(local: &str) -> bool{
+    let valid = |c: char| c.is_ascii_alphanumeric() || (!c.is_ascii() && c.is_alphabetic()) || matches!(c, '_' | '-' | '.');
+    local.chars().all(valid) &&
+        !local.starts_with('-') && !local.starts_with('.') && !local.ends_with('.')
+}
+
+#[cfg(test)]
+mod test_turtle{
+    use super::*;
+    use crate::rdf::Triple;
+
+    fn writer() -> TurtleWriter{
+        let prefixes = vec![
+            ("ex:".to_string(), "http://example.com/".to_string()),
+            ("exv:".to_string(), "http://example.com/vocab#".to_string()),
+            ("xsd:".to_string(), "http://www.w3.org/2001/XMLSchema#".to_string()),
+            ("rr:".to_string(), "http://www.w3.org/ns/r2rml#".to_string()),
+            ("".to_string(), "http://base.com/".to_string())
+        ].into_iter().collect();
+        TurtleWriter::new(&prefixes)
+    }
+
+    fn triple(subject: &str, predicate: &str, object: Term) -> Triple{
+        Triple{subject: Term::Iri(subject.to_string()), predicate: Term::Iri(predicate.to_string()), object, graph: None}
+    }
+
+    #[test]
+    fn iris_are_compacted_when_valid(){
+        let writer = writer();
+        assert_eq!(writer.iri("http://example.com/vocab#name"), "exv:name");
+        assert_eq!(writer.iri("http://example.com/city"), "ex:city");
+        assert_eq!(writer.iri("http://example.com/city/Madrid"), "<http://example.com/city/Madrid>");
+        assert_eq!(writer.iri("http://example.com/a.b"), "ex:a.b");
+        assert_eq!(writer.iri("http://example.com/end."), "<http://example.com/end.>");
+        assert_eq!(writer.iri("http://base.com/item-1"), "<item-1>");
+        assert_eq!(writer.iri("http://base.com/a/b"), "<http://base.com/a/b>");
+    }
+
+    #[test]
+    fn header_skips_mapping_namespaces(){
+        let mut out = Vec::new();
+        writer().write_header(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "@base <http://base.com/> .\n@prefix ex: <http://example.com/> .\n@prefix exv: <http://example.com/vocab#> .\n@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .\n\n");
+    }
+
+    #[test]
+    fn subjects_are_grouped_across_pushes(){
+        let mut spool = TripleSpool::new().unwrap();
+        let name = "http://example.com/vocab#name";
+        spool.push(&[triple("http://example.com/b", name, Term::Literal{value: "B".to_string(), datatype: None})]).unwrap();
+        spool.push(&[
+            triple("http://example.com/a", name, Term::Literal{value: "1".to_string(), datatype: Some("http://www.w3.org/2001/XMLSchema#int".to_string())}),
+            triple("http://example.com/a", name, Term::Literal{value: "A".to_string(), datatype: None})
+        ]).unwrap();
+        spool.push(&[triple("http://example.com/a", RDF_TYPE, Term::Iri("http://example.com/vocab#Thing".to_string()))]).unwrap();
+
+        let mut out = Vec::new();
+        writer().write(&mut spool, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ex:a a exv:Thing ;\n\texv:name \"1\"^^xsd:int ,\n\t\t\"A\" .\n\nex:b exv:name \"B\" .\n"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// Validation of the triples of a run with SHACL Core shapes: node and property shapes with targets of classes,
+// nodes, subjects and objects, and the constraints of cardinality, datatype, class, node kind, pattern and sh:in.
+// The results are written as a sh:ValidationReport in Turtle, and each one names the maps that created its triples.
+pub mod shapes;
+pub mod graph;
+
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::rdf::Term;
+use crate::rdf::terms::escape_literal;
+use crate::{error, warning, info};
+use shapes::{Constraint, ShapePath, ShapesGraph};
+use graph::DataGraph;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const SH: &str = "http://www.w3.org/ns/shacl#";
+
+// Namespace of the properties of the report that are not part of SHACL.
+const ROSSETE: &str = "https://github.com/RubenCid35/rossete-rdf/ns#";
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+// Results that are also shown in the log. All of them are in the report.
+const LOGGED_RESULTS: usize = 20;
+
+pub struct ValidationResult{
+    pub focus: Term,
+    pub path: Option<ShapePath>,
+    pub value: Option<Term>,
+    pub shape: Term,
+    pub component: &'static str,
+    pub severity: String,
+    pub message: String,
+    // Maps that created the triples of the result
+    pub maps: Vec<usize>
+}
+
+// Validates the triples, writes the report and fails if there is any violation. The warnings and the infos of the
+// shapes are reported but they do not fail the run.
+pub fn check(shapes: &ShapesGraph, graph: &DataGraph, map_names: &[String], report: Option<&PathBuf>) -> ResultApp<()>{
+    let results = validate(shapes, graph);
+    let mut text = Vec::new();
+    write_report(&results, map_names, &mut text)?;
+    match report{
+        Some(report) => std::fs::write(report, &text)?,
+        None if !results.is_empty() => std::io::stderr().write_all(&text)?,
+        None => {}
+    }
+
+    for result in results.iter().take(LOGGED_RESULTS){
+        let maps = result.maps.iter().map(|&map| map_names[map].as_str()).collect::<Vec<_>>().join(", ");
+        let path = match &result.path{
+            Some(ShapePath::Predicate(predicate)) => format!(" {}", predicate.to_ntriples()),
+            Some(ShapePath::Inverse(predicate)) => format!(" ^{}", predicate.to_ntriples()),
+            None => String::new()
+        };
+        let line = format!("[{}] {}{}: {}", maps, result.focus.to_ntriples(), path, result.message);
+        if is_violation(result){
+            error!("{}", line);
+        }else{
+            warning!("{}", line);
+        }
+    }
+    if results.len() > LOGGED_RESULTS{
+        warning!("... and {} more results, they are all in the validation report", results.len() - LOGGED_RESULTS);
+    }
+
+    let violations = results.iter().filter(|result| is_violation(result)).count();
+    if violations > 0{
+        error!("The RDF does not conform to the shapes, there are {} violations", violations);
+        return Err(ApplicationErrors::NotConformingToShapes)
+    }
+    if results.is_empty(){
+        info!("The RDF conforms to the shapes");
+    }else{
+        warning!("The RDF conforms to the shapes, but there are {} warnings", results.len());
+    }
+    Ok(())
+}
+
+fn is_violation(result: &ValidationResult) -> bool{
+    result.severity == format!("{}Violation", SH)
+}
+
+pub fn validate(shapes: &ShapesGraph, graph: &DataGraph) -> Vec<ValidationResult>{
+    let mut results = Vec::new();
+    for (idx, shape) in shapes.shapes.iter().enumerate(){
+        let mut focus_nodes = HashSet::new();
+        for target in shape.targets.iter(){
+            match target{
+                shapes::Target::Class(class) => focus_nodes.extend(graph.instances(class).into_iter().cloned()),
+                shapes::Target::Node(node) => { focus_nodes.insert(node.clone()); }
+                shapes::Target::SubjectsOf(predicate) => focus_nodes.extend(graph.nodes_with(predicate, true).into_iter().cloned()),
+                shapes::Target::ObjectsOf(predicate) => focus_nodes.extend(graph.nodes_with(predicate, false).into_iter().cloned())
+            }
+        }
+        // The results do not depend on the order of the triples in memory
+        let mut focus_nodes = focus_nodes.into_iter().collect::<Vec<_>>();
+        focus_nodes.sort();
+        for focus in focus_nodes.iter(){
+            validate_shape(shapes, idx, focus, graph, &mut results);
+        }
+    }
+    results
+}
+
+fn validate_shape(shapes: &ShapesGraph, idx: usize, focus: &Term, graph: &DataGraph, results: &mut Vec<ValidationResult>){
+    let shape = &shapes.shapes[idx];
+    // The value of a node shape is the focus node itself
+    let values = match &shape.path{
+        Some(ShapePath::Predicate(predicate)) => graph.objects(focus, predicate),
+        Some(ShapePath::Inverse(predicate)) => graph.subjects(focus, predicate),
+        None => vec![(focus, graph.maps_of(focus))]
+    };
+    let mut result = |constraint: &Constraint, value: Option<&Term>, maps: Vec<usize>, message: String|{
+        results.push(ValidationResult{
+            focus: focus.clone(),
+            path: shape.path.clone(),
+            value: value.cloned(),
+            shape: shape.id.clone(),
+            component: constraint.component(),
+            severity: shape.severity.clone(),
+            message: shape.message.clone().unwrap_or(message),
+            maps
+        });
+    };
+
+    for constraint in shape.constraints.iter(){
+        match constraint{
+            Constraint::MinCount(count) if values.len() < *count => {
+                result(constraint, None, graph.maps_of(focus), format!("It has {} values, less than the minimum of {}", values.len(), count));
+                continue
+            }
+            Constraint::MaxCount(count) if values.len() > *count => {
+                result(constraint, None, graph.maps_of(focus), format!("It has {} values, more than the maximum of {}", values.len(), count));
+                continue
+            }
+            Constraint::MinCount(_) | Constraint::MaxCount(_) => continue,
+            _ => {}
+        }
+        for (value, maps) in values.iter(){
+            if let Some(message) = value_error(constraint, value, graph){
+                result(constraint, Some(value), maps.clone(), message);
+            }
+        }
+    }
+    for &property in shape.properties.iter(){
+        validate_shape(shapes, property, focus, graph, results);
+    }
+}
+
+// Message of the constraint if the value does not conform to it.
+fn value_error(constraint: &Constraint, value: &Term, graph: &DataGraph) -> Option<String>{
+    let conforms = match constraint{
+        Constraint::Datatype(datatype) => match value{
+            Term::Literal{value, datatype: Some(found)} => found == datatype && is_valid_lexical(value, datatype),
+            Term::Literal{datatype: None, ..} => *datatype == format!("{}string", XSD),
+            Term::LangLiteral{..} => datatype == "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString",
+            _ => false
+        },
+        Constraint::Class(class) => graph.is_instance(value, class),
+        Constraint::NodeKind(kind) => match value{
+            Term::Iri(_) => kind.contains("IRI"),
+            Term::BlankNode(_) => kind.contains("BlankNode"),
+            _ => kind.contains("Literal")
+        },
+        Constraint::Pattern{regex, ..} => match value{
+            Term::Iri(value) | Term::Literal{value, ..} | Term::LangLiteral{value, ..} => regex.is_match(value),
+            Term::BlankNode(_) => false
+        },
+        Constraint::In(allowed) => allowed.contains(value),
+        Constraint::MinCount(_) | Constraint::MaxCount(_) => true
+    };
+    if conforms{
+        return None
+    }
+    Some(match constraint{
+        Constraint::Datatype(datatype) => format!("The value {} is not a valid <{}>", value.to_ntriples(), datatype),
+        Constraint::Class(class) => format!("The value {} is not an instance of {}", value.to_ntriples(), class.to_ntriples()),
+        Constraint::NodeKind(kind) => format!("The value {} is not a sh:{}", value.to_ntriples(), kind),
+        Constraint::Pattern{pattern, ..} => format!("The value {} does not match the pattern \"{}\"", value.to_ntriples(), pattern),
+        _ => format!("The value {} is not one of the values of sh:in", value.to_ntriples())
+    })
+}
+
+// Checks the lexical form of the common XSD datatypes. The rest are accepted as they are.
+fn is_valid_lexical(value: &str, datatype: &str) -> bool{
+    lazy_static!{
+        static ref DECIMAL: Regex = Regex::new(r"^[+-]?(\d+(\.\d*)?|\.\d+)$").unwrap();
+        static ref DOUBLE: Regex = Regex::new(r"^([+-]?(\d+(\.\d*)?|\.\d+)([eE][+-]?\d+)?|[+-]?INF|NaN)$").unwrap();
+        static ref DATE: Regex = Regex::new(r"^-?\d{4,}-(0[1-9]|1[0-2])-(0[1-9]|[12]\d|3[01])(Z|[+-]\d{2}:\d{2})?$").unwrap();
+        static ref DATE_TIME: Regex = Regex::new(r"^-?\d{4,}-(0[1-9]|1[0-2])-(0[1-9]|[12]\d|3[01])T([01]\d|2[0-3]):[0-5]\d:[0-5]\d(\.\d+)?(Z|[+-]\d{2}:\d{2})?$").unwrap();
+    }
+    let integer = || value.strip_prefix('+').unwrap_or(value).parse::<i128>().ok();
+    let name = match datatype.strip_prefix(XSD){
+        Some(name) => name,
+        None => return true
+    };
+    match name{
+        "integer" => integer().is_some(),
+        "long" => integer().is_some_and(|number| i64::try_from(number).is_ok()),
+        "int" => integer().is_some_and(|number| i32::try_from(number).is_ok()),
+        "short" => integer().is_some_and(|number| i16::try_from(number).is_ok()),
+        "byte" => integer().is_some_and(|number| i8::try_from(number).is_ok()),
+        "nonNegativeInteger" => integer().is_some_and(|number| number >= 0),
+        "positiveInteger" => integer().is_some_and(|number| number > 0),
+        "nonPositiveInteger" => integer().is_some_and(|number| number <= 0),
+        "negativeInteger" => integer().is_some_and(|number| number < 0),
+        "unsignedLong" => integer().is_some_and(|number| u64::try_from(number).is_ok()),
+        "unsignedInt" => integer().is_some_and(|number| u32::try_from(number).is_ok()),
+        "unsignedShort" => integer().is_some_and(|number| u16::try_from(number).is_ok()),
+        "unsignedByte" => integer().is_some_and(|number| u8::try_from(number).is_ok()),
+        "decimal" => DECIMAL.is_match(value),
+        "double" | "float" => DOUBLE.is_match(value),
+        "boolean" => ["true", "false", "1", "0"].contains(&value),
+        "date" => DATE.is_match(value),
+        "dateTime" => DATE_TIME.is_match(value),
+        _ => true
+    }
+}
+
+pub fn write_report<W: Write>(results: &[ValidationResult], map_names: &[String], out: &mut W) -> ResultApp<()>{
+    writeln!(out, "@prefix sh: <{}> .", SH)?;
+    writeln!(out, "@prefix rossete: <{}> .", ROSSETE)?;
+    writeln!(out)?;
+    writeln!(out, "[] a sh:ValidationReport ;")?;
+    write!(out, "    sh:conforms {}", results.is_empty())?;
+    for result in results.iter(){
+        writeln!(out, " ;")?;
+        writeln!(out, "    sh:result [")?;
+        writeln!(out, "        a sh:ValidationResult ;")?;
+        writeln!(out, "        sh:focusNode {} ;", result.focus.to_ntriples())?;
+        match &result.path{
+            Some(ShapePath::Predicate(predicate)) => writeln!(out, "        sh:resultPath {} ;", predicate.to_ntriples())?,
+            Some(ShapePath::Inverse(predicate)) => writeln!(out, "        sh:resultPath [ sh:inversePath {} ] ;", predicate.to_ntriples())?,
+            None => {}
+        }
+        if let Some(value) = &result.value{
+            writeln!(out, "        sh:value {} ;", value.to_ntriples())?;
+        }
+        writeln!(out, "        sh:sourceShape {} ;", result.shape.to_ntriples())?;
+        writeln!(out, "        sh:sourceConstraintComponent sh:{} ;", result.component)?;
+        match result.severity.strip_prefix(SH){
+            Some(severity) => writeln!(out, "        sh:resultSeverity sh:{} ;", severity)?,
+            None => writeln!(out, "        sh:resultSeverity {} ;", Term::Iri(result.severity.clone()).to_ntriples())?
+        }
+        for &map in result.maps.iter(){
+            writeln!(out, "        rossete:map \"{}\" ;", escape_literal(&map_names[map]))?;
+        }
+        writeln!(out, "        sh:resultMessage \"{}\"", escape_literal(&result.message))?;
+        write!(out, "    ]")?;
+    }
+    writeln!(out, " .")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_shacl{
+    use super::*;
+    use crate::rdf::{reader, Triple, RDF_TYPE};
+
+    #[test]
+    fn results_name_the_maps_of_their_triples(){
+        let shapes = ShapesGraph::from_triples(reader::read_turtle(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+            @prefix ex: <http://example.com/> .
+
+            ex:PersonShape a sh:NodeShape ;
+                sh:targetClass ex:Person ;
+                sh:nodeKind sh:IRI ;
+                sh:property [ sh:path ex:name ; sh:minCount 1 ; sh:maxCount 1 ] ;
+                sh:property [ sh:path ex:age ; sh:datatype xsd:integer ] ;
+                sh:property [ sh:path ex:gender ; sh:in ( "f" "m" ) ; sh:severity sh:Warning ] ;
+                sh:property [ sh:path ex:email ; sh:pattern "@EXAMPLE\\.COM$" ; sh:flags "i" ] .
+        "#, None).unwrap()).unwrap();
+
+        let iri = |name: &str| Term::Iri(format!("http://example.com/{}", name));
+        let literal = |value: &str, datatype: Option<&str>| Term::Literal{value: value.to_string(), datatype: datatype.map(|name| format!("{}{}", XSD, name))};
+        let triple = |s: &str, p: &str, o: Term| Triple{subject: iri(s), predicate: iri(p), object: o, graph: None};
+        let mut graph = DataGraph::new();
+        graph.push(0, &[
+            Triple{subject: iri("ann"), predicate: Term::Iri(RDF_TYPE.to_string()), object: iri("Person"), graph: None},
+            triple("ann", "name", literal("Ann", None)),
+            triple("ann", "age", literal("31", Some("integer"))),
+            triple("ann", "gender", literal("f", None)),
+            triple("ann", "email", literal("ann@example.com", None)),
+            Triple{subject: iri("bob"), predicate: Term::Iri(RDF_TYPE.to_string()), object: iri("Person"), graph: None},
+            triple("bob", "age", literal("nine", Some("integer"))),
+            triple("bob", "gender", literal("x", None))
+        ]);
+        graph.push(1, &[triple("ann", "name", literal("Anna", None))]);
+
+        let results = validate(&shapes, &graph);
+        let found = results.iter().map(|result| (result.focus.clone(), result.component, result.maps.clone())).collect::<Vec<_>>();
+        assert_eq!(found, [
+            (iri("ann"), "MaxCountConstraintComponent", vec![0, 1]),
+            (iri("bob"), "MinCountConstraintComponent", vec![0]),
+            (iri("bob"), "DatatypeConstraintComponent", vec![0]),
+            (iri("bob"), "InConstraintComponent", vec![0])
+        ]);
+        // The warning of sh:in does not fail the validation, but the rest do
+        assert_eq!(results.iter().filter(|result| is_violation(result)).count(), 3);
+
+        let mut report = Vec::new();
+        write_report(&results, &["people".to_string(), "names".to_string()], &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("sh:conforms false"));
+        assert!(report.contains("rossete:map \"names\""));
+        // The report is valid Turtle
+        assert!(reader::read_turtle(&report, None).is_ok());
+    }
+}
@@ -0,0 +1,138 @@
+// Triples of a run kept in memory for the validation, with the maps that have created them. The graphs of the triples
+// are not kept, the shapes validate all of them together.
+use crate::rdf::{Term, Triple, RDF_TYPE};
+
+use std::collections::HashMap;
+
+const RDFS_SUBCLASS_OF: &str = "http://www.w3.org/2000/01/rdf-schema#subClassOf";
+
+#[derive(Default)]
+pub struct DataGraph{
+    terms: Vec<Term>,
+    ids: HashMap<Term, usize>,
+    // Objects of each subject and predicate and subjects of each object and predicate, with the map of the triple
+    forward: HashMap<(usize, usize), Vec<(usize, usize)>>,
+    backward: HashMap<(usize, usize), Vec<(usize, usize)>>,
+    // Maps that have created a triple of each subject
+    subject_maps: HashMap<usize, Vec<usize>>
+}
+
+impl DataGraph{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    pub fn push(&mut self, map: usize, triples: &[Triple]){
+        for triple in triples.iter(){
+            let subject = self.intern(&triple.subject);
+            let predicate = self.intern(&triple.predicate);
+            let object = self.intern(&triple.object);
+            self.forward.entry((subject, predicate)).or_default().push((object, map));
+            self.backward.entry((object, predicate)).or_default().push((subject, map));
+            let maps = self.subject_maps.entry(subject).or_default();
+            if !maps.contains(&map){
+                maps.push(map);
+            }
+        }
+    }
+
+    fn intern(&mut self, term: &Term) -> usize{
+        if let Some(&id) = self.ids.get(term){
+            return id
+        }
+        self.terms.push(term.clone());
+        self.ids.insert(term.clone(), self.terms.len() - 1);
+        self.terms.len() - 1
+    }
+
+    fn id(&self, term: &Term) -> Option<usize>{
+        self.ids.get(term).copied()
+    }
+
+    // Distinct objects of a subject and a predicate, with the maps of their triples.
+    pub fn objects(&self, subject: &Term, predicate: &Term) -> Vec<(&Term, Vec<usize>)>{
+        self.lookup(&self.forward, subject, predicate)
+    }
+
+    // Distinct subjects of an object and a predicate, with the maps of their triples.
+    pub fn subjects(&self, object: &Term, predicate: &Term) -> Vec<(&Term, Vec<usize>)>{
+        self.lookup(&self.backward, object, predicate)
+    }
+
+    fn lookup(&self, index: &HashMap<(usize, usize), Vec<(usize, usize)>>, node: &Term, predicate: &Term) -> Vec<(&Term, Vec<usize>)>{
+        let values = match (self.id(node), self.id(predicate)){
+            (Some(node), Some(predicate)) => index.get(&(node, predicate)),
+            _ => None
+        };
+        // A class can have many instances, so the values already found are looked up by their position.
+        let mut found: Vec<(usize, Vec<usize>)> = Vec::new();
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+        for &(value, map) in values.into_iter().flatten(){
+            match positions.get(&value){
+                Some(&pos) => {
+                    let maps = &mut found[pos].1;
+                    if !maps.contains(&map){
+                        maps.push(map);
+                    }
+                }
+                None => {
+                    positions.insert(value, found.len());
+                    found.push((value, vec![map]));
+                }
+            }
+        }
+        found.into_iter().map(|(value, maps)| (&self.terms[value], maps)).collect()
+    }
+
+    // Maps that have created a triple with the node as subject.
+    pub fn maps_of(&self, node: &Term) -> Vec<usize>{
+        self.id(node).and_then(|node| self.subject_maps.get(&node)).cloned().unwrap_or_default()
+    }
+
+    // Subjects of the triples with the predicate, or their objects.
+    pub fn nodes_with(&self, predicate: &Term, subjects: bool) -> Vec<&Term>{
+        let predicate = match self.id(predicate){
+            Some(predicate) => predicate,
+            None => return Vec::new()
+        };
+        let index = if subjects { &self.forward } else { &self.backward };
+        index.keys().filter(|(_, found)| *found == predicate).map(|(node, _)| &self.terms[*node]).collect()
+    }
+
+    // Instances of the class or of its subclasses.
+    pub fn instances(&self, class: &Term) -> Vec<&Term>{
+        let rdf_type = Term::Iri(RDF_TYPE.to_string());
+        let subclass_of = Term::Iri(RDFS_SUBCLASS_OF.to_string());
+        let mut classes = vec![class];
+        let mut idx = 0;
+        while idx < classes.len(){
+            for (subclass, _) in self.subjects(classes[idx], &subclass_of){
+                if !classes.contains(&subclass){
+                    classes.push(subclass);
+                }
+            }
+            idx += 1;
+        }
+        classes.into_iter().flat_map(|class| self.subjects(class, &rdf_type)).map(|(node, _)| node).collect()
+    }
+
+    // Whether the node is an instance of the class or of one of its subclasses.
+    pub fn is_instance(&self, node: &Term, class: &Term) -> bool{
+        let rdf_type = Term::Iri(RDF_TYPE.to_string());
+        let subclass_of = Term::Iri(RDFS_SUBCLASS_OF.to_string());
+        let mut classes = self.objects(node, &rdf_type).into_iter().map(|(class, _)| class).collect::<Vec<_>>();
+        let mut idx = 0;
+        while idx < classes.len(){
+            if classes[idx] == class{
+                return true
+            }
+            for (superclass, _) in self.objects(classes[idx], &subclass_of){
+                if !classes.contains(&superclass){
+                    classes.push(superclass);
+                }
+            }
+            idx += 1;
+        }
+        false
+    }
+}
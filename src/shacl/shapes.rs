@@ -0,0 +1,287 @@
+// Shapes of a SHACL shapes graph, read from Turtle. Only the parameters of SHACL Core used by the validation are
+// kept, the rest of the constraints are ignored with a warning.
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::rdf::{self, Term, Triple, RDF_TYPE};
+use crate::{error, warning};
+use super::SH;
+
+use regex::{Regex, RegexBuilder};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const RDFS_CLASS: &str = "http://www.w3.org/2000/01/rdf-schema#Class";
+
+// Parameters of SHACL that do not validate anything or that are read with another one.
+const NON_VALIDATING: [&str; 15] = [
+    "targetClass", "targetNode", "targetSubjectsOf", "targetObjectsOf", "path", "property", "severity", "message",
+    "deactivated", "name", "description", "order", "group", "defaultValue", "flags"
+];
+
+const NODE_KINDS: [&str; 6] = ["IRI", "BlankNode", "Literal", "BlankNodeOrIRI", "BlankNodeOrLiteral", "IRIOrLiteral"];
+
+pub enum Target{
+    Class(Term),
+    Node(Term),
+    SubjectsOf(Term),
+    ObjectsOf(Term)
+}
+
+#[derive(Clone)]
+pub enum ShapePath{
+    Predicate(Term),
+    Inverse(Term)
+}
+
+pub enum Constraint{
+    MinCount(usize),
+    MaxCount(usize),
+    Datatype(String),
+    Class(Term),
+    // Local name of the kind in the SHACL namespace, like BlankNodeOrIRI
+    NodeKind(&'static str),
+    Pattern{
+        regex: Regex,
+        pattern: String
+    },
+    In(Vec<Term>)
+}
+
+impl Constraint{
+    // Local name of the constraint component, in the SHACL namespace.
+    pub fn component(&self) -> &'static str{
+        match self{
+            Self::MinCount(_) => "MinCountConstraintComponent",
+            Self::MaxCount(_) => "MaxCountConstraintComponent",
+            Self::Datatype(_) => "DatatypeConstraintComponent",
+            Self::Class(_) => "ClassConstraintComponent",
+            Self::NodeKind(_) => "NodeKindConstraintComponent",
+            Self::Pattern{..} => "PatternConstraintComponent",
+            Self::In(_) => "InConstraintComponent"
+        }
+    }
+}
+
+// A node shape, or a property shape if it has a path.
+pub struct Shape{
+    pub id: Term,
+    pub targets: Vec<Target>,
+    pub path: Option<ShapePath>,
+    pub constraints: Vec<Constraint>,
+    // Indexes of the property shapes of sh:property
+    pub properties: Vec<usize>,
+    pub severity: String,
+    pub message: Option<String>
+}
+
+pub struct ShapesGraph{
+    pub shapes: Vec<Shape>
+}
+
+impl ShapesGraph{
+    pub fn load(path: &Path) -> ResultApp<Self>{
+        if !path.is_file(){
+            error!("The shapes {} were not found", path.display());
+            return Err(ApplicationErrors::FileNotFound)
+        }
+        let text = std::fs::read_to_string(path)?;
+        let base = std::fs::canonicalize(path).ok().map(|path| format!("file://{}", path.display()));
+        Self::from_triples(rdf::reader::read_turtle(&text, base.as_deref())?)
+    }
+
+    pub fn from_triples(triples: Vec<Triple>) -> ResultApp<Self>{
+        let graph = ShapesData::new(&triples);
+        let sh = |name: &str| Term::Iri(format!("{}{}", SH, name));
+
+        // The shapes in the order they are found, so the results always have the same order.
+        let mut ids = Vec::new();
+        for triple in triples.iter(){
+            let is_shape = match &triple.predicate{
+                Term::Iri(predicate) if predicate == RDF_TYPE => triple.object == sh("NodeShape") || triple.object == sh("PropertyShape"),
+                Term::Iri(predicate) => predicate.strip_prefix(SH).is_some_and(|name| name.starts_with("target") || name == "path"),
+                _ => false
+            };
+            if is_shape && !ids.contains(&triple.subject){
+                ids.push(triple.subject.clone());
+            }
+            if triple.predicate == sh("property") && !ids.contains(&triple.object){
+                ids.push(triple.object.clone());
+            }
+        }
+        let deactivated = |id: &Term| graph.values(id, "deactivated").iter()
+            .any(|value| matches!(value, Term::Literal{value, ..} if value == "true"));
+        ids.retain(|id| !deactivated(id));
+        let index = ids.iter().enumerate().map(|(idx, id)| (id.clone(), idx)).collect::<HashMap<_, _>>();
+
+        let shapes = ids.iter().map(|id| graph.shape(id, &index)).collect::<ResultApp<Vec<_>>>()?;
+        Ok(Self{shapes})
+    }
+}
+
+// Values of each subject and predicate of the shapes graph.
+struct ShapesData<'a>{
+    values: HashMap<&'a Term, Vec<(&'a Term, &'a Term)>>
+}
+
+fn invalid(shape: &Term, message: &str) -> ApplicationErrors{
+    error!("The shape {} is not valid: {}", shape.to_ntriples(), message);
+    ApplicationErrors::InvalidShape
+}
+
+impl<'a> ShapesData<'a>{
+    fn new(triples: &'a [Triple]) -> Self{
+        let mut values: HashMap<&Term, Vec<(&Term, &Term)>> = HashMap::new();
+        for triple in triples.iter(){
+            values.entry(&triple.subject).or_default().push((&triple.predicate, &triple.object));
+        }
+        Self{values}
+    }
+
+    fn values(&self, subject: &Term, name: &str) -> Vec<&'a Term>{
+        self.values.get(subject).into_iter().flatten()
+            .filter(|(predicate, _)| matches!(predicate, Term::Iri(iri) if iri.strip_prefix(SH) == Some(name)))
+            .map(|(_, object)| *object)
+            .collect()
+    }
+
+    fn value(&self, subject: &Term, name: &str) -> ResultApp<Option<&'a Term>>{
+        match self.values(subject, name).as_slice(){
+            [] => Ok(None),
+            [value] => Ok(Some(value)),
+            _ => Err(invalid(subject, &format!("it has more than one sh:{}", name)))
+        }
+    }
+
+    fn shape(&self, id: &Term, index: &HashMap<Term, usize>) -> ResultApp<Shape>{
+        let mut targets = Vec::new();
+        for (name, target) in [("targetClass", Target::Class as fn(Term) -> Target), ("targetNode", Target::Node), ("targetSubjectsOf", Target::SubjectsOf), ("targetObjectsOf", Target::ObjectsOf)]{
+            targets.extend(self.values(id, name).into_iter().cloned().map(target));
+        }
+        // A shape that is also a class is a target of its instances
+        let is_class = self.values.get(id).into_iter().flatten()
+            .any(|(predicate, object)| matches!(predicate, Term::Iri(iri) if iri == RDF_TYPE) && matches!(object, Term::Iri(iri) if iri == RDFS_CLASS));
+        if is_class{
+            targets.push(Target::Class(id.clone()));
+        }
+
+        let path = match self.value(id, "path")?{
+            None => None,
+            Some(Term::Iri(iri)) => Some(ShapePath::Predicate(Term::Iri(iri.clone()))),
+            Some(path) => match self.value(path, "inversePath")?{
+                Some(Term::Iri(iri)) => Some(ShapePath::Inverse(Term::Iri(iri.clone()))),
+                _ => return Err(invalid(id, "only the predicates and sh:inversePath of a predicate are supported as sh:path"))
+            }
+        };
+
+        let mut constraints = Vec::new();
+        for name in ["minCount", "maxCount"]{
+            if let Some(value) = self.value(id, name)?{
+                let count = match value{
+                    Term::Literal{value, ..} => value.parse::<usize>().map_err(|_| invalid(id, &format!("sh:{} must be a non negative integer", name)))?,
+                    _ => return Err(invalid(id, &format!("sh:{} must be a non negative integer", name)))
+                };
+                if path.is_none(){
+                    return Err(invalid(id, &format!("sh:{} can only be used by a property shape", name)))
+                }
+                constraints.push(if name == "minCount" { Constraint::MinCount(count) } else { Constraint::MaxCount(count) });
+            }
+        }
+        for datatype in self.values(id, "datatype"){
+            match datatype{
+                Term::Iri(datatype) => constraints.push(Constraint::Datatype(datatype.clone())),
+                _ => return Err(invalid(id, "sh:datatype must be an IRI"))
+            }
+        }
+        for class in self.values(id, "class"){
+            constraints.push(Constraint::Class(class.clone()));
+        }
+        for kind in self.values(id, "nodeKind"){
+            let kind = match kind{
+                Term::Iri(kind) => kind.strip_prefix(SH).and_then(|kind| NODE_KINDS.iter().find(|name| **name == kind)),
+                _ => None
+            };
+            match kind{
+                Some(kind) => constraints.push(Constraint::NodeKind(kind)),
+                None => return Err(invalid(id, "sh:nodeKind must be sh:IRI, sh:BlankNode, sh:Literal, sh:BlankNodeOrIRI, sh:BlankNodeOrLiteral or sh:IRIOrLiteral"))
+            }
+        }
+        if let Some(pattern) = self.value(id, "pattern")?{
+            let pattern = literal_value(pattern).ok_or_else(|| invalid(id, "sh:pattern must be a string"))?;
+            let flags = match self.value(id, "flags")?{
+                Some(flags) => literal_value(flags).ok_or_else(|| invalid(id, "sh:flags must be a string"))?,
+                None => ""
+            };
+            let mut builder = RegexBuilder::new(pattern);
+            for flag in flags.chars(){
+                match flag{
+                    'i' => builder.case_insensitive(true),
+                    's' => builder.dot_matches_new_line(true),
+                    'm' => builder.multi_line(true),
+                    'x' => builder.ignore_whitespace(true),
+                    _ => return Err(invalid(id, &format!("the flag '{}' of sh:flags is not supported", flag)))
+                };
+            }
+            let regex = builder.build().map_err(|_| invalid(id, &format!("the sh:pattern \"{}\" is not a valid regular expression", pattern)))?;
+            constraints.push(Constraint::Pattern{regex, pattern: pattern.to_string()});
+        }
+        for list in self.values(id, "in"){
+            constraints.push(Constraint::In(self.list(id, list)?));
+        }
+
+        let mut properties = Vec::new();
+        for property in self.values(id, "property"){
+            // The deactivated property shapes are not in the index
+            if let Some(&idx) = index.get(property){
+                if self.value(property, "path")?.is_none(){
+                    return Err(invalid(property, "a property shape must have a sh:path"))
+                }
+                properties.push(idx);
+            }
+        }
+
+        let severity = match self.value(id, "severity")?{
+            Some(Term::Iri(severity)) => severity.clone(),
+            Some(_) => return Err(invalid(id, "sh:severity must be an IRI")),
+            None => format!("{}Violation", SH)
+        };
+        let message = self.values(id, "message").into_iter().filter_map(literal_value).next().map(|message| message.to_string());
+
+        for (predicate, _) in self.values.get(id).into_iter().flatten(){
+            if let Term::Iri(iri) = predicate{
+                let known = ["minCount", "maxCount", "datatype", "class", "nodeKind", "pattern", "in"];
+                if let Some(name) = iri.strip_prefix(SH).filter(|name| !NON_VALIDATING.contains(name) && !known.contains(name)){
+                    warning!("The shape {} uses sh:{}, which is not supported, so it is ignored", id.to_ntriples(), name);
+                }
+            }
+        }
+
+        Ok(Shape{id: id.clone(), targets, path, constraints, properties, severity, message})
+    }
+
+    // Items of an RDF list.
+    fn list(&self, shape: &Term, mut node: &'a Term) -> ResultApp<Vec<Term>>{
+        let property = |node: &'a Term, name: &str| self.values.get(node).into_iter().flatten()
+            .find(|(predicate, _)| matches!(predicate, Term::Iri(iri) if iri.strip_prefix(RDF) == Some(name)))
+            .map(|(_, object)| *object);
+        let mut items = Vec::new();
+        while !matches!(node, Term::Iri(iri) if iri.strip_prefix(RDF) == Some("nil")){
+            match (property(node, "first"), property(node, "rest")){
+                (Some(first), Some(rest)) if items.len() < self.values.len() => {
+                    items.push(first.clone());
+                    node = rest;
+                }
+                _ => return Err(invalid(shape, "sh:in must be a list"))
+            }
+        }
+        Ok(items)
+    }
+}
+
+fn literal_value(term: &Term) -> Option<&str>{
+    match term{
+        Term::Literal{value, ..} | Term::LangLiteral{value, ..} => Some(value),
+        _ => None
+    }
+}
@@ -0,0 +1,214 @@
+// SPARQL queries over the store written by a run, or over the mappings from the endpoint of the serve subcommand.
+// The queries are parsed and evaluated by the engine of Oxigraph, which reads the quads of each pattern from the
+// store or, in rewrite, joins the patterns of each basic graph pattern over the staged rows of the maps.
+pub mod rewrite;
+pub mod endpoint;
+
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::rdf::{Term, Triple};
+use crate::rdf::store::TripleStore;
+use crate::rdf::terms::{escape_literal, from_oxrdf, to_oxrdf, triple_from_oxrdf};
+use crate::{error, info};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use spareval::{InternalQuad, QueryEvaluator, QueryResults, QueryableDataset};
+use spargebra::SparqlParser;
+
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+// Runs the query over the store and writes the results to the standard output: a table for SELECT, N-Triples
+// for CONSTRUCT and true or false for ASK. The default graph is the union of all the graphs.
+pub fn run_query(store_path: &Path, query: &str) -> ResultApp<()>{
+    if !store_path.is_file(){
+        error!("The store {} was not found. It is written by a run with --store", store_path.display());
+        return Err(ApplicationErrors::FileNotFound)
+    }
+    let prefixes = query_prefixes(query);
+    let query = SparqlParser::new().parse_query(query)?;
+    let store = TripleStore::open(store_path)?;
+    let evaluator = QueryEvaluator::new();
+    let results = evaluator.prepare(&query).execute(&store)?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match results{
+        QueryResults::Solutions(solutions) => {
+            let variables = solutions.variables().iter().map(|variable| variable.as_str().to_string()).collect::<Vec<_>>();
+            let mut rows = Vec::new();
+            for solution in solutions{
+                let solution = solution?;
+                rows.push(variables.iter().map(|variable| solution.get(variable.as_str()).cloned().map(from_oxrdf)).collect::<Vec<_>>());
+            }
+            write_table(&mut out, &variables, &rows, &prefixes)?;
+            info!("The query has {} results", rows.len());
+        }
+        QueryResults::Graph(triples) => {
+            let mut count = 0;
+            for triple in triples{
+                writeln!(out, "{}", triple_from_oxrdf(triple?).to_ntriples())?;
+                count += 1;
+            }
+            info!("The query has constructed {} triples", count);
+        }
+        QueryResults::Boolean(value) => writeln!(out, "{}", value)?
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// The terms of the engine are the ones of rossete, so the quads of the store are given as they are read.
+impl<'a> QueryableDataset<'a> for &'a TripleStore{
+    type InternalTerm = Term;
+    type Error = ApplicationErrors;
+
+    fn internal_quads_for_pattern(&self, subject: Option<&Term>, predicate: Option<&Term>, object: Option<&Term>, graph_name: Option<Option<&Term>>) -> impl Iterator<Item = Result<InternalQuad<Term>, ApplicationErrors>> + use<'a>{
+        internal_quads(self.quads_for_pattern(subject, predicate, object, graph_name))
+    }
+
+    fn internalize_term(&self, term: oxrdf::Term) -> Result<Term, ApplicationErrors>{
+        Ok(from_oxrdf(term))
+    }
+
+    fn externalize_term(&self, term: Term) -> Result<oxrdf::Term, ApplicationErrors>{
+        Ok(to_oxrdf(&term))
+    }
+}
+
+// Quads of a pattern as the engine reads them. An error is given before any quad.
+pub fn internal_quads(quads: ResultApp<Vec<Triple>>) -> impl Iterator<Item = Result<InternalQuad<Term>, ApplicationErrors>>{
+    let (quads, error) = match quads{
+        Ok(quads) => (quads, None),
+        Err(error) => (Vec::new(), Some(error))
+    };
+    error.map(Err).into_iter().chain(quads.into_iter().map(|quad| Ok(InternalQuad{
+        subject: quad.subject,
+        predicate: quad.predicate,
+        object: quad.object,
+        graph_name: quad.graph
+    })))
+}
+
+// Prefixes declared by the query. The parsed query does not keep them, and they are only used to shorten the IRIs
+// of the table.
+fn query_prefixes(query: &str) -> Vec<(String, String)>{
+    lazy_static!{
+        static ref PREFIX: Regex = Regex::new(r"(?i)PREFIX\s+([^\s:]*):\s*<([^>]*)>").unwrap();
+    }
+    PREFIX.captures_iter(query).map(|captures| (captures[1].to_string(), captures[2].to_string())).collect()
+}
+
+fn write_table<W: Write>(out: &mut W, variables: &[String], rows: &[Vec<Option<Term>>], prefixes: &[(String, String)]) -> ResultApp<()>{
+    let header = variables.iter().map(|variable| format!("?{}", variable)).collect::<Vec<_>>();
+    let cells = rows.iter()
+        .map(|row| row.iter().map(|value| value.as_ref().map(|term| display_term(term, prefixes)).unwrap_or_default()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut widths = header.iter().map(|name| name.chars().count()).collect::<Vec<_>>();
+    for row in cells.iter(){
+        for (width, cell) in widths.iter_mut().zip(row.iter()){
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |out: &mut W, cells: &[String]| -> io::Result<()>{
+        for (cell, width) in cells.iter().zip(widths.iter()){
+            write!(out, "| {}{} ", cell, " ".repeat(width - cell.chars().count()))?;
+        }
+        writeln!(out, "|")
+    };
+    line(out, &header)?;
+    writeln!(out, "{}|", widths.iter().map(|width| format!("|{}", "-".repeat(width + 2))).collect::<String>())?;
+    for row in cells.iter(){
+        line(out, row)?;
+    }
+    Ok(())
+}
+
+// The IRIs are shortened with the prefixes of the query when the rest of the IRI is a simple name.
+fn display_term(term: &Term, prefixes: &[(String, String)]) -> String{
+    let compact = |iri: &str| prefixes.iter()
+        .filter_map(|(prefix, namespace)| iri.strip_prefix(namespace.as_str()).map(|local| (prefix, local)))
+        .find(|(_, local)| !local.is_empty() && local.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'))
+        .map(|(prefix, local)| format!("{}:{}", prefix, local));
+    match term{
+        Term::Iri(iri) => compact(iri).unwrap_or_else(|| term.to_ntriples()),
+        Term::Literal{value, datatype: Some(datatype)} if is_shorthand(value, datatype) => value.clone(),
+        Term::Literal{value, datatype: Some(datatype)} => match compact(datatype){
+            Some(datatype) => format!("\"{}\"^^{}", escape_literal(value), datatype),
+            None => term.to_ntriples()
+        },
+        _ => term.to_ntriples()
+    }
+}
+
+// The numbers and booleans that can be written without datatype, as in Turtle.
+fn is_shorthand(value: &str, datatype: &str) -> bool{
+    match datatype.strip_prefix(XSD){
+        Some("integer") => value.parse::<i128>().is_ok(),
+        Some("decimal") => value.contains('.') && !value.contains(['e', 'E']) && value.parse::<f64>().is_ok(),
+        Some("double") => value.contains(['e', 'E']) && value.parse::<f64>().is_ok(),
+        Some("boolean") => value == "true" || value == "false",
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test_sparql{
+    use super::*;
+
+    #[test]
+    fn queries_are_answered_from_the_store(){
+        let path = std::env::temp_dir().join(format!("rossete-test-sparql-{}.db", std::process::id()));
+        let iri = |name: &str| Term::Iri(format!("http://example.com/{}", name));
+        let literal = |value: &str, datatype: Option<&str>| Term::Literal{value: value.to_string(), datatype: datatype.map(|name| format!("{}{}", XSD, name))};
+        let triple = |s: &str, p: &str, o: Term, g: Option<&str>| Triple{subject: iri(s), predicate: iri(p), object: o, graph: g.map(iri)};
+        let mut store = TripleStore::create(&path).unwrap();
+        store.push(&[
+            triple("ann", "name", literal("Ann", None), None),
+            triple("ann", "age", literal("31", Some("integer")), None),
+            triple("bob", "name", literal("Bob", None), Some("people")),
+            triple("bob", "age", literal("9", Some("integer")), None),
+            triple("bob", "knows", iri("ann"), None),
+            triple("bob", "knows", iri("ann"), Some("people")),
+            triple("eve", "knows", iri("ann"), None)
+        ]).unwrap();
+        store.finish().unwrap();
+        let store = TripleStore::open(&path).unwrap();
+        let run = |query: &str| {
+            let query = SparqlParser::new().parse_query(&format!("PREFIX ex: <http://example.com/> {}", query)).unwrap();
+            match QueryEvaluator::new().prepare(&query).execute(&store).unwrap(){
+                QueryResults::Solutions(solutions) => solutions
+                    .map(|solution| solution.unwrap().values().iter().map(|value| value.clone().map(from_oxrdf)).collect::<Vec<_>>())
+                    .collect::<Vec<_>>(),
+                QueryResults::Boolean(value) => vec![vec![Some(literal(&value.to_string(), Some("boolean")))]],
+                QueryResults::Graph(triples) => triples.map(|triple| {
+                    let triple = triple_from_oxrdf(triple.unwrap());
+                    vec![Some(triple.subject), Some(triple.predicate), Some(triple.object)]
+                }).collect()
+            }
+        };
+
+        // The default graph has the triples of every graph once, and the people without name are kept by OPTIONAL
+        assert_eq!(run("SELECT ?person ?name WHERE { ?person ex:knows ?friend OPTIONAL { ?person ex:name ?name } } ORDER BY DESC(?person)"), [
+            vec![Some(iri("eve")), None], vec![Some(iri("bob")), Some(literal("Bob", None))]
+        ]);
+        assert_eq!(run("SELECT ?name WHERE { ?person ex:name ?name ; ex:age ?age FILTER(?age > 10) }"), [vec![Some(literal("Ann", None))]]);
+        assert_eq!(run("SELECT ?friend (COUNT(?person) AS ?count) WHERE { ?person ex:knows ?friend } GROUP BY ?friend"), [
+            vec![Some(iri("ann")), Some(literal("2", Some("integer")))]
+        ]);
+        assert_eq!(run("SELECT ?g ?s WHERE { GRAPH ?g { ?s ex:name ?name } }"), [vec![Some(iri("people")), Some(iri("bob"))]]);
+        assert_eq!(run("ASK { ex:bob ex:knows ex:ann }"), [vec![Some(literal("true", Some("boolean")))]]);
+        assert_eq!(run("ASK { ex:ann ex:knows ?someone }"), [vec![Some(literal("false", Some("boolean")))]]);
+        assert_eq!(run("CONSTRUCT { ?friend ex:knownBy ?person } WHERE { ?person ex:knows ?friend FILTER(regex(str(?person), 'BOB$', 'i')) }"), [
+            vec![Some(iri("ann")), Some(iri("knownBy")), Some(iri("bob"))]
+        ]);
+        assert_eq!(query_prefixes("PREFIX ex: <http://example.com/>\nprefix : <http://example.org/> SELECT"), [
+            ("ex".to_string(), "http://example.com/".to_string()), ("".to_string(), "http://example.org/".to_string())
+        ]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
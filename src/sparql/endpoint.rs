@@ -0,0 +1,231 @@
+// Local HTTP endpoint of the SPARQL 1.1 Protocol. The query is given in the query parameter of a GET, or in a POST as
+// a form or as the body, and the results are written in the first format of the Accept header that is supported.
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::rdf;
+use crate::{error, info, warning};
+
+use sparesults::{QueryResultsFormat, QueryResultsSerializer};
+use spareval::{QueryEvaluationError, QueryResults, QuerySolutionIter};
+use spargebra::{Query, SparqlParser};
+
+use std::io::Read;
+use std::time::Instant;
+
+// Path where the queries are received
+pub const ENDPOINT_PATH: &str = "/sparql";
+// Largest body of a request that is read
+const MAX_BODY_BYTES: u64 = 1 << 20;
+
+const SPARQL_JSON: &str = "application/sparql-results+json";
+const SPARQL_XML: &str = "application/sparql-results+xml";
+const CSV: &str = "text/csv";
+const TSV: &str = "text/tab-separated-values";
+const NTRIPLES: &str = "application/n-triples";
+const TURTLE: &str = "text/turtle";
+
+type HttpResponse = tiny_http::Response<std::io::Cursor<Vec<u8>>>;
+
+// Answers the requests one after the other until the process is stopped. The queries are answered by evaluate.
+pub fn serve<'a, F>(evaluate: F, address: &str) -> ResultApp<()>
+where F: Fn(&Query) -> Result<QueryResults<'a>, QueryEvaluationError>
+{
+    let server = match tiny_http::Server::http(address){
+        Ok(server) => server,
+        Err(reason) => {
+            error!("The SPARQL endpoint can not listen on {}: {}", address, reason);
+            return Err(ApplicationErrors::CantStartEndpoint)
+        }
+    };
+    info!("The SPARQL endpoint is listening on http://{}{}", address, ENDPOINT_PATH);
+    for mut request in server.incoming_requests(){
+        let response = answer(&evaluate, &mut request);
+        if let Err(reason) = request.respond(response){
+            warning!("The response could not be sent: {}", reason);
+        }
+    }
+    Ok(())
+}
+
+fn answer<'a, F>(evaluate: &F, request: &mut tiny_http::Request) -> HttpResponse
+where F: Fn(&Query) -> Result<QueryResults<'a>, QueryEvaluationError>
+{
+    let query = match request_query(request){
+        Ok(query) => query,
+        Err((status, message)) => return text_response(status, &message)
+    };
+    let accept = header(request, "Accept").unwrap_or_default();
+
+    let now = Instant::now();
+    let query = match SparqlParser::new().parse_query(&query){
+        Ok(query) => query,
+        Err(error) => return text_response(400, &format!("The query is not valid: {}", error))
+    };
+    let results = match evaluate(&query){
+        Ok(results) => results,
+        Err(error) => return text_response(500, &format!("The query could not be answered: {}", error))
+    };
+
+    let written = match results{
+        QueryResults::Graph(triples) => {
+            // N-Triples is also valid Turtle
+            let media_type = negotiate(&accept, &[NTRIPLES, TURTLE]).unwrap_or(NTRIPLES);
+            triples.map(|triple| triple.map(super::triple_from_oxrdf))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|triples| (media_type, rdf::to_ntriples(&triples).into_bytes()))
+                .map_err(|error| error.to_string())
+        }
+        QueryResults::Solutions(solutions) => {
+            let (media_type, format) = match negotiate(&accept, &[SPARQL_JSON, SPARQL_XML, CSV, TSV]){
+                Some(SPARQL_XML) => (SPARQL_XML, QueryResultsFormat::Xml),
+                Some(CSV) => (CSV, QueryResultsFormat::Csv),
+                Some(TSV) => (TSV, QueryResultsFormat::Tsv),
+                _ => (SPARQL_JSON, QueryResultsFormat::Json)
+            };
+            write_solutions(format, solutions).map(|body| (media_type, body))
+        }
+        QueryResults::Boolean(value) => {
+            let (media_type, format) = match negotiate(&accept, &[SPARQL_JSON, SPARQL_XML]){
+                Some(SPARQL_XML) => (SPARQL_XML, QueryResultsFormat::Xml),
+                _ => (SPARQL_JSON, QueryResultsFormat::Json)
+            };
+            QueryResultsSerializer::from_format(format).serialize_boolean_to_writer(Vec::new(), value)
+                .map(|body| (media_type, body))
+                .map_err(|error| error.to_string())
+        }
+    };
+    match written{
+        Ok((media_type, body)) => {
+            info!("Query answered in {} ms", now.elapsed().as_millis());
+            response(200, &format!("{}; charset=utf-8", media_type), body)
+        }
+        Err(error) => text_response(500, &format!("The query could not be answered: {}", error))
+    }
+}
+
+// The solutions are written as they are found.
+fn write_solutions(format: QueryResultsFormat, solutions: QuerySolutionIter) -> Result<Vec<u8>, String>{
+    let variables = solutions.variables().to_vec();
+    let mut serializer = QueryResultsSerializer::from_format(format)
+        .serialize_solutions_to_writer(Vec::new(), variables)
+        .map_err(|error| error.to_string())?;
+    for solution in solutions{
+        let solution = solution.map_err(|error| error.to_string())?;
+        serializer.serialize(&solution).map_err(|error| error.to_string())?;
+    }
+    serializer.finish().map_err(|error| error.to_string())
+}
+
+// Text of the query of a request, or the status and the reason why it can not be answered.
+fn request_query(request: &mut tiny_http::Request) -> Result<String, (u16, String)>{
+    let (path, parameters) = match request.url().split_once('?'){
+        Some((path, parameters)) => (path.to_string(), parameters.to_string()),
+        None => (request.url().to_string(), String::new())
+    };
+    if path != ENDPOINT_PATH{
+        return Err((404, format!("The SPARQL endpoint is in {}", ENDPOINT_PATH)))
+    }
+
+    match request.method(){
+        tiny_http::Method::Get => form_value(&parameters, "query"),
+        tiny_http::Method::Post => {
+            let content_type = header(request, "Content-Type").unwrap_or_default();
+            let content_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+            let mut body = String::new();
+            if let Err(reason) = request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body){
+                return Err((400, format!("The body of the request can not be read: {}", reason)))
+            }
+            match content_type.as_str(){
+                "application/sparql-query" => Ok(body),
+                "application/x-www-form-urlencoded" => form_value(&body, "query"),
+                _ => Err((415, "The query must be sent as application/sparql-query or application/x-www-form-urlencoded".to_string()))
+            }
+        }
+        _ => Err((405, "The queries are sent with GET or POST".to_string()))
+    }
+}
+
+// Value of a parameter of a query string or a form.
+fn form_value(form: &str, name: &str) -> Result<String, (u16, String)>{
+    for pair in form.split('&'){
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key == name{
+            return rdf::percent_decode(&value.replace('+', " "))
+                .ok_or_else(|| (400, format!("The parameter {} is not correctly encoded", name)))
+        }
+    }
+    Err((400, format!("The request does not have the parameter {}", name)))
+}
+
+fn header(request: &tiny_http::Request, name: &str) -> Option<String>{
+    request.headers().iter()
+        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.to_string())
+}
+
+// First of the supported media types accepted by the client, following their quality. */* accepts the first one.
+fn negotiate(accept: &str, supported: &[&'static str]) -> Option<&'static str>{
+    let mut ranges = accept.split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_type, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect::<Vec<_>>();
+    ranges.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    ranges.iter().find_map(|(media_type, _)| match media_type.as_str(){
+        "*/*" => supported.first().copied(),
+        "application/json" => supported.iter().copied().find(|&supported| supported == SPARQL_JSON),
+        "application/xml" => supported.iter().copied().find(|&supported| supported == SPARQL_XML),
+        media_type => supported.iter().copied().find(|&supported| supported == media_type)
+    })
+}
+
+fn text_response(status: u16, message: &str) -> HttpResponse{
+    if status >= 500{
+        error!("{}", message);
+    }
+    response(status, "text/plain; charset=utf-8", format!("{}\n", message).into_bytes())
+}
+
+fn response(status: u16, content_type: &str, body: Vec<u8>) -> HttpResponse{
+    let response = tiny_http::Response::from_data(body).with_status_code(status);
+    match tiny_http::Header::from_bytes("Content-Type", content_type){
+        Ok(header) => response.with_header(header),
+        Err(_) => response
+    }
+}
+
+#[cfg(test)]
+mod test_endpoint{
+    use super::*;
+    use spareval::QueryEvaluator;
+
+    #[test]
+    fn results_follow_the_accept_header(){
+        let solutions = [SPARQL_JSON, SPARQL_XML, CSV, TSV];
+        assert_eq!(negotiate("", &solutions), None);
+        assert_eq!(negotiate("*/*", &solutions), Some(SPARQL_JSON));
+        assert_eq!(negotiate("text/csv;q=0.5, application/sparql-results+xml", &solutions), Some(SPARQL_XML));
+        assert_eq!(negotiate("text/html, text/tab-separated-values;q=0.9, */*;q=0.1", &solutions), Some(TSV));
+        assert_eq!(negotiate("application/json;q=0", &solutions), None);
+        assert_eq!(negotiate("text/turtle", &[NTRIPLES, TURTLE]), Some(TURTLE));
+
+        assert_eq!(form_value("format=json&query=SELECT+*+WHERE+%7B%3Fs+%3Fp+%3Fo%7D", "query").unwrap(), "SELECT * WHERE {?s ?p ?o}");
+        assert_eq!(form_value("format=json", "query").unwrap_err().0, 400);
+
+        let query = SparqlParser::new().parse_query("SELECT ?x ?y WHERE { VALUES ?x { <http://example.com/a> \"b,c\" } }").unwrap();
+        let dataset = oxrdf::Dataset::new();
+        let solutions = match QueryEvaluator::new().prepare(&query).execute(&dataset).unwrap(){
+            QueryResults::Solutions(solutions) => solutions,
+            _ => panic!("SELECT must give solutions")
+        };
+        let csv = write_solutions(QueryResultsFormat::Csv, solutions).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "x,y\r\nhttp://example.com/a,\r\n\"b,c\",\r\n");
+    }
+}
@@ -0,0 +1,794 @@
+// SPARQL over the mappings, without materialising the graph. The basic graph patterns of the queries are sent to the
+// mappings service, where every triple pattern is matched with the triples that the maps can generate and each
+// combination of them is rewritten into a single SQL query over the tables of the staged data files. The engine reads
+// the other patterns, such as property paths, one quad pattern at a time. The rows are turned into terms as in a run,
+// so the solutions are the same as the ones of the generated RDF.
+use crate::ResultApp;
+use crate::errors::ApplicationErrors;
+use crate::config::IriEncoding;
+use crate::input::StagedData;
+use crate::mappings::{maps::Mapping, parts::Parts};
+use crate::mappings::shapes::{self, Kind, Shape};
+use crate::materialiser::{self, JoinKey};
+use crate::rdf::{self, Term};
+use crate::{error, info, warning};
+
+use oxiri::Iri;
+use oxrdf::{NamedNode, Variable};
+use spareval::{InternalQuad, QueryEvaluationError, QueryEvaluator, QueryResults, QuerySolutionIter, QueryableDataset, ServiceHandler};
+use spargebra::Query;
+use spargebra::algebra::GraphPattern;
+use spargebra::term::{NamedNodePattern, TermPattern};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+// Service that answers the basic graph patterns of the queries
+const MAPPINGS_SERVICE: &str = "urn:rossete:mappings";
+// Rows of the first map of a combination that are read by each SQL query
+const WINDOW_ROWS: i64 = 10_000;
+
+// Term of a triple pattern. The variables and the blank nodes of a group of patterns are numbered.
+#[derive(Debug, Clone)]
+enum PatternTerm{
+    Term(Term),
+    Variable(usize)
+}
+
+// How the terms of a position of the triples of a map are generated.
+enum Generator{
+    Constant(Term),
+    // rr:subjectMap
+    Subject(Parts),
+    // Parts of the rr:objectMap
+    Object(Vec<Parts>)
+}
+
+struct TermMap{
+    // Map whose rows generate the term: the map of the triple or, for a referencing object map, its parent map
+    map: usize,
+    generator: Generator,
+    shape: Shape
+}
+
+// A triple that a map generates for each row: subject, predicate and object.
+struct TripleMap{
+    map: usize,
+    terms: [TermMap; 3],
+    // Join conditions with the rows of the parent map, whose subject is the object. Without conditions, the parent
+    // map uses the same rows.
+    join: Option<Vec<JoinKey>>
+}
+
+// Columns of the table of a map that are read to generate its terms.
+struct MapColumns{
+    table: String,
+    columns: Vec<String>,
+    positions: HashMap<String, usize>
+}
+
+// SQL query of a combination of triple maps, one for each pattern of a group.
+struct CombinationQuery{
+    combination: Vec<usize>,
+    sql: String,
+    parameters: Vec<String>,
+    // Map of the rows of each alias, and the aliases of the subject and of the object of each pattern
+    aliases: Vec<usize>,
+    position_alias: Vec<[usize; 2]>,
+    // Largest col_id of the first map
+    last_row: i64
+}
+
+// The connection is shared with the mappings service, which the engine may keep in other threads.
+pub struct MappedGraph{
+    staged: Mutex<StagedData>,
+    mappings: Vec<Mapping>,
+    columns: Vec<MapColumns>,
+    triples: Vec<TripleMap>,
+    encoding: IriEncoding
+}
+
+impl MappedGraph{
+    // Prepares the triples of every map. All the maps must be staged, because their rows are read from the database.
+    pub fn new(staged: StagedData, mappings: Vec<Mapping>, encoding: IriEncoding) -> ResultApp<Self>{
+        let tables = mappings.iter()
+            .map(|map| (map.get_identifier().clone(), map.clone()))
+            .collect::<HashMap<_, _>>();
+        let positions = mappings.iter()
+            .enumerate()
+            .map(|(i, map)| (map.get_identifier().clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut columns = Vec::with_capacity(mappings.len());
+        let mut triples = Vec::new();
+        for (i, map) in mappings.iter().enumerate(){
+            let table = map.get_table_name()?;
+            let main_columns = materialiser::main_columns(map)?;
+            columns.push(MapColumns{positions: materialiser::column_positions(&main_columns), table: table.clone(), columns: main_columns});
+            if !table_exists(&staged.connection, &table)?{
+                warning!("The map {} does not use any field of its data file, so its triples can not be queried", map.get_identifier());
+                continue
+            }
+            materialiser::index_staged_map(&staged.connection, map, &tables)?;
+            triples.extend(map_triples(i, map, &mappings, &positions, &tables)?);
+        }
+        for (i, map) in mappings.iter().enumerate(){
+            if let Some(subject) = triples.iter().find(|triple| triple.map == i).map(|triple| &triple.terms[0].shape){
+                create_subject_index(&staged.connection, &columns[i].table, subject)?;
+            }
+            info!("The map {} can generate {} kinds of triples", map.get_identifier(), triples.iter().filter(|triple| triple.map == i).count());
+        }
+        Ok(Self{staged: Mutex::new(staged), mappings, columns, triples, encoding})
+    }
+
+    // Evaluates a query whose basic graph patterns are answered by the mappings service.
+    pub fn evaluate<'a>(graph: &'a Arc<Self>, query: &Query) -> Result<QueryResults<'a>, QueryEvaluationError>{
+        let evaluator = QueryEvaluator::new()
+            .with_service_handler(NamedNode::new_unchecked(MAPPINGS_SERVICE), MappingsService(Arc::clone(graph)));
+        evaluator.prepare(&mapped_query(query.clone())).execute(graph.as_ref())
+    }
+
+    // Solutions of a group of patterns, with the value of each numbered variable. The rows are read as the solutions
+    // are asked for, so a query with LIMIT only reads the rows that it needs.
+    fn solutions<G: Deref<Target = Self>>(graph: G, patterns: Vec<[PatternTerm; 3]>, variables: usize) -> Solutions<G>{
+        // Triple maps that can match each pattern by themselves
+        let candidates = patterns.iter()
+            .map(|pattern| (0..graph.triples.len())
+                .filter(|&triple| pattern.iter().zip(graph.triples[triple].terms.iter()).all(|(term, term_map)| match term{
+                    PatternTerm::Term(term) => compatible(&term_map.shape, &Shape::Constant(term.clone())),
+                    PatternTerm::Variable(_) => true
+                }))
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut combinations = Vec::new();
+        graph.combinations(&patterns, &candidates, &mut Vec::with_capacity(patterns.len()), &mut combinations);
+        Solutions{
+            graph,
+            patterns,
+            variables,
+            combinations: combinations.into_iter(),
+            query: None,
+            window: 0,
+            found: VecDeque::new(),
+            seen: HashSet::new()
+        }
+    }
+
+    // Adds the combinations of triple maps that can give a solution, discarding the ones whose shared variables can never
+    // have the same value.
+    fn combinations(&self, patterns: &[[PatternTerm; 3]], candidates: &[Vec<usize>], current: &mut Vec<usize>, found: &mut Vec<Vec<usize>>){
+        let k = current.len();
+        if k == patterns.len(){
+            found.push(current.clone());
+            return
+        }
+        for &candidate in candidates[k].iter(){
+            let compatible = patterns[k].iter().enumerate().all(|(position, term)| {
+                let variable = match term{
+                    PatternTerm::Variable(variable) => variable,
+                    PatternTerm::Term(_) => return true
+                };
+                let shape = &self.triples[candidate].terms[position].shape;
+                current.iter().chain(std::iter::once(&candidate)).enumerate()
+                    .flat_map(|(other_k, &other)| patterns[other_k].iter().enumerate().map(move |(other_position, other_term)| (other_k, other, other_position, other_term)))
+                    .filter(|&(other_k, _, other_position, _)| other_k < k || other_position < position)
+                    .all(|(_, other, other_position, other_term)| match other_term{
+                        PatternTerm::Variable(other_variable) if other_variable == variable => compatible(shape, &self.triples[other].terms[other_position].shape),
+                        _ => true
+                    })
+            });
+            if compatible{
+                current.push(candidate);
+                self.combinations(patterns, candidates, current, found);
+                current.pop();
+            }
+        }
+    }
+
+    // Joins the rows of a combination of triple maps in a single query. Each pattern reads the rows of its map and, for
+    // a referencing object map, the joined rows of the parent map.
+    fn combination_query(&self, patterns: &[[PatternTerm; 3]], combination: Vec<usize>) -> ResultApp<CombinationQuery>{
+        let mut aliases = Vec::new();
+        let mut conditions = Vec::new();
+        let mut position_alias = Vec::with_capacity(patterns.len());
+        for (k, &triple) in combination.iter().enumerate(){
+            let triple = &self.triples[triple];
+            let subject_alias = aliases.len();
+            aliases.push((format!("s{}", k), triple.map));
+            let object_alias = match &triple.join{
+                Some(keys) => {
+                    let (child, parent) = (format!("s{}", k), format!("o{}", k));
+                    if keys.is_empty(){
+                        conditions.push(format!("{}.col_id = {}.col_id", parent, child));
+                    }
+                    for key in keys.iter(){
+                        conditions.push(format!(
+                            "{} = {}",
+                            key.normalisation.sql_expression(&format!("{}.\"{}\"", child, key.child)),
+                            key.normalisation.sql_expression(&format!("{}.\"{}\"", parent, key.parent))
+                        ));
+                    }
+                    aliases.push((parent, triple.terms[2].map));
+                    aliases.len() - 1
+                }
+                None => subject_alias
+            };
+            position_alias.push([subject_alias, object_alias]);
+        }
+        let alias_of = |k: usize, position: usize| &aliases[position_alias[k][(position == 2) as usize]].0;
+
+        // The constants of the patterns and the variables that appear several times are compared in SQL when the
+        // columns that generate them are known. The terms are compared again once they are generated.
+        let mut parameters = Vec::new();
+        let mut first_seen: HashMap<usize, (usize, usize)> = HashMap::new();
+        for (k, pattern) in patterns.iter().enumerate(){
+            for (position, term) in pattern.iter().enumerate(){
+                let shape = &self.triples[combination[k]].terms[position].shape;
+                match term{
+                    PatternTerm::Term(term) => {
+                        for (column, value) in constant_columns(shape, term, self.encoding){
+                            parameters.push(value);
+                            conditions.push(format!("{}.\"{}\" = ?{}", alias_of(k, position), column, parameters.len()));
+                        }
+                    }
+                    PatternTerm::Variable(variable) => match first_seen.get(variable){
+                        Some(&(other_k, other_position)) => {
+                            let other_shape = &self.triples[combination[other_k]].terms[other_position].shape;
+                            for (column, other_column) in shared_columns(shape, other_shape){
+                                conditions.push(format!("{}.\"{}\" = {}.\"{}\"", alias_of(k, position), column, alias_of(other_k, other_position), other_column));
+                            }
+                        }
+                        None => {
+                            first_seen.insert(*variable, (k, position));
+                        }
+                    }
+                }
+            }
+        }
+        // The rows of the first map are read in windows of their col_id
+        conditions.push(format!("s0.col_id > ?{} AND s0.col_id <= ?{}", parameters.len() + 1, parameters.len() + 2));
+
+        let mut selected = Vec::new();
+        let mut tables = Vec::with_capacity(aliases.len());
+        for (alias, map) in aliases.iter(){
+            let columns = &self.columns[*map];
+            selected.extend(columns.columns.iter().map(|column| format!("{}.\"{}\"", alias, column)));
+            selected.push(format!("CAST({}.col_id AS TEXT)", alias));
+            tables.push(format!("{} AS {}", columns.table, alias));
+        }
+        let sql = format!("SELECT {} FROM {} WHERE {}", selected.join(", "), tables.join(", "), conditions.join(" AND "));
+        let last_row = self.staged.lock()?.connection.query_row(
+            &format!("SELECT coalesce(max(col_id), 0) FROM {};", self.columns[aliases[0].1].table),
+            [],
+            |row| row.get(0)
+        )?;
+        Ok(CombinationQuery{
+            combination,
+            sql,
+            parameters,
+            aliases: aliases.into_iter().map(|(_, map)| map).collect(),
+            position_alias,
+            last_row
+        })
+    }
+
+    // Solutions of a window of rows of the first map of a combination, which may repeat the ones of other rows.
+    fn window_solutions(&self, patterns: &[[PatternTerm; 3]], variables: usize, query: &CombinationQuery, window: (i64, i64)) -> ResultApp<Vec<Vec<Option<Term>>>>{
+        let staged = self.staged.lock()?;
+        let mut smt = staged.connection.prepare_cached(&query.sql)?;
+        let mut parameters = query.parameters.iter().map(|value| value as &dyn rusqlite::ToSql).collect::<Vec<_>>();
+        parameters.push(&window.0);
+        parameters.push(&window.1);
+        let mut rows = smt.query(parameters.as_slice())?;
+
+        let mut warn = false;
+        let widths = query.aliases.iter().map(|map| self.columns[*map].columns.len() + 1).collect::<Vec<_>>();
+        let mut values: Vec<Vec<String>> = widths.iter().map(|width| Vec::with_capacity(*width)).collect();
+        let mut solutions = Vec::new();
+        'rows: while let Some(row) = rows.next()?{
+            let mut idx = 0;
+            for (alias_values, width) in values.iter_mut().zip(widths.iter()){
+                alias_values.clear();
+                for _ in 0..*width{
+                    alias_values.push(row.get(idx).unwrap_or_default());
+                    idx += 1;
+                }
+            }
+
+            let mut solution: Vec<Option<Term>> = vec![None; variables];
+            for (k, pattern) in patterns.iter().enumerate(){
+                let triple = &self.triples[query.combination[k]];
+                for (position, term) in pattern.iter().enumerate(){
+                    let row = &values[query.position_alias[k][(position == 2) as usize]];
+                    let generated = match self.generate(&triple.terms[position], row, &mut warn)?{
+                        Some(generated) => generated,
+                        None => continue 'rows
+                    };
+                    match term{
+                        PatternTerm::Term(term) if *term != generated => continue 'rows,
+                        PatternTerm::Term(_) => {}
+                        PatternTerm::Variable(variable) => {
+                            let binding = &mut solution[*variable];
+                            match binding{
+                                Some(bound) if *bound != generated => continue 'rows,
+                                Some(_) => {}
+                                None => *binding = Some(generated)
+                            }
+                        }
+                    }
+                }
+            }
+            solutions.push(solution);
+        }
+        Ok(solutions)
+    }
+
+    // Generates the term of a row as it is done in a run.
+    fn generate(&self, term_map: &TermMap, row: &[String], warn: &mut bool) -> ResultApp<Option<Term>>{
+        let map = &self.mappings[term_map.map];
+        let positions = &self.columns[term_map.map].positions;
+        match &term_map.generator{
+            Generator::Constant(term) => Ok(Some(term.clone())),
+            Generator::Subject(subject) => materialiser::generate_subject(subject, map, row, positions, self.encoding, warn),
+            Generator::Object(object_map) => materialiser::term_from_object(map, object_map, row, positions, self.encoding, warn)
+        }
+    }
+}
+
+// Solutions of a group of patterns, read combination after combination and window after window of rows, without
+// repeated solutions.
+struct Solutions<G: Deref<Target = MappedGraph>>{
+    graph: G,
+    patterns: Vec<[PatternTerm; 3]>,
+    variables: usize,
+    combinations: std::vec::IntoIter<Vec<usize>>,
+    query: Option<CombinationQuery>,
+    // Last col_id of the first map that has been read
+    window: i64,
+    found: VecDeque<Vec<Option<Term>>>,
+    seen: HashSet<Vec<Option<Term>>>
+}
+
+impl<G: Deref<Target = MappedGraph>> Solutions<G>{
+    // Reads the next window of rows, or prepares the query of the next combination. False once every combination has
+    // been read.
+    fn advance(&mut self) -> ResultApp<bool>{
+        let query = match &self.query{
+            Some(query) if self.window < query.last_row => query,
+            _ => {
+                let combination = match self.combinations.next(){
+                    Some(combination) => combination,
+                    None => return Ok(false)
+                };
+                self.query = Some(self.graph.combination_query(&self.patterns, combination)?);
+                self.window = 0;
+                return Ok(true)
+            }
+        };
+        let window = (self.window, self.window + WINDOW_ROWS);
+        let solutions = self.graph.window_solutions(&self.patterns, self.variables, query, window)?;
+        self.window = window.1;
+        for solution in solutions{
+            if self.seen.insert(solution.clone()){
+                self.found.push_back(solution);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<G: Deref<Target = MappedGraph>> Iterator for Solutions<G>{
+    type Item = ResultApp<Vec<Option<Term>>>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        loop{
+            if let Some(solution) = self.found.pop_front(){
+                return Some(Ok(solution))
+            }
+            match self.advance(){
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(error) => {
+                    // Nothing else is read after an error
+                    self.combinations = Vec::new().into_iter();
+                    self.query = None;
+                    return Some(Err(error))
+                }
+            }
+        }
+    }
+}
+
+// Answers the basic graph patterns that mapped_query sends to the mappings service.
+struct MappingsService(Arc<MappedGraph>);
+
+impl ServiceHandler for MappingsService{
+    type Error = ApplicationErrors;
+
+    fn handle(&self, pattern: &GraphPattern, _base_iri: Option<&Iri<String>>) -> Result<QuerySolutionIter<'static>, ApplicationErrors>{
+        let patterns = match pattern{
+            GraphPattern::Bgp{patterns} => patterns,
+            _ => {
+                error!("The mappings service only answers basic graph patterns");
+                return Err(ApplicationErrors::InvalidSparqlQuery)
+            }
+        };
+
+        // The blank nodes of the patterns are numbered as the variables, but they are not given in the solutions
+        let mut names = Vec::new();
+        let mut number = |name: String| match names.iter().position(|other| *other == name){
+            Some(i) => i,
+            None => {
+                names.push(name);
+                names.len() - 1
+            }
+        };
+        let mut pattern_term = |term: TermPattern| match term{
+            TermPattern::NamedNode(iri) => PatternTerm::Term(Term::Iri(iri.into_string())),
+            TermPattern::Literal(literal) => PatternTerm::Term(super::from_oxrdf(literal.into())),
+            TermPattern::BlankNode(node) => PatternTerm::Variable(number(format!("_:{}", node.as_str()))),
+            TermPattern::Variable(variable) => PatternTerm::Variable(number(format!("?{}", variable.as_str())))
+        };
+        let patterns = patterns.iter()
+            .map(|pattern| [
+                pattern_term(pattern.subject.clone()),
+                pattern_term(pattern.predicate.clone().into()),
+                pattern_term(pattern.object.clone())
+            ])
+            .collect::<Vec<_>>();
+
+        let selected = names.iter()
+            .enumerate()
+            .filter_map(|(i, name)| name.strip_prefix('?').map(|name| (i, Variable::new_unchecked(name))))
+            .collect::<Vec<_>>();
+        let variables = selected.iter().map(|(_, variable)| variable.clone()).collect::<Arc<[Variable]>>();
+        let positions = selected.into_iter().map(|(i, _)| i).collect::<Vec<_>>();
+        let solutions = MappedGraph::solutions(Arc::clone(&self.0), patterns, names.len());
+        Ok(QuerySolutionIter::from_tuples(variables, solutions.map(move |solution| match solution{
+            Ok(solution) => Ok(positions.iter().map(|&i| solution[i].as_ref().map(super::to_oxrdf)).collect()),
+            Err(error) => Err(QueryEvaluationError::Dataset(Box::new(error)))
+        })))
+    }
+}
+
+// The graphs of the maps are not generated, so all the triples are in the default graph and there are no named graphs.
+// The engine only asks for the patterns that are not sent to the mappings service.
+impl<'a> QueryableDataset<'a> for &'a MappedGraph{
+    type InternalTerm = Term;
+    type Error = ApplicationErrors;
+
+    fn internal_quads_for_pattern(&self, subject: Option<&Term>, predicate: Option<&Term>, object: Option<&Term>, graph_name: Option<Option<&Term>>) -> impl Iterator<Item = Result<InternalQuad<Term>, ApplicationErrors>> + use<'a>{
+        let mut variables = 0;
+        let pattern = [subject, predicate, object].map(|term| match term{
+            Some(term) => PatternTerm::Term(term.clone()),
+            None => {
+                variables += 1;
+                PatternTerm::Variable(variables - 1)
+            }
+        });
+        let solutions = matches!(graph_name, Some(None)).then(|| MappedGraph::solutions(*self, vec![pattern.clone()], variables));
+        solutions.into_iter().flatten().filter_map(move |solution| {
+            let solution = match solution{
+                Ok(solution) => solution,
+                Err(error) => return Some(Err(error))
+            };
+            let mut terms = pattern.iter().map(|term| match term{
+                PatternTerm::Term(term) => Some(term.clone()),
+                PatternTerm::Variable(variable) => solution[*variable].clone()
+            });
+            Some(Ok(InternalQuad{subject: terms.next()??, predicate: terms.next()??, object: terms.next()??, graph_name: None}))
+        })
+    }
+
+    fn internalize_term(&self, term: oxrdf::Term) -> Result<Term, ApplicationErrors>{
+        Ok(super::from_oxrdf(term))
+    }
+
+    fn externalize_term(&self, term: Term) -> Result<oxrdf::Term, ApplicationErrors>{
+        Ok(super::to_oxrdf(&term))
+    }
+}
+
+// Sends the basic graph patterns of the query to the mappings service, so the patterns of each one are joined in SQL.
+// The patterns inside GRAPH are left to the engine, which finds no named graphs.
+pub fn mapped_query(query: Query) -> Query{
+    match query{
+        Query::Select{dataset, pattern, base_iri} => Query::Select{dataset, pattern: mapped_pattern(pattern), base_iri},
+        Query::Construct{template, dataset, pattern, base_iri} => Query::Construct{template, dataset, pattern: mapped_pattern(pattern), base_iri},
+        Query::Describe{dataset, pattern, base_iri} => Query::Describe{dataset, pattern: mapped_pattern(pattern), base_iri},
+        Query::Ask{dataset, pattern, base_iri} => Query::Ask{dataset, pattern: mapped_pattern(pattern), base_iri}
+    }
+}
+
+fn mapped_pattern(pattern: GraphPattern) -> GraphPattern{
+    let mapped = |pattern: Box<GraphPattern>| Box::new(mapped_pattern(*pattern));
+    match pattern{
+        GraphPattern::Bgp{patterns} if !patterns.is_empty() => GraphPattern::Service{
+            name: NamedNodePattern::NamedNode(NamedNode::new_unchecked(MAPPINGS_SERVICE)),
+            inner: Box::new(GraphPattern::Bgp{patterns}),
+            silent: false
+        },
+        GraphPattern::Join{left, right} => GraphPattern::Join{left: mapped(left), right: mapped(right)},
+        GraphPattern::LeftJoin{left, right, expression} => GraphPattern::LeftJoin{left: mapped(left), right: mapped(right), expression},
+        GraphPattern::Filter{expr, inner} => GraphPattern::Filter{expr, inner: mapped(inner)},
+        GraphPattern::Union{left, right} => GraphPattern::Union{left: mapped(left), right: mapped(right)},
+        GraphPattern::Extend{inner, variable, expression} => GraphPattern::Extend{inner: mapped(inner), variable, expression},
+        GraphPattern::Minus{left, right} => GraphPattern::Minus{left: mapped(left), right: mapped(right)},
+        GraphPattern::OrderBy{inner, expression} => GraphPattern::OrderBy{inner: mapped(inner), expression},
+        GraphPattern::Project{inner, variables} => GraphPattern::Project{inner: mapped(inner), variables},
+        GraphPattern::Distinct{inner} => GraphPattern::Distinct{inner: mapped(inner)},
+        GraphPattern::Reduced{inner} => GraphPattern::Reduced{inner: mapped(inner)},
+        GraphPattern::Slice{inner, start, length} => GraphPattern::Slice{inner: mapped(inner), start, length},
+        GraphPattern::Group{inner, variables, aggregates} => GraphPattern::Group{inner: mapped(inner), variables, aggregates},
+        // Property paths, VALUES, GRAPH and SERVICE
+        pattern => pattern
+    }
+}
+
+fn table_exists(db: &rusqlite::Connection, table: &str) -> ResultApp<bool>{
+    let count: i64 = db.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1;",
+        [table.trim_matches('"')],
+        |row| row.get(0)
+    )?;
+    Ok(count > 0)
+}
+
+// The subjects are the most common joins between patterns, so their columns are indexed.
+fn create_subject_index(db: &rusqlite::Connection, table: &str, subject: &Shape) -> ResultApp<()>{
+    let columns = match subject{
+        Shape::Template{columns, ..} => columns.clone(),
+        Shape::Reference{column, ..} => vec![column.clone()],
+        _ => return Ok(())
+    };
+    db.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS \"idx-subject-{}-{}\" ON {} ({});",
+        table.replace('"', ""),
+        columns.join("-"),
+        table,
+        columns.iter().map(|column| format!("\"{}\"", column)).collect::<Vec<_>>().join(", ")
+    ))?;
+    Ok(())
+}
+
+// Triples of a map: the class of the subject and a triple for each predicate object map.
+fn map_triples(i: usize, map: &Mapping, mappings: &[Mapping], positions: &HashMap<String, usize>, tables: &HashMap<String, Mapping>) -> ResultApp<Vec<TripleMap>>{
+    let mut warn = true;
+    let iterator = map.get_iterator()?;
+    let subject_map = map.get_subject();
+    let shape = match shapes::subject_shape(subject_map, map, &iterator, &mut warn){
+        Some(shape) => shape,
+        None => {
+            error!("The subject map of the map {} requieres a rr:template, rml:reference, rr:constant or a rr:termType rr:BlankNode", map.get_identifier());
+            return Err(ApplicationErrors::IncorrectMappingFormat)
+        }
+    };
+    let subject = || TermMap{map: i, generator: Generator::Subject(subject_map.clone()), shape: shape.clone()};
+    let constant = |term: Term| TermMap{map: i, generator: Generator::Constant(term.clone()), shape: Shape::Constant(term)};
+
+    // As in a run, only the first class is used
+    let mut triples = Vec::new();
+    if let Parts::SubjectMap{components} = subject_map{
+        if let Some(class) = components.iter().find_map(|part| if let Parts::Class(class) = part { Some(class) } else { None }){
+            let class = Term::Iri(materialiser::expand_iri(class, map, &mut warn));
+            triples.push(TripleMap{map: i, terms: [subject(), constant(Term::Iri(rdf::RDF_TYPE.to_string())), constant(class)], join: None});
+        }
+    }
+
+    let mut joins = materialiser::plan_joins(map, &map.get_table_name()?, tables)?;
+    for (j, predicate_map) in map.get_predicates().into_iter().enumerate(){
+        let (predicate, object_map) = match predicate_map{
+            Parts::PredicateObjectMap{predicate, object_map} => (Term::Iri(materialiser::expand_iri(predicate, map, &mut warn)), object_map),
+            _ => continue
+        };
+        let (object, join) = match joins.remove(&j){
+            Some(plan) => {
+                let parent = positions[plan.parent.get_identifier()];
+                let parent_map = &mappings[parent];
+                let shape = match shapes::subject_shape(parent_map.get_subject(), parent_map, &parent_map.get_iterator()?, &mut warn){
+                    Some(shape) => shape,
+                    None => continue
+                };
+                (TermMap{map: parent, generator: Generator::Subject(parent_map.get_subject().clone()), shape}, Some(plan.conditions))
+            }
+            None => (TermMap{map: i, generator: Generator::Object(object_map.clone()), shape: shapes::object_shape(object_map, map, &iterator, &mut warn)}, None)
+        };
+        triples.push(TripleMap{map: i, terms: [subject(), constant(predicate), object], join});
+    }
+    Ok(triples)
+}
+
+fn term_value(term: &Term) -> &str{
+    match term{
+        Term::Iri(value) | Term::BlankNode(value) | Term::Literal{value, ..} | Term::LangLiteral{value, ..} => value
+    }
+}
+
+// Whether two term maps can generate the same term.
+fn compatible(left: &Shape, right: &Shape) -> bool{
+    match (left, right){
+        (Shape::Constant(left), Shape::Constant(right)) => left == right,
+        (Shape::Constant(term), Shape::Template{kind, segments, ..}) | (Shape::Template{kind, segments, ..}, Shape::Constant(term)) => {
+            shapes::term_kind(term) == *kind && template_values(segments, term_value(term)).is_some()
+        }
+        (Shape::Constant(term), Shape::Reference{kind, ..}) | (Shape::Reference{kind, ..}, Shape::Constant(term)) => shapes::term_kind(term) == *kind,
+        (Shape::Constant(term), Shape::Row) | (Shape::Row, Shape::Constant(term)) => matches!(term, Term::BlankNode(_)),
+        (Shape::Template{kind: left_kind, segments: left, ..}, Shape::Template{kind: right_kind, segments: right, ..}) => {
+            // The text before the first field and after the last one must agree
+            let (left_first, right_first) = (&left[0], &right[0]);
+            let (left_last, right_last) = (&left[left.len() - 1], &right[right.len() - 1]);
+            left_kind == right_kind &&
+                (left_first.starts_with(right_first.as_str()) || right_first.starts_with(left_first.as_str())) &&
+                (left_last.ends_with(right_last.as_str()) || right_last.ends_with(left_last.as_str()))
+        }
+        (Shape::Template{kind: left, ..} | Shape::Reference{kind: left, ..}, Shape::Template{kind: right, ..} | Shape::Reference{kind: right, ..}) => left == right,
+        (Shape::Row, Shape::Row) => true,
+        (Shape::Row, Shape::Template{kind, ..} | Shape::Reference{kind, ..}) | (Shape::Template{kind, ..} | Shape::Reference{kind, ..}, Shape::Row) => *kind == Kind::BlankNode
+    }
+}
+
+// Values of the fields of a template that give the value. None if the value can not be generated by the template,
+// and no values if there are several ways of splitting it.
+fn template_values(segments: &[String], value: &str) -> Option<Vec<String>>{
+    let pattern = |field: &str| {
+        let fields = vec![field; segments.len() - 1];
+        let mut pattern = String::from("^");
+        for (segment, field) in segments.iter().zip(fields.iter().map(Some).chain(std::iter::once(None))){
+            pattern.push_str(&regex::escape(segment));
+            if let Some(field) = field{
+                pattern.push_str(field);
+            }
+        }
+        pattern.push('$');
+        regex::Regex::new(&pattern).ok()
+    };
+    let values = |regex: regex::Regex| regex.captures(value)
+        .map(|captures| captures.iter().skip(1).flatten().map(|capture| capture.as_str().to_string()).collect::<Vec<_>>());
+    let lazy = values(pattern("(.+?)")?)?;
+    let greedy = values(pattern("(.+)")?)?;
+    if lazy == greedy { Some(lazy) } else { Some(Vec::new()) }
+}
+
+// Columns of a term map and the values that they must have to generate a constant.
+fn constant_columns(shape: &Shape, term: &Term, encoding: IriEncoding) -> Vec<(String, String)>{
+    match shape{
+        Shape::Reference{column, ..} => vec![(column.clone(), term_value(term).to_string())],
+        Shape::Template{kind, segments, columns} => {
+            let values = template_values(segments, term_value(term)).unwrap_or_default();
+            if values.len() != columns.len(){
+                return Vec::new()
+            }
+            let values = match (kind, encoding){
+                // Only the values of IRIs are percent-encoded
+                (Kind::Iri, IriEncoding::Encode) => match values.iter().map(|value| rdf::percent_decode(value)).collect::<Option<Vec<_>>>(){
+                    Some(decoded) => decoded,
+                    None => return Vec::new()
+                },
+                _ => values
+            };
+            columns.iter().cloned().zip(values).collect()
+        }
+        _ => Vec::new()
+    }
+}
+
+// Columns that must be equal so two term maps generate the same term.
+fn shared_columns(left: &Shape, right: &Shape) -> Vec<(String, String)>{
+    match (left, right){
+        (Shape::Template{kind: left_kind, segments: left, columns: left_columns}, Shape::Template{kind: right_kind, segments: right, columns: right_columns}) if left_kind == right_kind && left == right => {
+            left_columns.iter().cloned().zip(right_columns.iter().cloned()).collect()
+        }
+        (Shape::Reference{kind: left_kind, column: left}, Shape::Reference{kind: right_kind, column: right}) if left_kind == right_kind => vec![(left.clone(), right.clone())],
+        _ => Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod test_rewrite{
+    use super::*;
+    use crate::config::AppConfiguration;
+    use crate::mappings::{AcceptedType, KeyNormalisation};
+
+    fn csv_map(name: &str, source: &std::path::Path, subject: &str, field: &str, predicate: &str, object_map: Vec<Parts>) -> Mapping{
+        let mut map = Mapping::new(name.to_string());
+        map.add_component(Parts::LogicalSource{source: source.to_path_buf(), reference_formulation: AcceptedType::CSV, iterator: String::new()});
+        map.add_component(Parts::SubjectMap{components: vec![Parts::Template{template: subject.to_string(), input_fields: vec![field.to_string()]}]});
+        map.add_component(Parts::PredicateObjectMap{predicate: predicate.to_string(), object_map});
+        map.change_prefixes(Arc::new(vec![("ex:".to_string(), "http://example.com/".to_string())].into_iter().collect()));
+        map
+    }
+
+    // Airports joined with the countries of their cities, staged as in the serve subcommand.
+    fn mapped_airports() -> Arc<MappedGraph>{
+        let dir = std::env::temp_dir().join(format!("rossete-test-rewrite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (airports, cities) = (dir.join("airports.csv"), dir.join("cities.csv"));
+        std::fs::write(&airports, "code,city\nMAD,Madrid\nBCN,Barcelona\nMAD,Madrid\nCDG,Paris\n").unwrap();
+        std::fs::write(&cities, "name,country\nMadrid,ES\nBarcelona,ES\nParis,FR\n").unwrap();
+
+        let mappings = vec![
+            csv_map("Airport", &airports, "http://example.com/airport/{}", "code", "ex:city", vec![
+                Parts::ParentMap("City".to_string()),
+                Parts::JoinCondition("city".to_string(), "name".to_string(), KeyNormalisation::default())
+            ]),
+            csv_map("City", &cities, "http://example.com/city/{}", "name", "ex:country", vec![
+                Parts::Reference("country".to_string())
+            ])
+        ];
+        let mut config = AppConfiguration::new(Vec::new());
+        crate::add_all_data_files(&mappings, &[], &mut config).unwrap();
+        let mut fields = HashMap::new();
+        crate::add_all_data_fields(&mappings, &mut fields).unwrap();
+        crate::add_all_join_fields(&mappings, &mut fields).unwrap();
+        let (staged, _) = crate::input::read_store_data_files(&mut config, fields).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        Arc::new(MappedGraph::new(staged, mappings, IriEncoding::Encode).unwrap())
+    }
+
+    #[test]
+    fn basic_graph_patterns_are_joined_in_sql(){
+        let graph = mapped_airports();
+        let solutions = |query: &str| {
+            let query = spargebra::SparqlParser::new().parse_query(query).unwrap();
+            let solutions = match MappedGraph::evaluate(&graph, &query).unwrap(){
+                QueryResults::Solutions(solutions) => solutions,
+                _ => panic!("SELECT must give solutions")
+            };
+            let variables = solutions.variables().to_vec();
+            let mut rows = solutions
+                .map(|solution| {
+                    let solution = solution.unwrap();
+                    variables.iter().map(|variable| solution.get(variable).map(|term| term.to_string()).unwrap_or_default()).collect::<Vec<_>>().join(" ")
+                })
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+
+        // The repeated row of MAD gives a single solution
+        assert_eq!(solutions("PREFIX ex: <http://example.com/> SELECT ?airport ?country WHERE { ?airport ex:city ?city . ?city ex:country ?country }"), [
+            "<http://example.com/airport/BCN> \"ES\"",
+            "<http://example.com/airport/CDG> \"FR\"",
+            "<http://example.com/airport/MAD> \"ES\""
+        ]);
+        assert_eq!(solutions("PREFIX ex: <http://example.com/> SELECT ?airport WHERE { ?airport ex:city [ ex:country \"ES\" ] } ORDER BY ?airport LIMIT 1"), [
+            "<http://example.com/airport/BCN>"
+        ]);
+        assert_eq!(solutions("PREFIX ex: <http://example.com/> SELECT (COUNT(*) AS ?n) WHERE { ?city ex:country ?country FILTER(?country != \"FR\") }"), [
+            "\"2\"^^<http://www.w3.org/2001/XMLSchema#integer>"
+        ]);
+        // Property paths are read one pattern at a time
+        assert_eq!(solutions("PREFIX ex: <http://example.com/> SELECT ?city WHERE { <http://example.com/airport/CDG> ex:city+ ?city }"), [
+            "<http://example.com/city/Paris>"
+        ]);
+    }
+
+    fn template(template: &str, fields: &[&str]) -> Shape{
+        shapes::template_shape(Kind::Iri, template, &fields.iter().map(|field| field.to_string()).collect::<Vec<_>>(), "")
+    }
+
+    #[test]
+    fn constants_are_matched_with_the_templates(){
+        let airport = template("http://example.com/airport/{}", &["code"]);
+        let route = template("http://example.com/route/{}-{}", &["from", "to"]);
+        let iri = |value: &str| Term::Iri(value.to_string());
+
+        assert!(compatible(&airport, &Shape::Constant(iri("http://example.com/airport/MAD"))));
+        assert!(!compatible(&airport, &Shape::Constant(iri("http://example.com/route/MAD-BCN"))));
+        assert!(!compatible(&airport, &Shape::Constant(Term::Literal{value: "http://example.com/airport/MAD".to_string(), datatype: None})));
+        assert!(!compatible(&airport, &route));
+        assert!(compatible(&airport, &template("http://example.com/{}", &["path"])));
+
+        let encoded = Term::Iri(format!("http://example.com/airport/{}", rdf::iri_safe("San José")));
+        assert_eq!(constant_columns(&airport, &encoded, IriEncoding::Encode), [("code".to_string(), "San José".to_string())]);
+        assert_eq!(constant_columns(&route, &iri("http://example.com/route/MAD-BCN"), IriEncoding::Encode), [
+            ("from".to_string(), "MAD".to_string()), ("to".to_string(), "BCN".to_string())
+        ]);
+        // The value can be split in two ways, so it is only compared once it is generated
+        assert!(constant_columns(&route, &iri("http://example.com/route/A-B-C"), IriEncoding::Encode).is_empty());
+        assert_eq!(shared_columns(&route, &route.clone()), [
+            ("from".to_string(), "from".to_string()), ("to".to_string(), "to".to_string())
+        ]);
+    }
+}
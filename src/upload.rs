@@ -0,0 +1,184 @@
+use crate::ResultApp;
+use crate::config::OutputFormat;
+use crate::errors::ApplicationErrors;
+use crate::{error, warning, info};
+
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// Attempts of each upload before it is given up
+const UPLOAD_ATTEMPTS: usize = 4;
+// Wait before the first retry. It is doubled after every failed attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// What the upload does with the triples that were already in the graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphMode{
+    // HTTP PUT: the graph only keeps the uploaded triples
+    Replace,
+    // HTTP POST: the uploaded triples are added to the graph
+    Merge
+}
+
+// Endpoint of the SPARQL 1.1 Graph Store HTTP Protocol that receives an output, like the /data service of Fuseki.
+#[derive(Debug, Clone)]
+pub struct GraphStore{
+    url: String,
+    // Named graph. Without it, the default graph is used
+    graph: Option<String>,
+    mode: GraphMode,
+    // Name of each header and the environment variable with its value, so the credentials are not in the command line
+    headers: Vec<(String, String)>,
+    attempts: usize,
+    retry_delay: Duration
+}
+
+impl GraphStore{
+    pub fn new(url: &str) -> Self{
+        Self{url: url.to_string(), graph: None, mode: GraphMode::Replace, headers: Vec::new(), attempts: UPLOAD_ATTEMPTS, retry_delay: RETRY_DELAY}
+    }
+
+    pub fn is_url(output: &str) -> bool{
+        let output = output.to_lowercase();
+        output.starts_with("http://") || output.starts_with("https://")
+    }
+
+    pub fn with_options(self, graph: Option<String>, mode: GraphMode, headers: Vec<(String, String)>) -> Self{
+        Self{graph, mode, headers, ..self}
+    }
+
+    pub fn name(&self) -> String{
+        match (&self.graph, self.url.contains('?')){
+            (_, true) => self.url.clone(),
+            (Some(graph), false) => format!("{} (graph <{}>)", self.url, graph),
+            (None, false) => format!("{} (default graph)", self.url)
+        }
+    }
+
+    // The variables are checked before the run, so a missing credential does not waste it.
+    pub fn check_headers(&self) -> ResultApp<()>{
+        self.header_values().map(|_| ())
+    }
+
+    fn header_values(&self) -> ResultApp<Vec<(&str, String)>>{
+        self.headers.iter()
+            .map(|(name, variable)| match std::env::var(variable){
+                Ok(value) => Ok((name.as_str(), value)),
+                Err(_) => {
+                    error!("The environment variable {} with the value of the header {} for {} is not set", variable, name, self.name());
+                    Err(ApplicationErrors::MissingEnvironmentVariable)
+                }
+            })
+            .collect()
+    }
+
+    // The graph is given in the query of the URL, unless the URL already has one.
+    fn request(&self, agent: &ureq::Agent) -> ureq::Request{
+        let method = match self.mode{
+            GraphMode::Replace => "PUT",
+            GraphMode::Merge => "POST"
+        };
+        match (&self.graph, self.url.contains('?')){
+            (_, true) => agent.request(method, &self.url),
+            (Some(graph), false) => agent.request(method, &self.url).query("graph", graph),
+            (None, false) => agent.request(method, &format!("{}?default", self.url))
+        }
+    }
+
+    // Sends the written output. The body is read from the file while it is sent, with chunked transfer encoding, so
+    // the file is read again when the upload is retried. The server errors and the failed connections are retried.
+    pub fn upload(&self, path: &Path, format: &OutputFormat) -> ResultApp<()>{
+        let content_type = match format.media_type(){
+            Some(content_type) => content_type,
+            None => {
+                error!("The format {:?} can not be uploaded to {}", format, self.name());
+                return Err(ApplicationErrors::IncorrectFieldType)
+            }
+        };
+        let agent = ureq::AgentBuilder::new().timeout_connect(CONNECT_TIMEOUT).build();
+        let mut delay = self.retry_delay;
+        for attempt in 1..=self.attempts{
+            let mut request = self.request(&agent).set("Content-Type", content_type);
+            for (name, value) in self.header_values()?{
+                request = request.set(name, &value);
+            }
+            let body = BufReader::new(fs::File::open(path)?);
+            let reason = match request.send(body){
+                Ok(response) => {
+                    info!("The output was uploaded to {} (HTTP {})", self.name(), response.status());
+                    return Ok(())
+                }
+                Err(ureq::Error::Status(status, response)) if is_transient(status) => format!("HTTP {} {}", status, response.status_text()),
+                Err(ureq::Error::Status(status, response)) => {
+                    let status_text = response.status_text().to_string();
+                    let message = response.into_string().unwrap_or_default();
+                    error!("The upload to {} was rejected with HTTP {} {}: {}", self.name(), status, status_text, message.trim());
+                    return Err(ApplicationErrors::FailedToUploadRDF)
+                }
+                Err(ureq::Error::Transport(transport)) => transport.to_string()
+            };
+            if attempt < self.attempts{
+                warning!("The upload to {} failed ({}), attempt {} of {}. It is retried in {:?}", self.name(), reason, attempt, self.attempts, delay);
+                thread::sleep(delay);
+                delay *= 2;
+            }else{
+                error!("The upload to {} failed ({}) after {} attempts", self.name(), reason, self.attempts);
+            }
+        }
+        Err(ApplicationErrors::FailedToUploadRDF)
+    }
+}
+
+// The store may be busy or restarting, so the upload can succeed later.
+fn is_transient(status: u16) -> bool{
+    status >= 500 || status == 408 || status == 429
+}
+
+#[cfg(test)]
+mod test_upload{
+    use super::*;
+
+    #[test]
+    fn failed_uploads_are_retried(){
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ds/data", server.server_addr());
+        let stub = thread::spawn(move || {
+            // The first attempt finds the store busy
+            let request = server.recv().unwrap();
+            request.respond(tiny_http::Response::empty(503)).unwrap();
+
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let header = |name: &str| request.headers().iter()
+                .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+                .map(|header| header.value.to_string());
+            let received = (request.method().to_string(), request.url().to_string(), header("Transfer-Encoding"), header("Content-Type"), header("Authorization"), body);
+            request.respond(tiny_http::Response::empty(201)).unwrap();
+            received
+        });
+
+        let path = std::env::temp_dir().join(format!("rossete-test-upload-{}.nt", std::process::id()));
+        let content = "<http://example.com/a> <http://example.com/b> \"c\" .\n".repeat(1000);
+        fs::write(&path, &content).unwrap();
+        std::env::set_var("ROSSETE_TEST_STORE_AUTH", "Bearer secret");
+
+        let headers = vec![("Authorization".to_string(), "ROSSETE_TEST_STORE_AUTH".to_string())];
+        let store = GraphStore{retry_delay: Duration::from_millis(10), ..GraphStore::new(&url).with_options(Some("http://example.com/g#1".to_string()), GraphMode::Replace, headers)};
+        let result = store.upload(&path, &OutputFormat::NTriplesMap);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        let (method, url, transfer_encoding, content_type, authorization, body) = stub.join().unwrap();
+        assert_eq!(method, "PUT");
+        assert_eq!(url, "/ds/data?graph=http%3A%2F%2Fexample.com%2Fg%231");
+        assert_eq!(transfer_encoding.as_deref(), Some("chunked"));
+        assert_eq!(content_type.as_deref(), Some("application/n-triples"));
+        assert_eq!(authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(body, content);
+    }
+}